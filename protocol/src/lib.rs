@@ -8,6 +8,19 @@ pub type ChannelName = String;
 
 pub type Key = String;
 
+pub type Id = u32;
+
+pub type ChannelId = Id;
+
+pub type MessageId = Id;
+
+pub type TokenId = Id;
+
+pub type Emoji = String;
+
+/// Seconds since the Unix epoch.
+pub type Timestamp = i64;
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Credentials {
     pub username: Username,
@@ -18,13 +31,108 @@ pub struct Credentials {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum ClientToServer {
     CreateSession(Credentials),
+    /// A one-time password or a recovery code
+    /// requested by `LoginUpdate::SecondFactorRequired`.
+    SecondFactor(String),
+    /// A session key or an API token.
     RestoreSession(Key),
+    /// Creates an account with an invite token and logs in.
+    Register {
+        token: Key,
+        credentials: Credentials,
+    },
+    /// Closes the current session on all devices that use it.
+    Logout,
     CreateChannel(ChannelName),
+    PostMessage {
+        channel: ChannelId,
+        text: String,
+    },
+    Subscribe(ChannelId),
+    Unsubscribe(ChannelId),
+    /// Requests a page of messages older than `before`
+    /// (the latest messages if `before` is `None`).
+    FetchHistory {
+        channel: ChannelId,
+        before: Option<MessageId>,
+        limit: u32,
+    },
+    /// Moves the read marker of the channel to the message.
+    MarkRead {
+        channel: ChannelId,
+        message: MessageId,
+    },
+    JoinChannel(ChannelId),
+    LeaveChannel(ChannelId),
+    InviteMember {
+        channel: ChannelId,
+        username: Username,
+    },
+    KickMember {
+        channel: ChannelId,
+        username: Username,
+    },
+    FetchMembers(ChannelId),
+    /// Requests channels and direct conversations of the user.
+    FetchChannels,
+    /// Opens (or creates if not exists) a direct conversation
+    /// between the user and other users.
+    OpenDirect {
+        usernames: Vec<Username>,
+    },
+    EditMessage {
+        message: MessageId,
+        text: String,
+    },
+    DeleteMessage(MessageId),
+    FetchRevisions(MessageId),
+    PostReply {
+        parent: MessageId,
+        text: String,
+    },
+    /// Requests a parent message with a page of replies older than `before`.
+    FetchThread {
+        message: MessageId,
+        before: Option<MessageId>,
+        limit: u32,
+    },
+    FollowThread(MessageId),
+    UnfollowThread(MessageId),
+    AddReaction {
+        message: MessageId,
+        emoji: Emoji,
+    },
+    RemoveReaction {
+        message: MessageId,
+        emoji: Emoji,
+    },
+    /// Replaces all fields of the user's profile.
+    UpdateProfile(ProfileInfo),
+    FetchProfile(Username),
+    /// Generates a new secret for an authenticator app.
+    EnrollTotp {
+        password: Password,
+    },
+    /// Enables the second factor with a code generated by the app.
+    ConfirmTotp {
+        code: String,
+        password: Password,
+    },
+    /// Disables the second factor with a code or a recovery code.
+    DisableTotp(String),
+    /// Creates an API token that expires after `lifetime` seconds.
+    CreateToken {
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    },
+    FetchTokens,
+    RevokeToken(TokenId),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum ServerToClient {
-    Delta(Delta),
+    Delta(Box<Delta>),
     Reaction(Reaction),
 }
 
@@ -33,6 +141,13 @@ pub enum ServerToClient {
 pub enum Delta {
     LoginUpdate(LoginUpdate),
     ChannelUpdate(ChannelUpdate),
+    MessageUpdate(MessageUpdate),
+    MemberUpdate(MemberUpdate),
+    ThreadUpdate(ThreadUpdate),
+    MentionUpdate(MentionUpdate),
+    ProfileUpdate(ProfileUpdate),
+    SecurityUpdate(SecurityUpdate),
+    TokenUpdate(TokenUpdate),
 }
 
 /// `Reaction`
@@ -42,6 +157,10 @@ pub enum Reaction {
     Fail {
         reason: String,
     },
+    /// The user or the token is not allowed to make the request.
+    PermissionDenied {
+        reason: String,
+    },
 }
 
 impl Reaction {
@@ -58,6 +177,68 @@ pub enum LoginUpdate {
         key: Key,
     },
     LoginFail,
+    /// Credentials are valid, but a code of the second factor is required.
+    SecondFactorRequired,
+    SecondFactorFail,
+    RegisterFail {
+        reason: String,
+    },
+    /// Too many failed attempts, the next one is possible
+    /// after `retry_after` seconds.
+    LoginLocked {
+        retry_after: u64,
+    },
+    /// The session was closed by the user or revoked.
+    LoggedOut,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum SecurityUpdate {
+    /// A secret that has to be confirmed with a code.
+    TotpEnrollment {
+        secret: String,
+        /// `otpauth://` URI for a QR code.
+        uri: String,
+    },
+    /// Recovery codes are sent only once.
+    TotpEnabled {
+        recovery_codes: Vec<String>,
+    },
+    TotpDisabled,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum TokenUpdate {
+    TokensList {
+        tokens: Vec<TokenInfo>,
+    },
+    /// The key is sent only once.
+    TokenCreated {
+        token: TokenInfo,
+        key: Key,
+    },
+    TokenRevoked {
+        id: TokenId,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TokenInfo {
+    pub id: TokenId,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: Timestamp,
+    pub last_used: Option<Timestamp>,
+    pub expires_at: Option<Timestamp>,
+}
+
+/// Requests allowed with an API token.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Fetching and subscribing only.
+    Read,
+    /// Everything except managing the account.
+    Write,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -65,9 +246,160 @@ pub enum ChannelUpdate {
     ChannelsList {
         channels: Vec<ChannelInfo>,
     },
+    ChannelCreated {
+        channel: ChannelInfo,
+    },
+    ChannelDeleted {
+        channel: ChannelId,
+    },
+    DirectOpened {
+        channel: ChannelInfo,
+    },
+    /// Sent to all connections of a user when a read marker moved.
+    ReadUpdated {
+        channel: ChannelId,
+        last_read: Option<MessageId>,
+        unread: u32,
+        mentions: u32,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MemberUpdate {
+    MembersList {
+        channel: ChannelId,
+        members: Vec<Username>,
+    },
+    MemberAdded {
+        channel: ChannelId,
+        username: Username,
+    },
+    MemberRemoved {
+        channel: ChannelId,
+        username: Username,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ChannelInfo {
+    pub id: ChannelId,
     pub title: String,
+    pub kind: ChannelKind,
+    /// Unread messages of a member, zero for other channels.
+    pub unread: u32,
+    /// Unread mentions of a member, zero for other channels.
+    pub mentions: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    Channel,
+    Direct,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MessageUpdate {
+    MessageAdded {
+        message: MessageInfo,
+    },
+    /// A page of messages ordered from the oldest to the newest.
+    /// `next` is a cursor for the previous page if it exists.
+    History {
+        channel: ChannelId,
+        messages: Vec<MessageInfo>,
+        next: Option<MessageId>,
+    },
+    MessageEdited {
+        message: MessageInfo,
+    },
+    MessageDeleted {
+        channel: ChannelId,
+        message: MessageId,
+    },
+    /// Previous versions of a message ordered from the oldest.
+    Revisions {
+        message: MessageId,
+        revisions: Vec<RevisionInfo>,
+    },
+    ReactionsChanged {
+        channel: ChannelId,
+        message: MessageId,
+        reactions: Vec<EmojiCount>,
+    },
+}
+
+/// Updates of threads delivered to followers only.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ThreadUpdate {
+    /// A page of replies ordered from the oldest to the newest.
+    Thread {
+        parent: MessageInfo,
+        replies: Vec<MessageInfo>,
+        next: Option<MessageId>,
+    },
+    ReplyAdded {
+        parent: MessageInfo,
+        reply: MessageInfo,
+    },
+}
+
+/// Updates delivered to mentioned users regardless of subscriptions.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MentionUpdate {
+    Mentioned {
+        message: MessageInfo,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ProfileUpdate {
+    Profile {
+        username: Username,
+        profile: ProfileInfo,
+    },
+    /// Sent to the user and to users that share channels with it.
+    ProfileChanged {
+        username: Username,
+        profile: ProfileInfo,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileInfo {
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+    /// IANA name of a time zone like `Europe/Berlin`.
+    pub timezone: Option<String>,
+    pub status: Option<String>,
+    /// URL of an avatar image.
+    pub avatar: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageInfo {
+    pub id: MessageId,
+    pub channel: ChannelId,
+    pub author: Username,
+    pub text: String,
+    pub created_at: Timestamp,
+    pub edited_at: Option<Timestamp>,
+    /// Parent message if it's a reply in a thread.
+    pub parent: Option<MessageId>,
+    pub reply_count: u32,
+    pub last_reply_at: Option<Timestamp>,
+    pub reactions: Vec<EmojiCount>,
+}
+
+/// Amount of users that added an emoji to a message.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EmojiCount {
+    pub emoji: Emoji,
+    pub count: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RevisionInfo {
+    pub editor: Username,
+    pub text: String,
+    pub created_at: Timestamp,
 }
@@ -1,24 +1,53 @@
-use crate::actors::Engine;
-use crate::control::{ClientToController, ControllerProtocol, ControllerToClient};
-use crate::network::{wrap, NetworkConnection};
-use anyhow::Error;
+use super::lockouts::Target;
+use super::permissions::Initiator;
+use crate::actors::{Engine, EngineError};
+use crate::control::{
+    socket_path, token_path, ApiTokenInfo, AuditInfo, ClientToController, ControllerProtocol,
+    ControllerToClient, LockoutInfo, SessionInfo, UserInfo,
+};
+use crate::db::types::{
+    AuditAction, ChannelId, ChannelName, MemberRole, Timestamp, TokenScope, UserId, UserRole,
+    Username,
+};
+use crate::db::{ApiToken, AuditFilter, User};
+use crate::generators::generate_key;
+use crate::network::{wrap, NetworkConnection, Transport};
+use anyhow::{anyhow, Error};
 use async_trait::async_trait;
+use constant_time_eq::constant_time_eq;
 use futures::{SinkExt, StreamExt};
 use meio::{wrapper, Actor, Context};
-use std::net::SocketAddr;
-use tokio::net::{TcpListener, TcpStream};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::block_in_place as wait;
+use tokio::time::timeout;
+
+/// Time to send the admin token after a ctrl connection was opened.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 wrapper!(CtrlServer for CtrlServerActor);
 
 impl CtrlServer {
-    pub fn start(addr: SocketAddr, engine: Engine) -> Self {
-        let actor = CtrlServerActor { addr, engine };
+    /// Listens on the Unix socket in the data directory and,
+    /// if `addr` is set, on a TCP address as well.
+    pub fn start(addr: Option<SocketAddr>, data_dir: PathBuf, engine: Engine) -> Self {
+        let actor = CtrlServerActor {
+            addr,
+            data_dir,
+            engine,
+        };
         meio::spawn(actor)
     }
 }
 
 pub struct CtrlServerActor {
-    addr: SocketAddr,
+    addr: Option<SocketAddr>,
+    data_dir: PathBuf,
     engine: Engine,
 }
 
@@ -39,42 +68,386 @@ impl Actor for CtrlServerActor {
 
 impl CtrlServerActor {
     async fn run(&mut self, _: Context<Self>) -> Result<(), Error> {
-        let mut listener = TcpListener::bind(&self.addr).await?;
-        let mut incoming = listener.incoming().fuse();
-        while let Some(stream) = incoming.next().await.transpose()? {
-            CtrlHandler::upgrade(stream, self.engine.clone());
+        let token = Arc::new(wait(|| load_or_create_token(&self.data_dir))?);
+        #[cfg(unix)]
+        let local = listen_unix(&self.data_dir, self.engine.clone(), token.clone());
+        #[cfg(not(unix))]
+        let local = futures::future::pending::<Result<(), Error>>();
+        if let Some(addr) = self.addr {
+            let tcp = listen_tcp(addr, self.engine.clone(), token);
+            tokio::try_join!(local, tcp)?;
+        } else {
+            local.await?;
         }
         Ok(())
     }
 }
 
+async fn listen_tcp(addr: SocketAddr, engine: Engine, token: Arc<String>) -> Result<(), Error> {
+    log::info!("Ctrl listens on {}", addr);
+    let mut listener = TcpListener::bind(&addr).await?;
+    let mut incoming = listener.incoming().fuse();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => CtrlHandler::upgrade(stream, engine.clone(), token.clone()),
+            // A failed connection doesn't stop the server
+            Err(err) => log::warn!("Can't accept a ctrl connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Accepts connections from processes of the socket's owner or root only.
+#[cfg(unix)]
+async fn listen_unix(data_dir: &Path, engine: Engine, token: Arc<String>) -> Result<(), Error> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+    use tokio::net::UnixListener;
+
+    let path = socket_path(data_dir);
+    fs::create_dir_all(data_dir)?;
+    if let Ok(metadata) = fs::symlink_metadata(&path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("not a socket: {}", path.display()));
+        }
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!(
+                "socket is used by another server: {}",
+                path.display()
+            ));
+        }
+        // Left by a previous run
+        fs::remove_file(&path)?;
+    }
+    // The socket is bound in a private directory and moved
+    // to the data directory when only the owner can connect
+    let private_dir = data_dir.join(".ctrl");
+    if private_dir.exists() {
+        fs::remove_dir_all(&private_dir)?;
+    }
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+    let private_path = private_dir.join("ctrl.sock");
+    let mut listener = UnixListener::bind(&private_path)?;
+    fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&private_path, &path)?;
+    fs::remove_dir(&private_dir)?;
+    let owner = fs::metadata(&path)?.uid();
+    log::info!("Ctrl listens on {}", path.display());
+    let mut incoming = listener.incoming().fuse();
+    while let Some(stream) = incoming.next().await {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("Can't accept a ctrl connection: {}", err);
+                continue;
+            }
+        };
+        let uid = match stream.peer_cred() {
+            Ok(cred) => cred.uid,
+            Err(err) => {
+                log::warn!("Can't get credentials of a ctrl connection: {}", err);
+                continue;
+            }
+        };
+        if uid == owner || uid == 0 {
+            CtrlHandler::upgrade(stream, engine.clone(), token.clone());
+        } else {
+            log::warn!("Ctrl connection from uid {} rejected", uid);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the admin token or generates it on the first start.
+/// Only the owner of the file can read it.
+fn load_or_create_token(data_dir: &Path) -> Result<String, Error> {
+    let path = token_path(data_dir);
+    if path.exists() {
+        let token = fs::read_to_string(&path)?;
+        let token = token.trim();
+        // An empty token would let anyone in
+        if token.is_empty() {
+            return Err(anyhow!("admin token is empty: {}", path.display()));
+        }
+        return Ok(token.to_owned());
+    }
+    log::info!("Generating admin token: {}", path.display());
+    fs::create_dir_all(data_dir)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let token = generate_key();
+    let mut file = options.open(&path)?;
+    file.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
 struct CtrlHandler {
     connection: NetworkConnection<ControllerProtocol>,
     engine: Engine,
+    token: Arc<String>,
 }
 
 impl CtrlHandler {
-    fn upgrade(stream: TcpStream, engine: Engine) {
-        tokio::spawn(Self::handle(stream, engine));
+    fn upgrade<S>(stream: S, engine: Engine, token: Arc<String>)
+    where
+        S: Transport + 'static,
+    {
+        tokio::spawn(Self::handle(stream, engine, token));
     }
 
-    async fn handle(stream: TcpStream, engine: Engine) {
+    async fn handle<S>(stream: S, engine: Engine, token: Arc<String>)
+    where
+        S: Transport + 'static,
+    {
         let connection = wrap(stream);
-        let this = Self { connection, engine };
+        let this = Self {
+            connection,
+            engine,
+            token,
+        };
         if let Err(err) = this.routine().await {
             log::error!("CtrlHandler error: {}", err);
         }
     }
 
+    /// Expects the admin token in the first message.
+    async fn handshake(&mut self) -> Result<bool, Error> {
+        // Silent connections are rejected as well
+        let msg = match timeout(HANDSHAKE_TIMEOUT, self.connection.next()).await {
+            Ok(msg) => msg.transpose()?,
+            Err(_) => None,
+        };
+        let authenticated = match msg {
+            Some(ClientToController::Authenticate { token }) => {
+                constant_time_eq(token.as_bytes(), self.token.as_bytes())
+            }
+            _ => false,
+        };
+        if authenticated {
+            self.send(ControllerToClient::Authenticated).await?;
+        } else {
+            log::warn!("Ctrl connection rejected");
+            let reason = "Authentication required".to_string();
+            self.send(ControllerToClient::Fail(reason)).await?;
+        }
+        Ok(authenticated)
+    }
+
     async fn send(&mut self, response: ControllerToClient) -> Result<(), Error> {
         self.connection.send(response).await.map_err(Error::from)
     }
 
+    async fn find_user_id(&mut self, username: Username) -> Result<UserId, Error> {
+        let user = self.engine.find_user(username.clone()).await?;
+        user.map(|user| user.id)
+            .ok_or_else(|| EngineError::UserNotFound(username).into())
+    }
+
+    async fn find_existing_user(&mut self, username: Username) -> Result<User, Error> {
+        let user = self.engine.find_user(username.clone()).await?;
+        user.ok_or_else(|| EngineError::UserNotFound(username).into())
+    }
+
+    async fn get_users(&mut self) -> Result<Vec<UserInfo>, Error> {
+        let users = self.engine.get_users().await?;
+        Ok(users.into_iter().map(user_info).collect())
+    }
+
+    async fn get_user(
+        &mut self,
+        username: Username,
+    ) -> Result<(UserInfo, Vec<ChannelName>), Error> {
+        let user = self.find_existing_user(username).await?;
+        let channels = self.engine.get_user_channels(user.id).await?;
+        let channels = channels
+            .into_iter()
+            .map(|channel| channel.channel)
+            .collect();
+        Ok((user_info(user), channels))
+    }
+
+    async fn rename_user(
+        &mut self,
+        username: Username,
+        new_username: Username,
+    ) -> Result<(), Error> {
+        let user_id = self.find_user_id(username).await?;
+        self.engine.rename_user(user_id, new_username).await
+    }
+
+    async fn set_disabled(&mut self, username: Username, disabled: bool) -> Result<(), Error> {
+        let user_id = self.find_user_id(username).await?;
+        self.engine.set_disabled(user_id, disabled).await
+    }
+
+    async fn delete_user(&mut self, username: Username) -> Result<(), Error> {
+        let user_id = self.find_user_id(username).await?;
+        self.engine.delete_user(user_id).await
+    }
+
+    async fn find_channel_id(&mut self, channel: ChannelName) -> Result<ChannelId, Error> {
+        let record = self.engine.find_channel(channel.clone()).await?;
+        record
+            .map(|record| record.id)
+            .ok_or_else(|| EngineError::ChannelNotFound(channel).into())
+    }
+
+    async fn add_member(&mut self, channel: ChannelName, username: Username) -> Result<(), Error> {
+        let channel_id = self.find_channel_id(channel).await?;
+        let user_id = self.find_user_id(username).await?;
+        self.engine
+            .add_member(channel_id, user_id, Initiator::Ctrl)
+            .await
+    }
+
+    async fn remove_member(
+        &mut self,
+        channel: ChannelName,
+        username: Username,
+    ) -> Result<(), Error> {
+        let channel_id = self.find_channel_id(channel).await?;
+        let user_id = self.find_user_id(username).await?;
+        self.engine
+            .remove_member(channel_id, user_id, Initiator::Ctrl)
+            .await
+    }
+
+    async fn set_user_role(&mut self, username: Username, role: UserRole) -> Result<(), Error> {
+        let user_id = self.find_user_id(username).await?;
+        self.engine
+            .set_user_role(user_id, role, Initiator::Ctrl)
+            .await
+    }
+
+    async fn set_member_role(
+        &mut self,
+        channel: ChannelName,
+        username: Username,
+        role: MemberRole,
+    ) -> Result<(), Error> {
+        let channel_id = self.find_channel_id(channel).await?;
+        let user_id = self.find_user_id(username).await?;
+        self.engine
+            .set_member_role(channel_id, user_id, role, Initiator::Ctrl)
+            .await
+    }
+
+    async fn get_members(&mut self, channel: ChannelName) -> Result<Vec<Username>, Error> {
+        let channel_id = self.find_channel_id(channel).await?;
+        let members = self.engine.get_members(channel_id).await?;
+        Ok(members.into_iter().map(|user| user.username).collect())
+    }
+
+    async fn get_sessions(&mut self, username: Username) -> Result<Vec<SessionInfo>, Error> {
+        let user_id = self.find_user_id(username).await?;
+        let sessions = self.engine.get_sessions(user_id).await?;
+        let sessions = sessions
+            .into_iter()
+            .map(|session| SessionInfo {
+                id: session.id.into(),
+                created_at: session.created_at,
+                last_seen: session.last_seen,
+            })
+            .collect();
+        Ok(sessions)
+    }
+
+    async fn revoke_sessions(&mut self, username: Username) -> Result<(), Error> {
+        let user_id = self.find_user_id(username).await?;
+        self.engine.revoke_sessions(user_id).await
+    }
+
+    async fn create_api_token(
+        &mut self,
+        username: Username,
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    ) -> Result<(String, ApiTokenInfo), Error> {
+        let user_id = self.find_user_id(username).await?;
+        let (key, token) = self
+            .engine
+            .create_token(user_id, name, scope, lifetime, Initiator::Ctrl)
+            .await?;
+        Ok((key, api_token_info(token)))
+    }
+
+    async fn get_api_tokens(&mut self, username: Username) -> Result<Vec<ApiTokenInfo>, Error> {
+        let user_id = self.find_user_id(username).await?;
+        let tokens = self.engine.get_tokens(user_id).await?;
+        Ok(tokens.into_iter().map(api_token_info).collect())
+    }
+
+    async fn get_lockouts(&mut self) -> Result<Vec<LockoutInfo>, Error> {
+        let lockouts = self.engine.get_lockouts().await?;
+        let lockouts = lockouts
+            .into_iter()
+            .map(|lockout| LockoutInfo {
+                target: lockout.target.to_string(),
+                failures: lockout.failures,
+                remaining: lockout.remaining.as_secs(),
+            })
+            .collect();
+        Ok(lockouts)
+    }
+
+    async fn clear_lockouts(
+        &mut self,
+        username: Option<Username>,
+        address: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        let mut targets = Vec::new();
+        if let Some(username) = username {
+            targets.push(Target::Username(username));
+        }
+        if let Some(address) = address {
+            targets.push(Target::Address(address));
+        }
+        self.engine.clear_lockouts(targets).await
+    }
+
+    async fn get_audit(
+        &mut self,
+        since: Option<Timestamp>,
+        actor: Option<String>,
+        action: Option<AuditAction>,
+    ) -> Result<Vec<AuditInfo>, Error> {
+        let filter = AuditFilter {
+            since,
+            actor,
+            action,
+        };
+        let records = self.engine.get_audit(filter).await?;
+        let records = records
+            .into_iter()
+            .map(|record| AuditInfo {
+                created_at: record.created_at,
+                actor: record.actor,
+                action: record.action,
+                target: record.target,
+                details: record.details,
+            })
+            .collect();
+        Ok(records)
+    }
+
     async fn routine(mut self) -> Result<(), Error> {
         log::debug!("CtrlHandler started");
+        if !self.handshake().await? {
+            return Ok(());
+        }
         while let Some(msg) = self.connection.next().await.transpose()? {
             log::trace!("Ctrl message: {:?}", msg);
             match msg {
+                ClientToController::Authenticate { .. } => {
+                    let reason = "Already authenticated".to_string();
+                    self.send(ControllerToClient::Fail(reason)).await?;
+                }
                 ClientToController::CreateUser { username, password } => {
                     log::debug!("Creating user: {}", username);
                     let response = self
@@ -101,6 +474,97 @@ impl CtrlHandler {
                         });
                     self.send(response).await?;
                 }
+                ClientToController::GetUsers => {
+                    log::debug!("Getting users");
+                    let response = self
+                        .get_users()
+                        .await
+                        .map(|users| ControllerToClient::UsersList { users })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get users: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetUser { username } => {
+                    log::debug!("Getting user {}", username);
+                    let response = self
+                        .get_user(username)
+                        .await
+                        .map(|(user, channels)| ControllerToClient::UserDetails { user, channels })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get user: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::RenameUser {
+                    username,
+                    new_username,
+                } => {
+                    log::debug!("Renaming user {} to {}", username, new_username);
+                    let response = self
+                        .rename_user(username.clone(), new_username.clone())
+                        .await
+                        .map(|_| ControllerToClient::UserRenamed {
+                            username,
+                            new_username,
+                        })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't rename user: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::DisableUser { username } => {
+                    log::debug!("Disabling user {}", username);
+                    let response = self
+                        .set_disabled(username.clone(), true)
+                        .await
+                        .map(|_| ControllerToClient::UserDisabled { username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't disable user: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::EnableUser { username } => {
+                    log::debug!("Enabling user {}", username);
+                    let response = self
+                        .set_disabled(username.clone(), false)
+                        .await
+                        .map(|_| ControllerToClient::UserEnabled { username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't enable user: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::DeleteUser { username } => {
+                    log::debug!("Deleting user {}", username);
+                    let response = self
+                        .delete_user(username.clone())
+                        .await
+                        .map(|_| ControllerToClient::UserDeleted { username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't delete user: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::CreateInvite { uses, lifetime } => {
+                    log::debug!("Creating invite for {} uses", uses);
+                    let response = self
+                        .engine
+                        .create_invite(uses, lifetime)
+                        .await
+                        .map(|token| ControllerToClient::InviteCreated { token })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't create invite: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
                 ClientToController::CreateChannel { channel, username } => {
                     let user = self.engine.find_user(username.clone()).await;
                     // TODO: Refactor that match part
@@ -110,7 +574,7 @@ impl CtrlHandler {
                                 log::debug!("Creating channel: {}", channel);
                                 let response = self
                                     .engine
-                                    .create_channel(channel.clone(), user.id)
+                                    .create_channel(channel.clone(), user.id, Initiator::Ctrl)
                                     .await
                                     .map(|_| ControllerToClient::ChannelCreated { channel })
                                     .unwrap_or_else(|err| {
@@ -152,7 +616,7 @@ impl CtrlHandler {
                     log::debug!("Deleting channel: {}", channel);
                     let response = self
                         .engine
-                        .delete_channel(channel.clone())
+                        .delete_channel(channel.clone(), Initiator::Ctrl)
                         .await
                         .map(|_| ControllerToClient::ChannelDeleted { channel })
                         .unwrap_or_else(|err| {
@@ -161,8 +625,220 @@ impl CtrlHandler {
                         });
                     self.send(response).await?;
                 }
+                ClientToController::AddMember { channel, username } => {
+                    log::debug!("Adding member {} to channel {}", username, channel);
+                    let response = self
+                        .add_member(channel.clone(), username.clone())
+                        .await
+                        .map(|_| ControllerToClient::MemberAdded { channel, username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't add member: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::RemoveMember { channel, username } => {
+                    log::debug!("Removing member {} from channel {}", username, channel);
+                    let response = self
+                        .remove_member(channel.clone(), username.clone())
+                        .await
+                        .map(|_| ControllerToClient::MemberRemoved { channel, username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't remove member: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetMembers { channel } => {
+                    log::debug!("Getting members of channel {}", channel);
+                    let response = self
+                        .get_members(channel.clone())
+                        .await
+                        .map(|members| ControllerToClient::MembersList { channel, members })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get members: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::SetUserRole { username, role } => {
+                    log::debug!("Setting role of {}: {}", username, role);
+                    let response = self
+                        .set_user_role(username.clone(), role)
+                        .await
+                        .map(|_| ControllerToClient::UserRoleSet { username, role })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't set user role: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::SetMemberRole {
+                    channel,
+                    username,
+                    role,
+                } => {
+                    log::debug!(
+                        "Setting role of {} in channel {}: {}",
+                        username,
+                        channel,
+                        role
+                    );
+                    let response = self
+                        .set_member_role(channel.clone(), username.clone(), role)
+                        .await
+                        .map(|_| ControllerToClient::MemberRoleSet {
+                            channel,
+                            username,
+                            role,
+                        })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't set member role: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetSessions { username } => {
+                    log::debug!("Getting sessions of {}", username);
+                    let response = self
+                        .get_sessions(username.clone())
+                        .await
+                        .map(|sessions| ControllerToClient::SessionsList { username, sessions })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get sessions: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::RevokeSession { session } => {
+                    log::debug!("Revoking session {}", session);
+                    let response = self
+                        .engine
+                        .revoke_session(session.into(), Initiator::Ctrl)
+                        .await
+                        .map(|_| ControllerToClient::SessionRevoked { session })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't revoke session: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::RevokeSessions { username } => {
+                    log::debug!("Revoking sessions of {}", username);
+                    let response = self
+                        .revoke_sessions(username.clone())
+                        .await
+                        .map(|_| ControllerToClient::SessionsRevoked { username })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't revoke sessions: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::CreateApiToken {
+                    username,
+                    name,
+                    scope,
+                    lifetime,
+                } => {
+                    log::debug!("Creating token {} of {}", name, username);
+                    let response = self
+                        .create_api_token(username, name, scope, lifetime)
+                        .await
+                        .map(|(key, token)| ControllerToClient::ApiTokenCreated { key, token })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't create token: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetApiTokens { username } => {
+                    log::debug!("Getting tokens of {}", username);
+                    let response = self
+                        .get_api_tokens(username.clone())
+                        .await
+                        .map(|tokens| ControllerToClient::ApiTokensList { username, tokens })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get tokens: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::RevokeApiToken { token } => {
+                    log::debug!("Revoking token {}", token);
+                    let response = self
+                        .engine
+                        .revoke_token(token.into(), Initiator::Ctrl)
+                        .await
+                        .map(|_| ControllerToClient::ApiTokenRevoked { token })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't revoke token: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetLockouts => {
+                    log::debug!("Getting lockouts");
+                    let response = self
+                        .get_lockouts()
+                        .await
+                        .map(|lockouts| ControllerToClient::LockoutsList { lockouts })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get lockouts: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::ClearLockouts { username, address } => {
+                    log::debug!("Clearing lockouts: {:?} {:?}", username, address);
+                    let response = self
+                        .clear_lockouts(username, address)
+                        .await
+                        .map(|_| ControllerToClient::LockoutsCleared)
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't clear lockouts: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
+                ClientToController::GetAudit {
+                    since,
+                    actor,
+                    action,
+                } => {
+                    log::debug!("Getting audit records");
+                    let response = self
+                        .get_audit(since, actor, action)
+                        .await
+                        .map(|records| ControllerToClient::AuditList { records })
+                        .unwrap_or_else(|err| {
+                            log::error!("Can't get audit records: {}", err);
+                            ControllerToClient::Fail(err.to_string())
+                        });
+                    self.send(response).await?;
+                }
             }
         }
         Ok(())
     }
 }
+
+fn user_info(user: User) -> UserInfo {
+    UserInfo {
+        id: user.id.into(),
+        username: user.username,
+        role: user.role,
+        disabled: user.disabled,
+    }
+}
+
+fn api_token_info(token: ApiToken) -> ApiTokenInfo {
+    ApiTokenInfo {
+        id: token.id.into(),
+        name: token.name,
+        scope: token.scope,
+        created_at: token.created_at,
+        last_used: token.last_used,
+        expires_at: token.expires_at,
+    }
+}
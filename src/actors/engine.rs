@@ -6,9 +6,9 @@
 //! a corresponding users performed by `ctrl` and `live`
 //! actors.
 //!
-//! Also `EngineActor` has to notify other actors about
-//! changes. It's a central point of all changes applied to
-//! a database.
+//! Also `EngineActor` notifies live connections about
+//! changes using a `Registry` of connections. It's a central
+//! point of all changes applied to a database.
 //!
 //! In the future `EngineActor` will wrap accompanied requests
 //! to transactions. That's another reason why is better to keep
@@ -17,22 +17,155 @@
 // TODO: Rewrite this module to fully async
 // when SQLite crates will support that.
 
-use crate::db::types::{ChannelId, ChannelName, Password, UserId, Username};
-use crate::db::{Channel, Dba, DbaError, Session, User};
-use crate::generators::generate_key;
+use super::lockouts::{Lockout, Lockouts, Target};
+use super::permissions::{Initiator, Permission};
+use super::registry::{ConnectionId, LiveSender, Login, Registry};
+use crate::db::types::{
+    AuditAction, ChannelId, ChannelKind, ChannelName, Emoji, KeyHash, MemberRole, MessageId,
+    Password, SessionId, Timestamp, TokenId, TokenScope, UserId, UserRole, Username,
+};
+use crate::db::{
+    ApiToken, AuditFilter, AuditRecord, Channel, Dba, DbaError, Message, Profile, Revision,
+    Session, Totp, User,
+};
+use crate::generators::{generate_key, generate_token, hash_key};
+use crate::mentions;
+use crate::passwords::{self, Verification};
+use crate::totp;
 use anyhow::Error;
 use async_trait::async_trait;
+use constant_time_eq::constant_time_eq;
 use meio::{wrapper, Actor, Address, Interaction, InteractionHandler};
-use protocol::Key;
+use protocol::{
+    ChannelUpdate, Credentials, Delta, Key, MemberUpdate, MentionUpdate, MessageUpdate,
+    ProfileUpdate, ThreadUpdate,
+};
 use rusqlite::Error as SqlError;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
 use tokio::task::block_in_place as wait;
 
+/// Maximal amount of messages in a history page. A page has at least one.
+const MAX_HISTORY_LIMIT: u32 = 100;
+
+/// Maximal amount of users in a direct conversation.
+const MAX_DIRECT_MEMBERS: usize = 8;
+
+/// Maximal length of an emoji name in bytes.
+const MAX_EMOJI_LEN: usize = 64;
+
+/// Maximal length of a display name in chars.
+const MAX_DISPLAY_NAME_LEN: usize = 64;
+
+/// Maximal length of a status text in chars.
+const MAX_STATUS_LEN: usize = 140;
+
+/// Maximal length of other profile fields in bytes.
+const MAX_PROFILE_FIELD_LEN: usize = 512;
+
+/// Maximal length of a name of an API token in chars.
+const MAX_TOKEN_NAME_LEN: usize = 64;
+
+/// Maximal lifetime of invites and API tokens in seconds.
+const MAX_LIFETIME: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// Prefix of names of direct conversations.
+/// Named channels can't start with it.
+const DIRECT_PREFIX: &str = "@";
+
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("user {user_id:?} is not a member of channel {channel_id:?}")]
+    NotMember {
+        channel_id: ChannelId,
+        user_id: UserId,
+    },
+    #[error("user {user_id:?} is already a member of channel {channel_id:?}")]
+    AlreadyMember {
+        channel_id: ChannelId,
+        user_id: UserId,
+    },
+    #[error("can't remove the last member of channel {channel_id:?}")]
+    LastMember { channel_id: ChannelId },
+    #[error("user is the only member of channels: {}", .channels.join(", "))]
+    OnlyMember { channels: Vec<ChannelName> },
+    #[error("can't find user: {0}")]
+    UserNotFound(Username),
+    #[error("can't find channel: {0}")]
+    ChannelNotFound(ChannelName),
+    #[error("channel {channel_id:?} doesn't exist")]
+    UnknownChannel { channel_id: ChannelId },
+    #[error("invalid channel name: {0}")]
+    InvalidChannelName(ChannelName),
+    #[error("direct channel {channel_id:?} can't be changed")]
+    DirectChannel { channel_id: ChannelId },
+    #[error("direct channel must have from 2 to {max} members")]
+    DirectMembers { max: usize },
+    #[error("message {message_id:?} doesn't exist")]
+    UnknownMessage { message_id: MessageId },
+    #[error("invalid emoji: {0}")]
+    InvalidEmoji(Emoji),
+    #[error("message {message_id:?} is a reply and can't have a thread")]
+    NestedThread { message_id: MessageId },
+    #[error("session {session_id:?} doesn't exist")]
+    UnknownSession { session_id: SessionId },
+    #[error("token {token_id:?} doesn't exist")]
+    UnknownToken { token_id: TokenId },
+    #[error("invalid token name: {0}")]
+    InvalidTokenName(String),
+    #[error("lifetime must be from 1 to {max} seconds: {lifetime}")]
+    InvalidLifetime { lifetime: i64, max: i64 },
+    #[error("invalid username: {0}")]
+    InvalidUsername(Username),
+    #[error("user {user_id:?} is deleted")]
+    DeletedUser { user_id: UserId },
+    #[error("username is already taken: {0}")]
+    UsernameTaken(Username),
+    #[error("password can't be empty")]
+    EmptyPassword,
+    #[error("invite is invalid or expired")]
+    InvalidInvite,
+    #[error("second factor is already enabled")]
+    TotpEnabled,
+    #[error("second factor is not enrolled")]
+    TotpNotEnrolled,
+    #[error("invalid password")]
+    InvalidPassword,
+    #[error("invalid code of the second factor")]
+    InvalidCode,
+    #[error("invalid {field} in profile")]
+    InvalidProfile { field: &'static str },
+    #[error("permission denied: user {user_id:?} can't {permission}")]
+    PermissionDenied {
+        user_id: UserId,
+        permission: Permission,
+    },
+}
+
+/// Limits of sessions lifetime in seconds.
+#[derive(Debug, Clone)]
+pub struct SessionLimits {
+    /// A session expires if it wasn't restored for this period.
+    pub idle: i64,
+    /// A session expires after this period since a login.
+    pub lifetime: i64,
+}
+
 /// `Engine` provides business logic methods to manage data.
 wrapper!(Engine for EngineActor);
 
 impl Engine {
-    pub fn start() -> Self {
-        let actor = EngineActor { dba: None };
+    /// Opens the database in `data_dir`.
+    pub fn start(data_dir: PathBuf, session_limits: SessionLimits) -> Self {
+        let actor = EngineActor {
+            data_dir,
+            dba: None,
+            registry: Registry::default(),
+            lockouts: Lockouts::default(),
+            session_limits,
+        };
         meio::spawn(actor)
     }
 
@@ -53,236 +186,2396 @@ impl Engine {
             .await
     }
 
+    /// Creates an invite for `uses` registrations that expires
+    /// after `lifetime` seconds. Returns a token that is not stored anywhere.
+    pub async fn create_invite(&mut self, uses: u32, lifetime: Option<i64>) -> Result<Key, Error> {
+        let token = generate_key();
+        let token_hash = hash_key(&token);
+        self.interaction(CreateInvite {
+            token_hash,
+            uses,
+            lifetime,
+        })
+        .await?;
+        Ok(token)
+    }
+
+    /// Creates a user with an invite token.
+    pub async fn register(&mut self, token: Key, credentials: Credentials) -> Result<User, Error> {
+        let token_hash = hash_key(&token);
+        self.interaction(Register {
+            token_hash,
+            credentials,
+        })
+        .await
+    }
+
     pub async fn find_user(&mut self, username: Username) -> Result<Option<User>, Error> {
         self.interaction(FindUser { username }).await
     }
 
-    pub async fn create_session(&mut self, user_id: UserId) -> Result<Key, Error> {
-        let key = generate_key();
-        // TODO: Protect key here
-        self.interaction(CreateSession {
+    /// The email is returned to the user itself and to admins only.
+    pub async fn get_profile(
+        &mut self,
+        user_id: UserId,
+        initiator: Initiator,
+    ) -> Result<Profile, Error> {
+        self.interaction(GetProfile { user_id, initiator }).await
+    }
+
+    /// Replaces the profile and notifies users that share channels with the user.
+    pub async fn update_profile(&mut self, user_id: UserId, profile: Profile) -> Result<(), Error> {
+        self.interaction(UpdateProfile { user_id, profile }).await
+    }
+
+    /// Returns all users except deleted.
+    pub async fn get_users(&mut self) -> Result<Vec<User>, Error> {
+        self.interaction(GetUsers).await
+    }
+
+    pub async fn rename_user(&mut self, user_id: UserId, username: Username) -> Result<(), Error> {
+        self.interaction(RenameUser { user_id, username }).await
+    }
+
+    /// Disabled users can't log in and their sessions are revoked.
+    pub async fn set_disabled(&mut self, user_id: UserId, disabled: bool) -> Result<(), Error> {
+        self.interaction(SetDisabled { user_id, disabled }).await
+    }
+
+    /// Removes a user from all channels, revokes sessions and frees the username.
+    /// Messages of the user are kept.
+    pub async fn delete_user(&mut self, user_id: UserId) -> Result<(), Error> {
+        self.interaction(DeleteUser { user_id }).await
+    }
+
+    /// Checks credentials of a login attempt from an address.
+    pub async fn authenticate(
+        &mut self,
+        credentials: Credentials,
+        address: Option<IpAddr>,
+    ) -> Result<Authentication, Error> {
+        self.interaction(Authenticate {
+            credentials,
+            address,
+        })
+        .await
+    }
+
+    /// Checks a code of the second factor of a user that passed `authenticate`.
+    pub async fn verify_second_factor(
+        &mut self,
+        user_id: UserId,
+        code: String,
+        address: Option<IpAddr>,
+    ) -> Result<Authentication, Error> {
+        self.interaction(VerifySecondFactor {
+            user_id,
+            code,
+            address,
+        })
+        .await
+    }
+
+    /// Generates a new TOTP secret and returns it with a provisioning URI.
+    /// The second factor is not required until `confirm_totp` is called.
+    pub async fn enroll_totp(
+        &mut self,
+        user_id: UserId,
+        password: Password,
+    ) -> Result<(String, String), Error> {
+        self.interaction(EnrollTotp { user_id, password }).await
+    }
+
+    /// Enables the second factor and returns recovery codes that are not stored anywhere.
+    pub async fn confirm_totp(
+        &mut self,
+        user_id: UserId,
+        code: String,
+        password: Password,
+    ) -> Result<Vec<Key>, Error> {
+        self.interaction(ConfirmTotp {
             user_id,
-            key: key.clone(),
+            code,
+            password,
+        })
+        .await
+    }
+
+    pub async fn disable_totp(&mut self, user_id: UserId, code: String) -> Result<(), Error> {
+        self.interaction(DisableTotp { user_id, code }).await
+    }
+
+    pub async fn get_lockouts(&mut self) -> Result<Vec<Lockout>, Error> {
+        self.interaction(GetLockouts).await
+    }
+
+    /// Clears lockouts of the targets or all lockouts if there are no targets.
+    pub async fn clear_lockouts(&mut self, targets: Vec<Target>) -> Result<(), Error> {
+        self.interaction(ClearLockouts { targets }).await
+    }
+
+    /// Creates a session and returns its key that is not stored anywhere.
+    pub async fn create_session(&mut self, user_id: UserId) -> Result<(Key, Session), Error> {
+        let key = generate_key();
+        let key_hash = hash_key(&key);
+        let session = self
+            .interaction(CreateSession { user_id, key_hash })
+            .await?;
+        Ok((key, session))
+    }
+
+    /// Returns a session if it's not expired and updates its last visit.
+    pub async fn restore_session(&mut self, key: Key) -> Result<Option<Session>, Error> {
+        let key_hash = hash_key(&key);
+        self.interaction(RestoreSession { key_hash }).await
+    }
+
+    /// Updates the last visit of a live session and returns the time
+    /// it expires at, or `None` if the session has already expired.
+    pub async fn touch_session(
+        &mut self,
+        session_id: SessionId,
+    ) -> Result<Option<Timestamp>, Error> {
+        self.interaction(TouchSession { session_id }).await
+    }
+
+    pub async fn get_sessions(&mut self, user_id: UserId) -> Result<Vec<Session>, Error> {
+        self.interaction(GetSessions { user_id }).await
+    }
+
+    /// Deletes a session and disconnects its live connections.
+    pub async fn revoke_session(
+        &mut self,
+        session_id: SessionId,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(RevokeSession {
+            session_id,
+            initiator,
+        })
+        .await
+    }
+
+    /// Deletes all sessions of a user and disconnects its live connections.
+    pub async fn revoke_sessions(&mut self, user_id: UserId) -> Result<(), Error> {
+        self.interaction(RevokeSessions { user_id }).await
+    }
+
+    /// Creates an API token and returns its key that is not stored anywhere.
+    pub async fn create_token(
+        &mut self,
+        user_id: UserId,
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+        initiator: Initiator,
+    ) -> Result<(Key, ApiToken), Error> {
+        let key = generate_token();
+        let key_hash = hash_key(&key);
+        let token = self
+            .interaction(CreateToken {
+                user_id,
+                key_hash,
+                name,
+                scope,
+                lifetime,
+                initiator,
+            })
+            .await?;
+        Ok((key, token))
+    }
+
+    /// Returns a token if it's not expired and its user is not disabled.
+    pub async fn use_token(&mut self, key: Key) -> Result<Option<ApiToken>, Error> {
+        let key_hash = hash_key(&key);
+        self.interaction(UseToken { key_hash }).await
+    }
+
+    pub async fn get_tokens(&mut self, user_id: UserId) -> Result<Vec<ApiToken>, Error> {
+        self.interaction(GetTokens { user_id }).await
+    }
+
+    /// Deletes a token and disconnects its live connections.
+    /// Users can revoke their own tokens only.
+    pub async fn revoke_token(
+        &mut self,
+        token_id: TokenId,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(RevokeToken {
+            token_id,
+            initiator,
         })
         .await
-        .map(|_| key)
     }
 
-    pub async fn find_session(&mut self, key: Key) -> Result<Option<Session>, Error> {
-        // TODO: Check key here
-        self.interaction(FindSession { key }).await
+    /// Returns records of the audit log from the oldest.
+    pub async fn get_audit(&mut self, filter: AuditFilter) -> Result<Vec<AuditRecord>, Error> {
+        self.interaction(GetAudit { filter }).await
     }
 
     pub async fn create_channel(
         &mut self,
         channel: ChannelName,
         user_id: UserId,
+        initiator: Initiator,
     ) -> Result<(), Error> {
-        self.interaction(CreateChannel { channel, user_id }).await
+        self.interaction(CreateChannel {
+            channel,
+            user_id,
+            initiator,
+        })
+        .await
+    }
+
+    pub async fn find_channel(&mut self, channel: ChannelName) -> Result<Option<Channel>, Error> {
+        self.interaction(FindChannel { channel }).await
     }
 
     pub async fn get_channels(&mut self) -> Result<Vec<Channel>, Error> {
         self.interaction(GetChannels {}).await
     }
 
-    pub async fn delete_channel(&mut self, channel: ChannelName) -> Result<(), Error> {
-        self.interaction(DeleteChannel { channel }).await
+    pub async fn get_user_channels(&mut self, user_id: UserId) -> Result<Vec<Channel>, Error> {
+        self.interaction(GetUserChannels { user_id }).await
     }
-}
 
-pub struct EngineActor {
-    dba: Option<Dba>,
-}
+    /// Returns a direct channel of users or creates it if not exists.
+    pub async fn open_direct(&mut self, user_ids: Vec<UserId>) -> Result<Channel, Error> {
+        self.interaction(OpenDirect { user_ids }).await
+    }
 
-#[derive(Debug)]
-pub struct CreateUser {
-    username: Username,
-    password: Password,
+    pub async fn delete_channel(
+        &mut self,
+        channel: ChannelName,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(DeleteChannel { channel, initiator }).await
+    }
+
+    /// Adds a member. It's joining if the initiator is the user.
+    pub async fn add_member(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(AddMember {
+            channel_id,
+            user_id,
+            initiator,
+        })
+        .await
+    }
+
+    /// Removes a member. It's leaving if the initiator is the user.
+    pub async fn remove_member(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(RemoveMember {
+            channel_id,
+            user_id,
+            initiator,
+        })
+        .await
+    }
+
+    pub async fn set_user_role(
+        &mut self,
+        user_id: UserId,
+        role: UserRole,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(SetUserRole {
+            user_id,
+            role,
+            initiator,
+        })
+        .await
+    }
+
+    pub async fn set_member_role(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        role: MemberRole,
+        initiator: Initiator,
+    ) -> Result<(), Error> {
+        self.interaction(SetMemberRole {
+            channel_id,
+            user_id,
+            role,
+            initiator,
+        })
+        .await
+    }
+
+    pub async fn get_members(&mut self, channel_id: ChannelId) -> Result<Vec<User>, Error> {
+        self.interaction(GetMembers { channel_id }).await
+    }
+
+    pub async fn is_member(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<bool, Error> {
+        self.interaction(IsMember {
+            channel_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn post_message(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        text: String,
+    ) -> Result<Message, Error> {
+        self.interaction(PostMessage {
+            channel_id,
+            user_id,
+            text,
+        })
+        .await
+    }
+
+    pub async fn edit_message(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        text: String,
+    ) -> Result<Message, Error> {
+        self.interaction(EditMessage {
+            message_id,
+            user_id,
+            text,
+        })
+        .await
+    }
+
+    pub async fn delete_message(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<(), Error> {
+        self.interaction(DeleteMessage {
+            message_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn get_revisions(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<Vec<Revision>, Error> {
+        self.interaction(GetRevisions {
+            message_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn post_reply(
+        &mut self,
+        parent_id: MessageId,
+        user_id: UserId,
+        text: String,
+    ) -> Result<Message, Error> {
+        self.interaction(PostReply {
+            parent_id,
+            user_id,
+            text,
+        })
+        .await
+    }
+
+    pub async fn fetch_thread(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        before: Option<MessageId>,
+        limit: u32,
+    ) -> Result<Thread, Error> {
+        self.interaction(FetchThread {
+            message_id,
+            user_id,
+            before,
+            limit,
+        })
+        .await
+    }
+
+    pub async fn follow_thread(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<(), Error> {
+        self.interaction(FollowThread {
+            message_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn unfollow_thread(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<(), Error> {
+        self.interaction(UnfollowThread {
+            message_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn add_emoji(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        emoji: Emoji,
+    ) -> Result<(), Error> {
+        self.interaction(AddEmoji {
+            message_id,
+            user_id,
+            emoji,
+        })
+        .await
+    }
+
+    pub async fn remove_emoji(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        emoji: Emoji,
+    ) -> Result<(), Error> {
+        self.interaction(RemoveEmoji {
+            message_id,
+            user_id,
+            emoji,
+        })
+        .await
+    }
+
+    pub async fn fetch_history(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        before: Option<MessageId>,
+        limit: u32,
+    ) -> Result<History, Error> {
+        self.interaction(FetchHistory {
+            channel_id,
+            user_id,
+            before,
+            limit,
+        })
+        .await
+    }
+
+    pub async fn mark_read(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        message_id: MessageId,
+    ) -> Result<(), Error> {
+        self.interaction(MarkRead {
+            channel_id,
+            user_id,
+            message_id,
+        })
+        .await
+    }
+
+    /// Registers a live connection of a user to receive deltas.
+    pub async fn register_live(
+        &mut self,
+        user_id: UserId,
+        login: Login,
+        sender: LiveSender,
+    ) -> Result<ConnectionId, Error> {
+        self.interaction(RegisterLive {
+            user_id,
+            login,
+            sender,
+        })
+        .await
+    }
+
+    pub async fn unregister_live(&mut self, connection_id: ConnectionId) -> Result<(), Error> {
+        self.interaction(UnregisterLive { connection_id }).await
+    }
+
+    /// Subscribes a connection of the user to a channel the user is a member of.
+    pub async fn subscribe(
+        &mut self,
+        connection_id: ConnectionId,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<(), Error> {
+        self.interaction(Subscribe {
+            connection_id,
+            channel_id,
+            user_id,
+        })
+        .await
+    }
+
+    pub async fn unsubscribe(
+        &mut self,
+        connection_id: ConnectionId,
+        channel_id: ChannelId,
+    ) -> Result<(), Error> {
+        self.interaction(Unsubscribe {
+            connection_id,
+            channel_id,
+        })
+        .await
+    }
+}
+
+pub struct EngineActor {
+    data_dir: PathBuf,
+    dba: Option<Dba>,
+    registry: Registry,
+    lockouts: Lockouts,
+    session_limits: SessionLimits,
+}
+
+#[derive(Debug)]
+pub struct CreateUser {
+    username: Username,
+    password: Password,
+}
+
+impl Interaction for CreateUser {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct CreateInvite {
+    token_hash: KeyHash,
+    uses: u32,
+    lifetime: Option<i64>,
+}
+
+impl Interaction for CreateInvite {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct Register {
+    token_hash: KeyHash,
+    credentials: Credentials,
+}
+
+impl Interaction for Register {
+    type Output = User;
+}
+
+#[derive(Debug)]
+struct UpdatePassword {
+    username: Username,
+    password: Password,
+}
+
+impl Interaction for UpdatePassword {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct FindUser {
+    username: Username,
+}
+
+impl Interaction for FindUser {
+    type Output = Option<User>;
+}
+
+#[derive(Debug)]
+struct GetProfile {
+    user_id: UserId,
+    initiator: Initiator,
+}
+
+impl Interaction for GetProfile {
+    type Output = Profile;
+}
+
+#[derive(Debug)]
+struct UpdateProfile {
+    user_id: UserId,
+    profile: Profile,
+}
+
+impl Interaction for UpdateProfile {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct GetUsers;
+
+impl Interaction for GetUsers {
+    type Output = Vec<User>;
+}
+
+#[derive(Debug)]
+struct RenameUser {
+    user_id: UserId,
+    username: Username,
+}
+
+impl Interaction for RenameUser {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct SetDisabled {
+    user_id: UserId,
+    disabled: bool,
+}
+
+impl Interaction for SetDisabled {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct DeleteUser {
+    user_id: UserId,
+}
+
+impl Interaction for DeleteUser {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct Authenticate {
+    credentials: Credentials,
+    address: Option<IpAddr>,
+}
+
+impl Interaction for Authenticate {
+    type Output = Authentication;
+}
+
+#[derive(Debug)]
+pub enum Authentication {
+    Accepted(User),
+    Rejected,
+    /// Credentials are valid, but the account is disabled.
+    Disabled,
+    /// Credentials are valid, but a code of the second factor is required.
+    SecondFactor(User),
+    /// Too many failed attempts for the username or from the address.
+    Locked {
+        retry_after: Duration,
+    },
+}
+
+#[derive(Debug)]
+struct VerifySecondFactor {
+    user_id: UserId,
+    code: String,
+    address: Option<IpAddr>,
+}
+
+impl Interaction for VerifySecondFactor {
+    type Output = Authentication;
+}
+
+#[derive(Debug)]
+struct EnrollTotp {
+    user_id: UserId,
+    password: Password,
+}
+
+impl Interaction for EnrollTotp {
+    type Output = (String, String);
+}
+
+#[derive(Debug)]
+struct ConfirmTotp {
+    user_id: UserId,
+    code: String,
+    password: Password,
+}
+
+impl Interaction for ConfirmTotp {
+    type Output = Vec<Key>;
+}
+
+#[derive(Debug)]
+struct DisableTotp {
+    user_id: UserId,
+    code: String,
+}
+
+impl Interaction for DisableTotp {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct GetLockouts;
+
+impl Interaction for GetLockouts {
+    type Output = Vec<Lockout>;
+}
+
+#[derive(Debug)]
+struct ClearLockouts {
+    targets: Vec<Target>,
+}
+
+impl Interaction for ClearLockouts {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct CreateSession {
+    user_id: UserId,
+    key_hash: KeyHash,
+}
+
+impl Interaction for CreateSession {
+    type Output = Session;
+}
+
+#[derive(Debug)]
+struct RestoreSession {
+    key_hash: KeyHash,
+}
+
+impl Interaction for RestoreSession {
+    type Output = Option<Session>;
+}
+
+#[derive(Debug)]
+struct TouchSession {
+    session_id: SessionId,
+}
+
+impl Interaction for TouchSession {
+    type Output = Option<Timestamp>;
+}
+
+#[derive(Debug)]
+struct GetSessions {
+    user_id: UserId,
+}
+
+impl Interaction for GetSessions {
+    type Output = Vec<Session>;
+}
+
+#[derive(Debug)]
+struct RevokeSession {
+    session_id: SessionId,
+    initiator: Initiator,
+}
+
+impl Interaction for RevokeSession {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct RevokeSessions {
+    user_id: UserId,
+}
+
+impl Interaction for RevokeSessions {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct CreateToken {
+    user_id: UserId,
+    key_hash: KeyHash,
+    name: String,
+    scope: TokenScope,
+    lifetime: Option<i64>,
+    initiator: Initiator,
+}
+
+impl Interaction for CreateToken {
+    type Output = ApiToken;
+}
+
+#[derive(Debug)]
+struct UseToken {
+    key_hash: KeyHash,
+}
+
+impl Interaction for UseToken {
+    type Output = Option<ApiToken>;
+}
+
+#[derive(Debug)]
+struct GetTokens {
+    user_id: UserId,
+}
+
+impl Interaction for GetTokens {
+    type Output = Vec<ApiToken>;
+}
+
+#[derive(Debug)]
+struct RevokeToken {
+    token_id: TokenId,
+    initiator: Initiator,
+}
+
+impl Interaction for RevokeToken {
+    type Output = ();
+}
+
+#[derive(Debug)]
+struct GetAudit {
+    filter: AuditFilter,
+}
+
+impl Interaction for GetAudit {
+    type Output = Vec<AuditRecord>;
+}
+
+#[derive(Debug)]
+pub struct CreateChannel {
+    channel: ChannelName,
+    /// Channel's creator (first member/owner)
+    user_id: UserId,
+    initiator: Initiator,
+}
+
+impl Interaction for CreateChannel {
+    type Output = (); // TODO: Return channel info? At least channel Id.
+}
+
+#[derive(Debug)]
+struct FindChannel {
+    channel: ChannelName,
+}
+
+impl Interaction for FindChannel {
+    type Output = Option<Channel>;
+}
+
+#[derive(Debug)]
+pub struct GetChannels {
+    // TODO: Add user's filter
+}
+
+impl Interaction for GetChannels {
+    type Output = Vec<Channel>;
+}
+
+#[derive(Debug)]
+pub struct GetUserChannels {
+    user_id: UserId,
+}
+
+impl Interaction for GetUserChannels {
+    type Output = Vec<Channel>;
+}
+
+#[derive(Debug)]
+pub struct OpenDirect {
+    /// All members of a direct channel
+    user_ids: Vec<UserId>,
+}
+
+impl Interaction for OpenDirect {
+    type Output = Channel;
+}
+
+#[derive(Debug)]
+pub struct AddMember {
+    channel_id: ChannelId,
+    user_id: UserId,
+    initiator: Initiator,
+}
+
+impl Interaction for AddMember {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct RemoveMember {
+    channel_id: ChannelId,
+    user_id: UserId,
+    initiator: Initiator,
+}
+
+impl Interaction for RemoveMember {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct SetUserRole {
+    user_id: UserId,
+    role: UserRole,
+    initiator: Initiator,
+}
+
+impl Interaction for SetUserRole {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct SetMemberRole {
+    channel_id: ChannelId,
+    user_id: UserId,
+    role: MemberRole,
+    initiator: Initiator,
+}
+
+impl Interaction for SetMemberRole {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct GetMembers {
+    channel_id: ChannelId,
+}
+
+impl Interaction for GetMembers {
+    type Output = Vec<User>;
+}
+
+#[derive(Debug)]
+pub struct IsMember {
+    channel_id: ChannelId,
+    user_id: UserId,
+}
+
+impl Interaction for IsMember {
+    type Output = bool;
+}
+
+#[derive(Debug)]
+pub struct DeleteChannel {
+    channel: ChannelName,
+    initiator: Initiator,
+}
+
+impl Interaction for DeleteChannel {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct PostMessage {
+    channel_id: ChannelId,
+    /// Message's author (must be a member of the channel)
+    user_id: UserId,
+    text: String,
+}
+
+impl Interaction for PostMessage {
+    type Output = Message;
+}
+
+#[derive(Debug)]
+pub struct EditMessage {
+    message_id: MessageId,
+    /// Editor (must be an author of the message)
+    user_id: UserId,
+    text: String,
+}
+
+impl Interaction for EditMessage {
+    type Output = Message;
+}
+
+#[derive(Debug)]
+pub struct DeleteMessage {
+    message_id: MessageId,
+    /// An author of the message or a moderator of the channel
+    user_id: UserId,
+}
+
+impl Interaction for DeleteMessage {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct GetRevisions {
+    message_id: MessageId,
+    /// Reader (must be a member of the channel)
+    user_id: UserId,
+}
+
+impl Interaction for GetRevisions {
+    type Output = Vec<Revision>;
+}
+
+#[derive(Debug)]
+pub struct AddEmoji {
+    message_id: MessageId,
+    /// Must be a member of the channel
+    user_id: UserId,
+    emoji: Emoji,
+}
+
+impl Interaction for AddEmoji {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct RemoveEmoji {
+    message_id: MessageId,
+    user_id: UserId,
+    emoji: Emoji,
+}
+
+impl Interaction for RemoveEmoji {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct FetchHistory {
+    channel_id: ChannelId,
+    /// Reader (must be a member of the channel)
+    user_id: UserId,
+    before: Option<MessageId>,
+    limit: u32,
+}
+
+#[derive(Debug)]
+pub struct MarkRead {
+    channel_id: ChannelId,
+    user_id: UserId,
+    /// The last message the user has seen
+    message_id: MessageId,
+}
+
+impl Interaction for MarkRead {
+    type Output = ();
+}
+
+/// A page of messages ordered from the oldest to the newest.
+#[derive(Debug)]
+pub struct History {
+    pub messages: Vec<Message>,
+    /// Cursor for the previous page.
+    pub next: Option<MessageId>,
+}
+
+impl History {
+    /// Creates a history from a page of messages ordered from the newest
+    /// that contains an extra message if a previous page exists.
+    fn from_page(mut messages: Vec<Message>, limit: u32) -> Self {
+        let next = {
+            if messages.len() > limit as usize {
+                messages.truncate(limit as usize);
+                messages.last().map(|message| message.id)
+            } else {
+                None
+            }
+        };
+        messages.reverse();
+        Self { messages, next }
+    }
+}
+
+impl Interaction for FetchHistory {
+    type Output = History;
+}
+
+#[derive(Debug)]
+pub struct PostReply {
+    parent_id: MessageId,
+    /// Reply's author (must be a member of the channel)
+    user_id: UserId,
+    text: String,
+}
+
+impl Interaction for PostReply {
+    type Output = Message;
+}
+
+#[derive(Debug)]
+pub struct FetchThread {
+    message_id: MessageId,
+    /// Reader (must be a member of the channel)
+    user_id: UserId,
+    before: Option<MessageId>,
+    limit: u32,
+}
+
+/// A parent message with a page of replies.
+#[derive(Debug)]
+pub struct Thread {
+    pub parent: Message,
+    pub replies: History,
+}
+
+impl Interaction for FetchThread {
+    type Output = Thread;
+}
+
+#[derive(Debug)]
+pub struct FollowThread {
+    message_id: MessageId,
+    user_id: UserId,
+}
+
+impl Interaction for FollowThread {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct UnfollowThread {
+    message_id: MessageId,
+    user_id: UserId,
+}
+
+impl Interaction for UnfollowThread {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct RegisterLive {
+    user_id: UserId,
+    login: Login,
+    sender: LiveSender,
+}
+
+impl Interaction for RegisterLive {
+    type Output = ConnectionId;
+}
+
+#[derive(Debug)]
+pub struct UnregisterLive {
+    connection_id: ConnectionId,
+}
+
+impl Interaction for UnregisterLive {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct Subscribe {
+    connection_id: ConnectionId,
+    channel_id: ChannelId,
+    user_id: UserId,
+}
+
+impl Interaction for Subscribe {
+    type Output = ();
+}
+
+#[derive(Debug)]
+pub struct Unsubscribe {
+    connection_id: ConnectionId,
+    channel_id: ChannelId,
+}
+
+impl Interaction for Unsubscribe {
+    type Output = ();
+}
+
+#[async_trait]
+impl Actor for EngineActor {
+    type Interface = Engine;
+
+    fn generic_name() -> &'static str {
+        "Engine"
+    }
+
+    async fn initialize(&mut self, _address: Address<Self>) -> Result<(), Error> {
+        std::fs::create_dir_all(&self.data_dir)?;
+        let dba = Dba::open(&self.data_dir)?;
+        self.dba = Some(dba);
+        wait(|| self.dba().initialize())?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<CreateUser> for EngineActor {
+    async fn handle(&mut self, input: CreateUser) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Creating user: {}", input.username);
+            if !mentions::is_valid_username(&input.username) {
+                let err = EngineError::InvalidUsername(input.username);
+                return Err(Error::from(err));
+            }
+            if input.password.is_empty() {
+                return Err(Error::from(EngineError::EmptyPassword));
+            }
+            let user = self.insert_user(input.username, &input.password)?;
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::CreateUser,
+                &user.username,
+                None,
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<CreateInvite> for EngineActor {
+    async fn handle(&mut self, input: CreateInvite) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Creating invite: {} uses", input.uses);
+            check_lifetime(input.lifetime)?;
+            self.dba()
+                .create_invite(input.token_hash, input.uses, input.lifetime)?;
+            let details = match input.lifetime {
+                Some(lifetime) => format!("uses: {}, lifetime: {}s", input.uses, lifetime),
+                None => format!("uses: {}", input.uses),
+            };
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::CreateInvite,
+                "invite",
+                Some(&details),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<Register> for EngineActor {
+    async fn handle(&mut self, input: Register) -> Result<User, Error> {
+        wait(|| {
+            let creds = input.credentials;
+            log::trace!("Registering user: {}", creds.username);
+            if !mentions::is_valid_username(&creds.username) {
+                let err = EngineError::InvalidUsername(creds.username);
+                return Err(Error::from(err));
+            }
+            if creds.password.is_empty() {
+                return Err(Error::from(EngineError::EmptyPassword));
+            }
+            if optional(self.dba().get_user(creds.username.clone()))?.is_some() {
+                let err = EngineError::UsernameTaken(creds.username);
+                return Err(Error::from(err));
+            }
+            let hash = passwords::hash(&creds.password)?;
+            // A use is taken only if a user is created
+            let user = self
+                .dba()
+                .create_invited_user(creds.username, hash, input.token_hash)?
+                .ok_or(EngineError::InvalidInvite)?;
+            let initiator = Initiator::User(user.id);
+            self.audit(initiator, AuditAction::Register, &user.username, None)?;
+            Ok(user)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<UpdatePassword> for EngineActor {
+    async fn handle(&mut self, input: UpdatePassword) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Updating password: {}", input.username);
+            if input.password.is_empty() {
+                return Err(Error::from(EngineError::EmptyPassword));
+            }
+            let hash = passwords::hash(&input.password)?;
+            let user_id = self.dba().get_user(input.username)?.id;
+            let user = self.get_existing_user(user_id)?;
+            self.dba().set_password(user.id, hash)?;
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::UpdatePassword,
+                &user.username,
+                None,
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<FindUser> for EngineActor {
+    async fn handle(&mut self, input: FindUser) -> Result<Option<User>, Error> {
+        optional(wait(|| self.dba().get_user(input.username)))
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetProfile> for EngineActor {
+    async fn handle(&mut self, input: GetProfile) -> Result<Profile, Error> {
+        wait(|| {
+            let mut profile = self.dba().get_profile(input.user_id)?;
+            if !self.can_view_email(input.initiator, input.user_id)? {
+                profile.email = None;
+            }
+            Ok(profile)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<UpdateProfile> for EngineActor {
+    async fn handle(&mut self, input: UpdateProfile) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Updating profile: {:?}", input);
+            let profile = normalize_profile(input.profile)?;
+            self.dba().set_profile(input.user_id, &profile)?;
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            let mut recipients = vec![user.id];
+            for channel in self.dba().get_user_channels(user.id)? {
+                recipients.extend(self.dba().get_member_ids(channel.id)?);
+            }
+            recipients.sort_by_key(|id| u32::from(*id));
+            recipients.dedup();
+            let mut viewers = Vec::new();
+            let mut others = Vec::new();
+            for recipient in recipients {
+                if self.can_view_email(Initiator::User(recipient), user.id)? {
+                    viewers.push(recipient);
+                } else {
+                    others.push(recipient);
+                }
+            }
+            let update = ProfileUpdate::ProfileChanged {
+                username: user.username.clone(),
+                profile: profile.clone().into(),
+            };
+            let delta = Delta::ProfileUpdate(update);
+            self.registry.send_to_users(&viewers, delta);
+            let update = ProfileUpdate::ProfileChanged {
+                username: user.username,
+                profile: Profile {
+                    email: None,
+                    ..profile
+                }
+                .into(),
+            };
+            let delta = Delta::ProfileUpdate(update);
+            self.registry.send_to_users(&others, delta);
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetUsers> for EngineActor {
+    async fn handle(&mut self, _: GetUsers) -> Result<Vec<User>, Error> {
+        wait(|| self.dba().get_users()).map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<RenameUser> for EngineActor {
+    async fn handle(&mut self, input: RenameUser) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Renaming user: {:?}", input);
+            if !mentions::is_valid_username(&input.username) {
+                let err = EngineError::InvalidUsername(input.username);
+                return Err(Error::from(err));
+            }
+            if optional(self.dba().get_user(input.username.clone()))?.is_some() {
+                let err = EngineError::UsernameTaken(input.username);
+                return Err(Error::from(err));
+            }
+            let user = self.get_existing_user(input.user_id)?;
+            self.dba().rename_user(user.id, input.username.clone())?;
+            let details = format!("new username: {}", input.username);
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::RenameUser,
+                &user.username,
+                Some(&details),
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<SetDisabled> for EngineActor {
+    async fn handle(&mut self, input: SetDisabled) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Setting disabled flag: {:?}", input);
+            let user = self.get_existing_user(input.user_id)?;
+            self.dba().set_user_disabled(user.id, input.disabled)?;
+            let action = if input.disabled {
+                self.drop_sessions(user.id)?;
+                self.registry.disconnect_tokens(user.id);
+                AuditAction::DisableUser
+            } else {
+                AuditAction::EnableUser
+            };
+            self.audit(Initiator::Ctrl, action, &user.username, None)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<DeleteUser> for EngineActor {
+    async fn handle(&mut self, input: DeleteUser) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Deleting user: {:?}", input);
+            let user = self.get_existing_user(input.user_id)?;
+            let mut channels = Vec::new();
+            let mut directs = Vec::new();
+            let mut blocking = Vec::new();
+            for channel in self.dba().get_user_channels(user.id)? {
+                let members = self.dba().get_member_ids(channel.id)?;
+                if members.len() > 1 {
+                    channels.push((channel.id, members));
+                } else if channel.kind == ChannelKind::Direct {
+                    // Nobody else reads a direct channel of a single member
+                    directs.push(channel.channel);
+                } else {
+                    // Every channel must have at least one member
+                    blocking.push(channel.channel);
+                }
+            }
+            if !blocking.is_empty() {
+                let err = EngineError::OnlyMember { channels: blocking };
+                return Err(Error::from(err));
+            }
+            self.drop_sessions(user.id)?;
+            self.registry.disconnect_tokens(user.id);
+            for channel in directs {
+                self.dba().delete_channel(channel)?;
+            }
+            self.dba().delete_user(user.id)?;
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::DeleteUser,
+                &user.username,
+                None,
+            )?;
+            for (channel_id, members) in channels {
+                let update = MemberUpdate::MemberRemoved {
+                    channel: channel_id.into(),
+                    username: user.username.clone(),
+                };
+                let delta = Delta::MemberUpdate(update);
+                self.registry.send_to_channel(channel_id, &members, delta);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<Authenticate> for EngineActor {
+    async fn handle(&mut self, input: Authenticate) -> Result<Authentication, Error> {
+        wait(|| {
+            let creds = input.credentials;
+            log::trace!("Authenticating user: {}", creds.username);
+            let mut targets = vec![Target::Username(creds.username.clone())];
+            if let Some(address) = input.address {
+                targets.push(Target::Address(address));
+            }
+            if let Some(retry_after) = self.lockouts.check(&targets) {
+                return Ok(Authentication::Locked { retry_after });
+            }
+            if let Some(user) = self.verify_credentials(creds)? {
+                if user.disabled {
+                    Ok(Authentication::Disabled)
+                } else if self.enabled_totp(user.id)?.is_some() {
+                    // Failures are reset after the second factor only
+                    Ok(Authentication::SecondFactor(user))
+                } else {
+                    self.lockouts.reset(&targets);
+                    Ok(Authentication::Accepted(user))
+                }
+            } else {
+                self.lockouts.fail(&targets);
+                Ok(Authentication::Rejected)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<VerifySecondFactor> for EngineActor {
+    async fn handle(&mut self, input: VerifySecondFactor) -> Result<Authentication, Error> {
+        wait(|| {
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            log::trace!("Verifying second factor of user: {}", user.username);
+            let mut targets = vec![Target::Username(user.username.clone())];
+            if let Some(address) = input.address {
+                targets.push(Target::Address(address));
+            }
+            if let Some(retry_after) = self.lockouts.check(&targets) {
+                return Ok(Authentication::Locked { retry_after });
+            }
+            if user.deleted {
+                return Ok(Authentication::Rejected);
+            }
+            if user.disabled {
+                return Ok(Authentication::Disabled);
+            }
+            let valid = match self.enabled_totp(user.id)? {
+                Some(totp) => self.check_second_factor(user.id, &totp, &input.code)?,
+                // Disabled since the password was checked
+                None => true,
+            };
+            if valid {
+                self.lockouts.reset(&targets);
+                Ok(Authentication::Accepted(user))
+            } else {
+                self.lockouts.fail(&targets);
+                Ok(Authentication::Rejected)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<EnrollTotp> for EngineActor {
+    async fn handle(&mut self, input: EnrollTotp) -> Result<(String, String), Error> {
+        wait(|| {
+            log::trace!("Enrolling TOTP of user: {:?}", input.user_id);
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.check_password(&user, &input.password)?;
+            if self.enabled_totp(user.id)?.is_some() {
+                return Err(Error::from(EngineError::TotpEnabled));
+            }
+            let secret = totp::generate_secret();
+            self.dba().set_totp_secret(user.id, &secret)?;
+            let uri = totp::provisioning_uri(&secret, &user.username);
+            Ok((secret, uri))
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<ConfirmTotp> for EngineActor {
+    async fn handle(&mut self, input: ConfirmTotp) -> Result<Vec<Key>, Error> {
+        wait(|| {
+            log::trace!("Confirming TOTP of user: {:?}", input.user_id);
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.check_password(&user, &input.password)?;
+            let totp = optional(self.dba().get_totp(input.user_id))?
+                .ok_or(EngineError::TotpNotEnrolled)?;
+            if totp.enabled {
+                return Err(Error::from(EngineError::TotpEnabled));
+            }
+            let step = totp::verify(&totp.secret, &input.code, totp::current_step(), None)?
+                .ok_or(EngineError::InvalidCode)?;
+            self.dba().enable_totp(input.user_id)?;
+            self.dba().set_totp_step(input.user_id, step as i64)?;
+            let codes = totp::generate_recovery_codes();
+            for code in codes.iter() {
+                self.dba()
+                    .add_recovery_code(input.user_id, hash_key(code))?;
+            }
+            let initiator = Initiator::User(user.id);
+            self.audit(initiator, AuditAction::EnableTotp, &user.username, None)?;
+            Ok(codes)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<DisableTotp> for EngineActor {
+    async fn handle(&mut self, input: DisableTotp) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Disabling TOTP of user: {:?}", input.user_id);
+            let totp = self
+                .enabled_totp(input.user_id)?
+                .ok_or(EngineError::TotpNotEnrolled)?;
+            if !self.check_second_factor(input.user_id, &totp, &input.code)? {
+                return Err(Error::from(EngineError::InvalidCode));
+            }
+            self.dba().delete_totp(input.user_id)?;
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            let initiator = Initiator::User(user.id);
+            self.audit(initiator, AuditAction::DisableTotp, &user.username, None)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetLockouts> for EngineActor {
+    async fn handle(&mut self, _: GetLockouts) -> Result<Vec<Lockout>, Error> {
+        Ok(self.lockouts.locked())
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<ClearLockouts> for EngineActor {
+    async fn handle(&mut self, input: ClearLockouts) -> Result<(), Error> {
+        log::trace!("Clearing lockouts: {:?}", input);
+        let target = if input.targets.is_empty() {
+            self.lockouts.clear_all();
+            "all".to_string()
+        } else {
+            self.lockouts.reset(&input.targets);
+            let names: Vec<_> = input.targets.iter().map(Target::to_string).collect();
+            names.join(", ")
+        };
+        wait(|| self.audit(Initiator::Ctrl, AuditAction::ClearLockouts, &target, None))
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<CreateSession> for EngineActor {
+    async fn handle(&mut self, input: CreateSession) -> Result<Session, Error> {
+        wait(|| {
+            log::trace!("Creating session for: {:?}", input.user_id);
+            self.dba()
+                .create_session(input.user_id, input.key_hash.clone())?;
+            let session = self.dba().get_session(input.key_hash)?;
+            Ok(session)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<RestoreSession> for EngineActor {
+    async fn handle(&mut self, input: RestoreSession) -> Result<Option<Session>, Error> {
+        wait(|| {
+            let limits = self.session_limits.clone();
+            self.dba()
+                .delete_expired_sessions(limits.idle, limits.lifetime)?;
+            let session =
+                optional(self.dba().get_session(input.key_hash.clone()))?.filter(|session| {
+                    constant_time_eq(session.key_hash.as_bytes(), input.key_hash.as_bytes())
+                });
+            if let Some(session) = session.as_ref() {
+                self.dba().touch_session(session.id)?;
+            }
+            Ok(session)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<TouchSession> for EngineActor {
+    async fn handle(&mut self, input: TouchSession) -> Result<Option<Timestamp>, Error> {
+        wait(|| {
+            let limits = self.session_limits.clone();
+            self.dba()
+                .delete_expired_sessions(limits.idle, limits.lifetime)?;
+            self.dba().touch_session(input.session_id)?;
+            let session = optional(self.dba().get_session_by_id(input.session_id))?;
+            let expires_at = session.map(|session| {
+                let idle_at = session.last_seen + limits.idle;
+                let lifetime_at = session.created_at + limits.lifetime;
+                idle_at.min(lifetime_at)
+            });
+            Ok(expires_at)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetSessions> for EngineActor {
+    async fn handle(&mut self, input: GetSessions) -> Result<Vec<Session>, Error> {
+        wait(|| self.dba().get_user_sessions(input.user_id)).map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<RevokeSession> for EngineActor {
+    async fn handle(&mut self, input: RevokeSession) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Revoking session: {:?}", input);
+            if !self.dba().delete_session(input.session_id)? {
+                let err = EngineError::UnknownSession {
+                    session_id: input.session_id,
+                };
+                return Err(Error::from(err));
+            }
+            self.registry.disconnect_sessions(&[input.session_id]);
+            match input.initiator {
+                // Users revoke only their own sessions by logging out
+                Initiator::User(_) => Ok(()),
+                Initiator::Ctrl => {
+                    let target = format!("session {}", u32::from(input.session_id));
+                    self.audit(Initiator::Ctrl, AuditAction::RevokeSession, &target, None)
+                }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<RevokeSessions> for EngineActor {
+    async fn handle(&mut self, input: RevokeSessions) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Revoking sessions: {:?}", input);
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.drop_sessions(user.id)?;
+            self.audit(
+                Initiator::Ctrl,
+                AuditAction::RevokeSessions,
+                &user.username,
+                None,
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<CreateToken> for EngineActor {
+    async fn handle(&mut self, input: CreateToken) -> Result<ApiToken, Error> {
+        wait(|| {
+            log::trace!("Creating token for: {:?}", input.user_id);
+            let name = input.name.trim();
+            if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LEN {
+                let err = EngineError::InvalidTokenName(input.name.clone());
+                return Err(Error::from(err));
+            }
+            check_lifetime(input.lifetime)?;
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.dba().create_token(
+                user.id,
+                input.key_hash.clone(),
+                name,
+                input.scope,
+                input.lifetime,
+            )?;
+            let token = self.dba().get_token(input.key_hash)?;
+            let details = format!("{} ({})", token.name, token.scope);
+            self.audit(
+                input.initiator,
+                AuditAction::CreateToken,
+                &user.username,
+                Some(&details),
+            )?;
+            Ok(token)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<UseToken> for EngineActor {
+    async fn handle(&mut self, input: UseToken) -> Result<Option<ApiToken>, Error> {
+        wait(|| {
+            let token = optional(self.dba().get_token(input.key_hash))?;
+            if let Some(token) = token {
+                let user = self.dba().get_user_by_id(token.user_id)?;
+                if user.disabled {
+                    return Ok(None);
+                }
+                self.dba().touch_token(token.id)?;
+                Ok(Some(token))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetTokens> for EngineActor {
+    async fn handle(&mut self, input: GetTokens) -> Result<Vec<ApiToken>, Error> {
+        wait(|| self.dba().get_user_tokens(input.user_id)).map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<RevokeToken> for EngineActor {
+    async fn handle(&mut self, input: RevokeToken) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Revoking token: {:?}", input);
+            let token = optional(self.dba().get_token_by_id(input.token_id))?;
+            // Tokens of other users look like missing ones
+            let token = token.filter(|token| match input.initiator {
+                Initiator::Ctrl => true,
+                Initiator::User(user_id) => token.user_id == user_id,
+            });
+            if token.is_none() || !self.dba().delete_token(input.token_id)? {
+                let err = EngineError::UnknownToken {
+                    token_id: input.token_id,
+                };
+                return Err(Error::from(err));
+            }
+            self.registry.disconnect_token(input.token_id);
+            let target = format!("token {}", u32::from(input.token_id));
+            self.audit(input.initiator, AuditAction::RevokeToken, &target, None)
+        })
+    }
+}
+
+#[async_trait]
+impl InteractionHandler<GetAudit> for EngineActor {
+    async fn handle(&mut self, input: GetAudit) -> Result<Vec<AuditRecord>, Error> {
+        wait(|| self.dba().get_audit_records(&input.filter)).map_err(Error::from)
+    }
 }
 
-impl Interaction for CreateUser {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<DeleteChannel> for EngineActor {
+    async fn handle(&mut self, input: DeleteChannel) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Deleting channel: {:?}", input);
+            let channel = self.dba().get_channel(input.channel.clone())?;
+            if channel.kind == ChannelKind::Direct {
+                let err = EngineError::DirectChannel {
+                    channel_id: channel.id,
+                };
+                return Err(Error::from(err));
+            }
+            self.check(input.initiator, Permission::DeleteChannel(channel.id))?;
+            let members = self.dba().get_member_ids(channel.id)?;
+            self.dba().delete_channel(input.channel)?;
+            self.audit(
+                input.initiator,
+                AuditAction::DeleteChannel,
+                &channel.channel,
+                None,
+            )?;
+            let update = ChannelUpdate::ChannelDeleted {
+                channel: channel.id.into(),
+            };
+            let delta = Delta::ChannelUpdate(update);
+            self.registry.send_to_channel(channel.id, &members, delta);
+            for user_id in members {
+                self.registry.unsubscribe_user(user_id, channel.id);
+            }
+            Ok(())
+        })
+    }
 }
 
-#[derive(Debug)]
-struct UpdatePassword {
-    username: Username,
-    password: Password,
+#[async_trait]
+impl InteractionHandler<CreateChannel> for EngineActor {
+    async fn handle(&mut self, input: CreateChannel) -> Result<(), Error> {
+        // TODO: Use TRANSACTION here
+        wait(|| {
+            log::trace!("Creating channel: {:?}", input);
+            if input.channel.is_empty() || input.channel.starts_with(DIRECT_PREFIX) {
+                let err = EngineError::InvalidChannelName(input.channel);
+                return Err(Error::from(err));
+            }
+            self.check(input.initiator, Permission::CreateChannel)?;
+            self.dba()
+                .create_channel(input.channel.clone(), ChannelKind::Channel)?;
+            let channel = self.dba().get_channel(input.channel)?;
+            self.dba().add_member(channel.id, input.user_id)?;
+            self.dba()
+                .set_member_role(channel.id, input.user_id, MemberRole::Owner)?;
+            self.audit(
+                input.initiator,
+                AuditAction::CreateChannel,
+                &channel.channel,
+                None,
+            )?;
+            let update = ChannelUpdate::ChannelCreated {
+                channel: channel.into(),
+            };
+            let delta = Delta::ChannelUpdate(update);
+            self.registry.send_to_users(&[input.user_id], delta);
+            Ok(())
+        })
+    }
 }
 
-impl Interaction for UpdatePassword {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<GetChannels> for EngineActor {
+    async fn handle(&mut self, _: GetChannels) -> Result<Vec<Channel>, Error> {
+        wait(|| self.dba().get_channels()).map_err(Error::from)
+    }
 }
 
-#[derive(Debug)]
-struct FindUser {
-    username: Username,
+#[async_trait]
+impl InteractionHandler<GetUserChannels> for EngineActor {
+    async fn handle(&mut self, input: GetUserChannels) -> Result<Vec<Channel>, Error> {
+        wait(|| self.dba().get_user_channels(input.user_id)).map_err(Error::from)
+    }
 }
 
-impl Interaction for FindUser {
-    type Output = Option<User>;
+#[async_trait]
+impl InteractionHandler<OpenDirect> for EngineActor {
+    async fn handle(&mut self, mut input: OpenDirect) -> Result<Channel, Error> {
+        // TODO: Use TRANSACTION here
+        wait(|| {
+            log::trace!("Opening direct channel: {:?}", input);
+            input.user_ids.sort_by_key(|id| u32::from(*id));
+            input.user_ids.dedup();
+            let amount = input.user_ids.len();
+            if amount < 2 || amount > MAX_DIRECT_MEMBERS {
+                let err = EngineError::DirectMembers {
+                    max: MAX_DIRECT_MEMBERS,
+                };
+                return Err(Error::from(err));
+            }
+            // The name is unique for every set of users
+            let ids: Vec<_> = input
+                .user_ids
+                .iter()
+                .map(|id| u32::from(*id).to_string())
+                .collect();
+            let name = format!("{}{}", DIRECT_PREFIX, ids.join(","));
+            if let Some(mut channel) = optional(self.dba().get_channel(name.clone()))? {
+                channel.title = self.dba().get_direct_title(channel.id)?;
+                return Ok(channel);
+            }
+            self.dba()
+                .create_channel(name.clone(), ChannelKind::Direct)?;
+            let mut channel = self.dba().get_channel(name)?;
+            for user_id in input.user_ids.iter() {
+                self.dba().add_member(channel.id, *user_id)?;
+            }
+            channel.title = self.dba().get_direct_title(channel.id)?;
+            let update = ChannelUpdate::ChannelCreated {
+                channel: channel.clone().into(),
+            };
+            let delta = Delta::ChannelUpdate(update);
+            self.registry.send_to_users(&input.user_ids, delta);
+            Ok(channel)
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct CreateSession {
-    user_id: UserId,
-    key: Key,
+#[async_trait]
+impl InteractionHandler<FindChannel> for EngineActor {
+    async fn handle(&mut self, input: FindChannel) -> Result<Option<Channel>, Error> {
+        optional(wait(|| self.dba().get_channel(input.channel)))
+    }
 }
 
-impl Interaction for CreateSession {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<AddMember> for EngineActor {
+    async fn handle(&mut self, input: AddMember) -> Result<(), Error> {
+        // TODO: Use TRANSACTION here
+        wait(|| {
+            log::trace!("Adding member: {:?}", input);
+            let channel = optional(self.dba().get_channel_by_id(input.channel_id))?.ok_or(
+                EngineError::UnknownChannel {
+                    channel_id: input.channel_id,
+                },
+            )?;
+            if channel.kind == ChannelKind::Direct {
+                let err = EngineError::DirectChannel {
+                    channel_id: channel.id,
+                };
+                return Err(Error::from(err));
+            }
+            let permission = if input.initiator == Initiator::User(input.user_id) {
+                Permission::JoinChannel(channel.id)
+            } else {
+                Permission::AddMembers(channel.id)
+            };
+            self.check(input.initiator, permission)?;
+            if self.dba().is_member(channel.id, input.user_id)? {
+                let err = EngineError::AlreadyMember {
+                    channel_id: channel.id,
+                    user_id: input.user_id,
+                };
+                return Err(Error::from(err));
+            }
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.dba().add_member(channel.id, user.id)?;
+            let details = format!("channel: {}", channel.channel);
+            self.audit(
+                input.initiator,
+                AuditAction::AddMember,
+                &user.username,
+                Some(&details),
+            )?;
+            let members = self.dba().get_member_ids(channel.id)?;
+            let update = MemberUpdate::MemberAdded {
+                channel: channel.id.into(),
+                username: user.username,
+            };
+            let delta = Delta::MemberUpdate(update);
+            self.registry.send_to_channel(channel.id, &members, delta);
+            Ok(())
+        })
+    }
 }
 
-#[derive(Debug)]
-struct FindSession {
-    key: Key,
+#[async_trait]
+impl InteractionHandler<RemoveMember> for EngineActor {
+    async fn handle(&mut self, input: RemoveMember) -> Result<(), Error> {
+        // TODO: Use TRANSACTION here
+        wait(|| {
+            log::trace!("Removing member: {:?}", input);
+            let channel = optional(self.dba().get_channel_by_id(input.channel_id))?.ok_or(
+                EngineError::UnknownChannel {
+                    channel_id: input.channel_id,
+                },
+            )?;
+            if channel.kind == ChannelKind::Direct {
+                let err = EngineError::DirectChannel {
+                    channel_id: channel.id,
+                };
+                return Err(Error::from(err));
+            }
+            if input.initiator != Initiator::User(input.user_id) {
+                self.check(input.initiator, Permission::RemoveMembers(channel.id))?;
+            }
+            let members = self.dba().get_member_ids(input.channel_id)?;
+            if !members.contains(&input.user_id) {
+                let err = EngineError::NotMember {
+                    channel_id: input.channel_id,
+                    user_id: input.user_id,
+                };
+                return Err(Error::from(err));
+            }
+            // Every channel must have at least one member
+            if members.len() == 1 {
+                let err = EngineError::LastMember {
+                    channel_id: input.channel_id,
+                };
+                return Err(Error::from(err));
+            }
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.dba().remove_member(input.channel_id, user.id)?;
+            let details = format!("channel: {}", channel.channel);
+            self.audit(
+                input.initiator,
+                AuditAction::RemoveMember,
+                &user.username,
+                Some(&details),
+            )?;
+            // The removed member is notified as well
+            let update = MemberUpdate::MemberRemoved {
+                channel: input.channel_id.into(),
+                username: user.username,
+            };
+            let delta = Delta::MemberUpdate(update);
+            self.registry
+                .send_to_channel(input.channel_id, &members, delta);
+            self.registry.unsubscribe_user(user.id, input.channel_id);
+            Ok(())
+        })
+    }
 }
 
-impl Interaction for FindSession {
-    type Output = Option<Session>;
+#[async_trait]
+impl InteractionHandler<SetUserRole> for EngineActor {
+    async fn handle(&mut self, input: SetUserRole) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Setting user role: {:?}", input);
+            self.check(input.initiator, Permission::ManageRoles)?;
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            self.dba().set_user_role(user.id, input.role)?;
+            let details = format!("role: {}", input.role);
+            self.audit(
+                input.initiator,
+                AuditAction::SetUserRole,
+                &user.username,
+                Some(&details),
+            )
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct CreateChannel {
-    channel: ChannelName,
-    /// Channel's creator (first member/owner)
-    user_id: UserId,
+#[async_trait]
+impl InteractionHandler<SetMemberRole> for EngineActor {
+    async fn handle(&mut self, input: SetMemberRole) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Setting member role: {:?}", input);
+            self.check(input.initiator, Permission::ManageMembers(input.channel_id))?;
+            self.ensure_member(input.channel_id, input.user_id)?;
+            self.dba()
+                .set_member_role(input.channel_id, input.user_id, input.role)?;
+            let user = self.dba().get_user_by_id(input.user_id)?;
+            let channel = self.dba().get_channel_by_id(input.channel_id)?;
+            let details = format!("channel: {}, role: {}", channel.channel, input.role);
+            self.audit(
+                input.initiator,
+                AuditAction::SetMemberRole,
+                &user.username,
+                Some(&details),
+            )
+        })
+    }
 }
 
-impl Interaction for CreateChannel {
-    type Output = (); // TODO: Return channel info? At least channel Id.
+#[async_trait]
+impl InteractionHandler<GetMembers> for EngineActor {
+    async fn handle(&mut self, input: GetMembers) -> Result<Vec<User>, Error> {
+        wait(|| self.dba().get_members(input.channel_id)).map_err(Error::from)
+    }
 }
 
-#[derive(Debug)]
-pub struct GetChannels {
-    // TODO: Add user's filter
+#[async_trait]
+impl InteractionHandler<IsMember> for EngineActor {
+    async fn handle(&mut self, input: IsMember) -> Result<bool, Error> {
+        wait(|| self.dba().is_member(input.channel_id, input.user_id)).map_err(Error::from)
+    }
 }
 
-impl Interaction for GetChannels {
-    type Output = Vec<Channel>;
+#[async_trait]
+impl InteractionHandler<PostMessage> for EngineActor {
+    async fn handle(&mut self, input: PostMessage) -> Result<Message, Error> {
+        wait(|| {
+            log::trace!("Posting message: {:?}", input);
+            self.ensure_member(input.channel_id, input.user_id)?;
+            let id = self
+                .dba()
+                .create_message(input.channel_id, input.user_id, input.text)?;
+            let message = self.dba().get_message(id)?;
+            let members = self.dba().get_member_ids(input.channel_id)?;
+            let update = MessageUpdate::MessageAdded {
+                message: message.clone().into(),
+            };
+            let delta = Delta::MessageUpdate(update);
+            self.registry
+                .send_to_channel(input.channel_id, &members, delta);
+            self.send_mentions(&message)?;
+            Ok(message)
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct AddMember {
-    channel_id: ChannelId,
-    user_id: UserId,
+#[async_trait]
+impl InteractionHandler<EditMessage> for EngineActor {
+    async fn handle(&mut self, input: EditMessage) -> Result<Message, Error> {
+        wait(|| {
+            log::trace!("Editing message: {:?}", input);
+            let message = self.editable_message(input.message_id, input.user_id)?;
+            self.dba()
+                .edit_message(message.id, input.user_id, input.text)?;
+            let message = self.dba().get_message(message.id)?;
+            let members = self.dba().get_member_ids(message.channel_id)?;
+            let update = MessageUpdate::MessageEdited {
+                message: message.clone().into(),
+            };
+            let delta = Delta::MessageUpdate(update);
+            self.registry
+                .send_to_channel(message.channel_id, &members, delta);
+            self.send_mentions(&message)?;
+            Ok(message)
+        })
+    }
 }
 
-impl Interaction for AddMember {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<DeleteMessage> for EngineActor {
+    async fn handle(&mut self, input: DeleteMessage) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Deleting message: {:?}", input);
+            let message = self.editable_message(input.message_id, input.user_id)?;
+            self.dba().delete_message(message.id)?;
+            let members = self.dba().get_member_ids(message.channel_id)?;
+            let update = MessageUpdate::MessageDeleted {
+                channel: message.channel_id.into(),
+                message: message.id.into(),
+            };
+            let delta = Delta::MessageUpdate(update);
+            self.registry
+                .send_to_channel(message.channel_id, &members, delta);
+            let parent = match message.parent_id {
+                Some(parent_id) => optional(self.dba().get_message(parent_id))?,
+                None => None,
+            };
+            if let Some(parent) = parent {
+                // Replaces the parent to update counters of the thread
+                let update = MessageUpdate::MessageEdited {
+                    message: parent.into(),
+                };
+                let delta = Delta::MessageUpdate(update);
+                self.registry
+                    .send_to_channel(message.channel_id, &members, delta);
+            }
+            Ok(())
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct RemoveMember {
-    channel_id: ChannelId,
-    user_id: UserId,
+#[async_trait]
+impl InteractionHandler<GetRevisions> for EngineActor {
+    async fn handle(&mut self, input: GetRevisions) -> Result<Vec<Revision>, Error> {
+        wait(|| {
+            let message = self.existing_message(input.message_id)?;
+            self.ensure_member(message.channel_id, input.user_id)?;
+            let revisions = self.dba().get_revisions(message.id)?;
+            Ok(revisions)
+        })
+    }
 }
 
-impl Interaction for RemoveMember {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<AddEmoji> for EngineActor {
+    async fn handle(&mut self, input: AddEmoji) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Adding emoji: {:?}", input);
+            if input.emoji.is_empty() || input.emoji.len() > MAX_EMOJI_LEN {
+                let err = EngineError::InvalidEmoji(input.emoji);
+                return Err(Error::from(err));
+            }
+            let message = self.existing_message(input.message_id)?;
+            self.ensure_member(message.channel_id, input.user_id)?;
+            self.dba()
+                .add_emoji(message.id, input.user_id, input.emoji)?;
+            self.send_reactions(&message)
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct DeleteChannel {
-    channel: ChannelName,
+#[async_trait]
+impl InteractionHandler<RemoveEmoji> for EngineActor {
+    async fn handle(&mut self, input: RemoveEmoji) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Removing emoji: {:?}", input);
+            let message = self.existing_message(input.message_id)?;
+            self.ensure_member(message.channel_id, input.user_id)?;
+            self.dba()
+                .remove_emoji(message.id, input.user_id, input.emoji)?;
+            self.send_reactions(&message)
+        })
+    }
 }
 
-impl Interaction for DeleteChannel {
-    type Output = ();
+#[async_trait]
+impl InteractionHandler<FetchHistory> for EngineActor {
+    async fn handle(&mut self, input: FetchHistory) -> Result<History, Error> {
+        wait(|| {
+            log::trace!("Fetching history: {:?}", input);
+            self.ensure_member(input.channel_id, input.user_id)?;
+            let limit = input.limit.max(1).min(MAX_HISTORY_LIMIT);
+            // Takes an extra message to know is there a previous page
+            let messages = self
+                .dba()
+                .get_messages(input.channel_id, input.before, limit + 1)?;
+            Ok(History::from_page(messages, limit))
+        })
+    }
 }
 
 #[async_trait]
-impl Actor for EngineActor {
-    type Interface = Engine;
-
-    fn generic_name() -> &'static str {
-        "Engine"
+impl InteractionHandler<MarkRead> for EngineActor {
+    async fn handle(&mut self, input: MarkRead) -> Result<(), Error> {
+        wait(|| {
+            log::trace!("Marking as read: {:?}", input);
+            self.ensure_member(input.channel_id, input.user_id)?;
+            let message = self.existing_message(input.message_id)?;
+            if message.channel_id != input.channel_id {
+                let err = EngineError::UnknownMessage {
+                    message_id: message.id,
+                };
+                return Err(Error::from(err));
+            }
+            self.dba()
+                .mark_read(input.channel_id, input.user_id, message.id)?;
+            let state = self.dba().get_read_state(input.channel_id, input.user_id)?;
+            // Syncs the state with other devices of the user
+            let update = ChannelUpdate::ReadUpdated {
+                channel: input.channel_id.into(),
+                last_read: state.last_read.map(Into::into),
+                unread: state.unread,
+                mentions: state.mentions,
+            };
+            let delta = Delta::ChannelUpdate(update);
+            self.registry.send_to_users(&[input.user_id], delta);
+            Ok(())
+        })
     }
+}
 
-    async fn initialize(&mut self, _address: Address<Self>) -> Result<(), Error> {
-        std::fs::create_dir_all(crate::db::DATA_DIR)?;
-        let dba = Dba::open()?;
-        self.dba = Some(dba);
-        wait(|| self.dba().initialize())?;
-        Ok(())
+#[async_trait]
+impl InteractionHandler<PostReply> for EngineActor {
+    async fn handle(&mut self, input: PostReply) -> Result<Message, Error> {
+        wait(|| {
+            log::trace!("Posting reply: {:?}", input);
+            let parent = self.thread_parent(input.parent_id)?;
+            self.ensure_member(parent.channel_id, input.user_id)?;
+            let id = self
+                .dba()
+                .create_reply(parent.id, input.user_id, input.text)?;
+            // The author follows the thread since the first reply
+            if parent.reply_count == 0 {
+                self.dba().follow_thread(parent.id, parent.author_id)?;
+            }
+            self.dba().follow_thread(parent.id, input.user_id)?;
+            let reply = self.dba().get_message(id)?;
+            let parent = self.dba().get_message(parent.id)?;
+            let followers = self.thread_followers(&parent)?;
+            let update = ThreadUpdate::ReplyAdded {
+                parent: parent.into(),
+                reply: reply.clone().into(),
+            };
+            let delta = Delta::ThreadUpdate(update);
+            self.registry.send_to_users(&followers, delta);
+            self.send_mentions(&reply)?;
+            Ok(reply)
+        })
     }
 }
 
 #[async_trait]
-impl InteractionHandler<CreateUser> for EngineActor {
-    async fn handle(&mut self, input: CreateUser) -> Result<(), Error> {
-        wait(|| -> Result<(), DbaError> {
-            log::trace!("Creating user: {:?}", input);
-            // TODO: User RETURNING id possible?
-            self.dba().create_user(input.username.clone())?;
-            let user = self.dba().get_user(input.username)?;
-            self.dba().set_password(user.id, input.password)?;
-            Ok(())
+impl InteractionHandler<FetchThread> for EngineActor {
+    async fn handle(&mut self, input: FetchThread) -> Result<Thread, Error> {
+        wait(|| {
+            log::trace!("Fetching thread: {:?}", input);
+            let parent = self.thread_parent(input.message_id)?;
+            self.ensure_member(parent.channel_id, input.user_id)?;
+            let limit = input.limit.max(1).min(MAX_HISTORY_LIMIT);
+            // Takes an extra message to know is there a previous page
+            let messages = self.dba().get_replies(parent.id, input.before, limit + 1)?;
+            let replies = History::from_page(messages, limit);
+            Ok(Thread { parent, replies })
         })
-        .map_err(Error::from)
     }
 }
 
 #[async_trait]
-impl InteractionHandler<UpdatePassword> for EngineActor {
-    async fn handle(&mut self, input: UpdatePassword) -> Result<(), Error> {
-        // TODO: Protect password
-        wait(|| -> Result<(), DbaError> {
-            log::trace!("Updating password: {:?}", input);
-            let user = self.dba().get_user(input.username)?;
-            self.dba().set_password(user.id, input.password)?;
+impl InteractionHandler<FollowThread> for EngineActor {
+    async fn handle(&mut self, input: FollowThread) -> Result<(), Error> {
+        wait(|| {
+            let parent = self.thread_parent(input.message_id)?;
+            self.ensure_member(parent.channel_id, input.user_id)?;
+            self.dba().follow_thread(parent.id, input.user_id)?;
             Ok(())
         })
-        .map_err(Error::from)
     }
 }
 
 #[async_trait]
-impl InteractionHandler<FindUser> for EngineActor {
-    async fn handle(&mut self, input: FindUser) -> Result<Option<User>, Error> {
-        optional(wait(|| self.dba().get_user(input.username)))
+impl InteractionHandler<UnfollowThread> for EngineActor {
+    async fn handle(&mut self, input: UnfollowThread) -> Result<(), Error> {
+        wait(|| self.dba().unfollow_thread(input.message_id, input.user_id)).map_err(Error::from)
     }
 }
 
 #[async_trait]
-impl InteractionHandler<CreateSession> for EngineActor {
-    async fn handle(&mut self, input: CreateSession) -> Result<(), Error> {
-        wait(|| self.dba().create_session(input.user_id, input.key)).map_err(Error::from)
+impl InteractionHandler<RegisterLive> for EngineActor {
+    async fn handle(&mut self, input: RegisterLive) -> Result<ConnectionId, Error> {
+        let connection_id = self
+            .registry
+            .register(input.user_id, input.login, input.sender);
+        Ok(connection_id)
     }
 }
 
 #[async_trait]
-impl InteractionHandler<FindSession> for EngineActor {
-    async fn handle(&mut self, input: FindSession) -> Result<Option<Session>, Error> {
-        optional(wait(|| self.dba().get_session(input.key)))
+impl InteractionHandler<UnregisterLive> for EngineActor {
+    async fn handle(&mut self, input: UnregisterLive) -> Result<(), Error> {
+        self.registry.unregister(input.connection_id);
+        Ok(())
     }
 }
 
 #[async_trait]
-impl InteractionHandler<DeleteChannel> for EngineActor {
-    async fn handle(&mut self, input: DeleteChannel) -> Result<(), Error> {
-        wait(|| self.dba().delete_channel(input.channel)).map_err(Error::from)
+impl InteractionHandler<Subscribe> for EngineActor {
+    async fn handle(&mut self, input: Subscribe) -> Result<(), Error> {
+        wait(|| self.ensure_member(input.channel_id, input.user_id))?;
+        self.registry
+            .subscribe(input.connection_id, input.channel_id);
+        Ok(())
     }
 }
 
 #[async_trait]
-impl InteractionHandler<CreateChannel> for EngineActor {
-    async fn handle(&mut self, input: CreateChannel) -> Result<(), Error> {
-        // TODO: Use TRANSACTION here
-        wait(|| {
-            log::trace!("Creating channel: {:?}", input);
-            self.dba().create_channel(input.channel.clone())?;
-            let channel = self.dba().get_channel(input.channel)?;
-            self.dba().add_member(channel.id, input.user_id)?;
-            Ok(())
-        })
+impl InteractionHandler<Unsubscribe> for EngineActor {
+    async fn handle(&mut self, input: Unsubscribe) -> Result<(), Error> {
+        self.registry
+            .unsubscribe(input.connection_id, input.channel_id);
+        Ok(())
     }
 }
 
-#[async_trait]
-impl InteractionHandler<GetChannels> for EngineActor {
-    async fn handle(&mut self, _: GetChannels) -> Result<Vec<Channel>, Error> {
-        wait(|| self.dba().get_channels()).map_err(Error::from)
+/// Checks a lifetime of an invite or an API token.
+fn check_lifetime(lifetime: Option<i64>) -> Result<(), Error> {
+    match lifetime {
+        Some(lifetime) if lifetime <= 0 || lifetime > MAX_LIFETIME => {
+            let err = EngineError::InvalidLifetime {
+                lifetime,
+                max: MAX_LIFETIME,
+            };
+            Err(Error::from(err))
+        }
+        _ => Ok(()),
     }
 }
 
@@ -294,9 +2587,491 @@ fn optional<T>(res: Result<T, DbaError>) -> Result<Option<T>, Error> {
     }
 }
 
+/// Trims fields of a profile, turns empty fields into `None`
+/// and checks the rest.
+fn normalize_profile(profile: Profile) -> Result<Profile, Error> {
+    fn field(
+        value: Option<String>,
+        field: &'static str,
+        valid: impl Fn(&str) -> bool,
+    ) -> Result<Option<String>, Error> {
+        let value = value
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        match value {
+            Some(value) if !valid(&value) => {
+                Err(Error::from(EngineError::InvalidProfile { field }))
+            }
+            value => Ok(value),
+        }
+    }
+    let display_name = field(profile.display_name, "display name", |value| {
+        value.chars().count() <= MAX_DISPLAY_NAME_LEN && !value.chars().any(char::is_control)
+    })?;
+    let email = field(profile.email, "email", |value| {
+        let mut parts = value.splitn(2, '@');
+        let local = parts.next().unwrap_or_default();
+        let domain = parts.next().unwrap_or_default();
+        value.len() <= MAX_PROFILE_FIELD_LEN
+            && !local.is_empty()
+            && domain.contains('.')
+            && !value.chars().any(char::is_whitespace)
+    })?;
+    let timezone = field(profile.timezone, "timezone", |value| {
+        value.len() <= MAX_PROFILE_FIELD_LEN
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "/_+-".contains(c))
+    })?;
+    let status = field(profile.status, "status", |value| {
+        value.chars().count() <= MAX_STATUS_LEN && !value.chars().any(char::is_control)
+    })?;
+    let avatar = field(profile.avatar, "avatar", |value| {
+        value.len() <= MAX_PROFILE_FIELD_LEN
+            && (value.starts_with("https://") || value.starts_with("http://"))
+            && !value.chars().any(char::is_whitespace)
+    })?;
+    Ok(Profile {
+        display_name,
+        email,
+        timezone,
+        status,
+        avatar,
+    })
+}
+
 /// EngineActor routines.
 impl EngineActor {
     fn dba(&mut self) -> &mut Dba {
         self.dba.as_mut().expect("DBA lost")
     }
+
+    fn insert_user(&mut self, username: Username, password: &str) -> Result<User, Error> {
+        let hash = passwords::hash(password)?;
+        // TODO: User RETURNING id possible?
+        self.dba().create_user(username.clone())?;
+        let user = self.dba().get_user(username)?;
+        self.dba().set_password(user.id, hash)?;
+        Ok(user)
+    }
+
+    /// Returns a user that wasn't deleted.
+    fn get_existing_user(&mut self, user_id: UserId) -> Result<User, Error> {
+        let user = self.dba().get_user_by_id(user_id)?;
+        if user.deleted {
+            let err = EngineError::DeletedUser { user_id };
+            return Err(Error::from(err));
+        }
+        Ok(user)
+    }
+
+    /// Appends a record about an administrative change to the audit log.
+    fn audit(
+        &mut self,
+        initiator: Initiator,
+        action: AuditAction,
+        target: &str,
+        details: Option<&str>,
+    ) -> Result<(), Error> {
+        let (actor_id, actor) = match initiator {
+            Initiator::Ctrl => (None, AuditRecord::CTRL_ACTOR.to_string()),
+            Initiator::User(user_id) => {
+                let username = self.dba().get_user_by_id(user_id)?.username;
+                (Some(user_id), username)
+            }
+        };
+        self.dba()
+            .add_audit_record(actor_id, &actor, action, target, details)?;
+        Ok(())
+    }
+
+    /// Deletes all sessions of a user and disconnects its live connections.
+    fn drop_sessions(&mut self, user_id: UserId) -> Result<(), Error> {
+        let session_ids: Vec<_> = self
+            .dba()
+            .get_user_sessions(user_id)?
+            .into_iter()
+            .map(|session| session.id)
+            .collect();
+        for session_id in session_ids.iter() {
+            self.dba().delete_session(*session_id)?;
+        }
+        self.registry.disconnect_sessions(&session_ids);
+        Ok(())
+    }
+
+    /// Returns the second factor of a user if it's enrolled and confirmed.
+    fn enabled_totp(&mut self, user_id: UserId) -> Result<Option<Totp>, Error> {
+        let totp = optional(self.dba().get_totp(user_id))?;
+        Ok(totp.filter(|totp| totp.enabled))
+    }
+
+    /// Checks a one-time password or takes a recovery code.
+    fn check_second_factor(
+        &mut self,
+        user_id: UserId,
+        totp: &Totp,
+        code: &str,
+    ) -> Result<bool, Error> {
+        let last_step = totp.last_step.map(|step| step as u64);
+        let step = totp::verify(&totp.secret, code, totp::current_step(), last_step)?;
+        if let Some(step) = step {
+            self.dba().set_totp_step(user_id, step as i64)?;
+            Ok(true)
+        } else {
+            let used = self
+                .dba()
+                .use_recovery_code(user_id, hash_key(code.trim()))?;
+            Ok(used)
+        }
+    }
+
+    fn verify_credentials(&mut self, creds: Credentials) -> Result<Option<User>, Error> {
+        let user = optional(self.dba().get_user(creds.username))?;
+        match user {
+            Some(user) if !user.deleted => {
+                match passwords::verify(&user.password, &creds.password)? {
+                    Verification::Valid => Ok(Some(user)),
+                    Verification::Outdated => {
+                        log::debug!("Rehashing password of user {:?}", user.id);
+                        let hash = passwords::hash(&creds.password)?;
+                        self.dba().set_password(user.id, hash)?;
+                        Ok(Some(user))
+                    }
+                    Verification::Invalid => Ok(None),
+                }
+            }
+            _ => {
+                // Takes the same time as for an existing user
+                passwords::hash(&creds.password)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Emails are visible to their users and to admins.
+    fn can_view_email(&mut self, initiator: Initiator, user_id: UserId) -> Result<bool, Error> {
+        match initiator {
+            Initiator::Ctrl => Ok(true),
+            Initiator::User(viewer_id) if viewer_id == user_id => Ok(true),
+            Initiator::User(viewer_id) => {
+                let role = self.dba().get_user_by_id(viewer_id)?.role;
+                Ok(Permission::ViewEmails.allowed(role, None))
+            }
+        }
+    }
+
+    /// Returns `InvalidPassword` if the password of the user doesn't match.
+    fn check_password(&mut self, user: &User, password: &str) -> Result<(), Error> {
+        match passwords::verify(&user.password, password)? {
+            Verification::Valid | Verification::Outdated => Ok(()),
+            Verification::Invalid => Err(Error::from(EngineError::InvalidPassword)),
+        }
+    }
+
+    /// Returns `PermissionDenied` if the initiator doesn't have the permission.
+    fn check(&mut self, initiator: Initiator, permission: Permission) -> Result<(), Error> {
+        let user_id = match initiator {
+            Initiator::Ctrl => return Ok(()),
+            Initiator::User(user_id) => user_id,
+        };
+        let role = self.dba().get_user_by_id(user_id)?.role;
+        let member = match permission.channel_id() {
+            Some(channel_id) => optional(self.dba().get_member_role(channel_id, user_id))?,
+            None => None,
+        };
+        if permission.allowed(role, member) {
+            Ok(())
+        } else {
+            let err = EngineError::PermissionDenied {
+                user_id,
+                permission,
+            };
+            Err(Error::from(err))
+        }
+    }
+
+    fn ensure_member(&mut self, channel_id: ChannelId, user_id: UserId) -> Result<(), Error> {
+        if self.dba().is_member(channel_id, user_id)? {
+            Ok(())
+        } else {
+            let err = EngineError::NotMember {
+                channel_id,
+                user_id,
+            };
+            Err(Error::from(err))
+        }
+    }
+
+    /// Returns a message that can have a thread.
+    fn thread_parent(&mut self, message_id: MessageId) -> Result<Message, Error> {
+        let message = self.existing_message(message_id)?;
+        if message.parent_id.is_some() {
+            let err = EngineError::NestedThread { message_id };
+            return Err(Error::from(err));
+        }
+        Ok(message)
+    }
+
+    /// Returns followers of a thread that are still members of the channel.
+    fn thread_followers(&mut self, parent: &Message) -> Result<Vec<UserId>, Error> {
+        let members = self.dba().get_member_ids(parent.channel_id)?;
+        let followers = self
+            .dba()
+            .get_follower_ids(parent.id)?
+            .into_iter()
+            .filter(|user_id| members.contains(user_id))
+            .collect();
+        Ok(followers)
+    }
+
+    /// Sends actual reactions of a message to the channel.
+    fn send_reactions(&mut self, message: &Message) -> Result<(), Error> {
+        let reactions = self.dba().get_emoji_counts(message.id)?;
+        let members = self.dba().get_member_ids(message.channel_id)?;
+        let update = MessageUpdate::ReactionsChanged {
+            channel: message.channel_id.into(),
+            message: message.id.into(),
+            reactions: reactions.into_iter().map(Into::into).collect(),
+        };
+        let delta = Delta::MessageUpdate(update);
+        self.registry
+            .send_to_channel(message.channel_id, &members, delta);
+        Ok(())
+    }
+
+    /// Stores mentions of a message and notifies mentioned members
+    /// even if they don't watch the channel. Members mentioned before
+    /// an edit are not notified again.
+    fn send_mentions(&mut self, message: &Message) -> Result<(), Error> {
+        let mentions = mentions::parse(&message.body);
+        let members = self.dba().get_members(message.channel_id)?;
+        let notified = self.dba().get_mentioned_ids(message.id)?;
+        let mut mentioned = Vec::new();
+        for member in members {
+            if member.id == message.author_id || notified.contains(&member.id) {
+                continue;
+            }
+            let matched = mentions.channel
+                || (mentions.here && self.registry.is_online(member.id))
+                || mentions.usernames.contains(&member.username);
+            if matched {
+                self.dba().add_mention(message.id, member.id)?;
+                mentioned.push(member.id);
+            }
+        }
+        if !mentioned.is_empty() {
+            let update = MentionUpdate::Mentioned {
+                message: message.clone().into(),
+            };
+            let delta = Delta::MentionUpdate(update);
+            self.registry.send_to_users(&mentioned, delta);
+        }
+        Ok(())
+    }
+
+    fn existing_message(&mut self, message_id: MessageId) -> Result<Message, Error> {
+        optional(self.dba().get_message(message_id))?
+            .ok_or_else(|| EngineError::UnknownMessage { message_id }.into())
+    }
+
+    /// Returns a message if the user moderates its channel
+    /// or is its author and a member of its channel.
+    fn editable_message(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<Message, Error> {
+        let message = self.existing_message(message_id)?;
+        let permission = Permission::ModerateMessages(message.channel_id);
+        let denied = match self.check(Initiator::User(user_id), permission) {
+            // Admins moderate channels they are not members of
+            Ok(()) => return Ok(message),
+            Err(err) => err,
+        };
+        self.ensure_member(message.channel_id, user_id)?;
+        if message.author_id != user_id {
+            return Err(denied);
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test(threaded_scheduler)]
+    async fn subscribe_members_only() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 60,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        engine.create_user("alice".into(), "secret".into()).await?;
+        engine.create_user("bob".into(), "secret".into()).await?;
+        let alice = engine.find_user("alice".into()).await?.expect("no alice");
+        let bob = engine.find_user("bob".into()).await?.expect("no bob");
+        let channel = "general".to_string();
+        engine
+            .create_channel(channel.clone(), alice.id, Initiator::Ctrl)
+            .await?;
+        let channel_id = engine.find_channel(channel).await?.expect("no channel").id;
+
+        let (_, session) = engine.create_session(bob.id).await?;
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let login = Login::Session(session.id);
+        let connection_id = engine.register_live(bob.id, login, sender).await?;
+        let res = engine.subscribe(connection_id, channel_id, bob.id).await;
+        assert!(res.is_err());
+
+        engine
+            .add_member(channel_id, bob.id, Initiator::Ctrl)
+            .await?;
+        engine.subscribe(connection_id, channel_id, bob.id).await?;
+        Ok(())
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn removed_member_unsubscribed() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 60,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        engine.create_user("alice".into(), "secret".into()).await?;
+        engine.create_user("bob".into(), "secret".into()).await?;
+        let alice = engine.find_user("alice".into()).await?.expect("no alice");
+        let bob = engine.find_user("bob".into()).await?.expect("no bob");
+        let channel = "general".to_string();
+        engine
+            .create_channel(channel.clone(), alice.id, Initiator::Ctrl)
+            .await?;
+        let channel_id = engine.find_channel(channel).await?.expect("no channel").id;
+        engine
+            .add_member(channel_id, bob.id, Initiator::Ctrl)
+            .await?;
+
+        let (_, session) = engine.create_session(bob.id).await?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let login = Login::Session(session.id);
+        let connection_id = engine.register_live(bob.id, login, sender).await?;
+        engine.subscribe(connection_id, channel_id, bob.id).await?;
+        engine
+            .remove_member(channel_id, bob.id, Initiator::Ctrl)
+            .await?;
+        while receiver.try_recv().is_ok() {}
+
+        engine
+            .post_message(channel_id, alice.id, "hello".into())
+            .await?;
+        while let Ok(delta) = receiver.try_recv() {
+            let added = matches!(
+                delta,
+                Delta::MessageUpdate(MessageUpdate::MessageAdded { .. })
+            );
+            assert!(!added, "removed member got a message");
+        }
+        Ok(())
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn touch_session_deadline() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 3600,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        engine.create_user("alice".into(), "secret".into()).await?;
+        let alice = engine.find_user("alice".into()).await?.expect("no alice");
+        let (_, session) = engine.create_session(alice.id).await?;
+        let expires_at = engine.touch_session(session.id).await?.expect("no session");
+        assert!(expires_at > session.created_at);
+        assert!(expires_at < session.created_at + 3600);
+
+        engine.revoke_session(session.id, Initiator::Ctrl).await?;
+        assert_eq!(engine.touch_session(session.id).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn moderators_edit_messages() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 60,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        engine.create_user("alice".into(), "secret".into()).await?;
+        engine.create_user("bob".into(), "secret".into()).await?;
+        engine.create_user("carol".into(), "secret".into()).await?;
+        let alice = engine.find_user("alice".into()).await?.expect("no alice");
+        let bob = engine.find_user("bob".into()).await?.expect("no bob");
+        let carol = engine.find_user("carol".into()).await?.expect("no carol");
+        let channel = "general".to_string();
+        engine
+            .create_channel(channel.clone(), alice.id, Initiator::Ctrl)
+            .await?;
+        let channel_id = engine.find_channel(channel).await?.expect("no channel").id;
+        engine
+            .add_member(channel_id, bob.id, Initiator::Ctrl)
+            .await?;
+        let message = engine
+            .post_message(channel_id, alice.id, "hello".into())
+            .await?;
+
+        // Members can't edit messages of others
+        let res = engine
+            .edit_message(message.id, bob.id, "edited".into())
+            .await;
+        assert!(res.is_err());
+
+        // Admins don't have to be members of a channel
+        engine
+            .set_user_role(carol.id, UserRole::Admin, Initiator::Ctrl)
+            .await?;
+        engine
+            .edit_message(message.id, carol.id, "edited".into())
+            .await?;
+        let revisions = engine.get_revisions(message.id, alice.id).await?;
+        assert_eq!(revisions[0].editor, "carol");
+        engine.delete_message(message.id, carol.id).await?;
+        Ok(())
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn delete_only_member() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 60,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        engine.create_user("alice".into(), "secret".into()).await?;
+        let alice = engine.find_user("alice".into()).await?.expect("no alice");
+        let channel = "general".to_string();
+        engine
+            .create_channel(channel.clone(), alice.id, Initiator::Ctrl)
+            .await?;
+
+        let err = engine.delete_user(alice.id).await.unwrap_err();
+        assert!(err.to_string().contains("general"));
+        engine.delete_channel(channel, Initiator::Ctrl).await?;
+        engine.delete_user(alice.id).await?;
+        Ok(())
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn reject_empty_passwords() -> Result<(), Error> {
+        let limits = SessionLimits {
+            idle: 60,
+            lifetime: 60,
+        };
+        let mut engine = Engine::start(std::env::temp_dir(), limits);
+        let res = engine.create_user("alice".into(), "".into()).await;
+        assert!(res.is_err());
+        engine.create_user("alice".into(), "secret".into()).await?;
+        let res = engine.set_password("alice".into(), "".into()).await;
+        assert!(res.is_err());
+        Ok(())
+    }
 }
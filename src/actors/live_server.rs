@@ -1,34 +1,55 @@
-use crate::actors::Engine;
+use super::permissions::Initiator;
+use super::registry::{ConnectionId, LiveSender, Login};
+use crate::actors::{Authentication, Engine, EngineError};
 use crate::assets::{read_assets, Assets};
-use crate::db::types::UserId;
+use crate::db::types::{ChannelId, SessionId, Timestamp, TokenScope, UserId, Username};
+use crate::generators::TOKEN_PREFIX;
+use crate::tls::{self, CertResolver, TlsFiles};
 use anyhow::Error;
 use async_trait::async_trait;
 use futures::{SinkExt, StreamExt};
 use headers::{ContentType, HeaderMapExt};
+use hyper::server::conn::Http;
 use meio::{wrapper, Actor, Context};
-use protocol::{ClientToServer, Delta, LoginUpdate, Reaction, ServerToClient};
+use protocol::{
+    ChannelUpdate, ClientToServer, Delta, Key, LoginUpdate, MemberUpdate, MessageUpdate,
+    ProfileUpdate, Reaction, SecurityUpdate, ServerToClient, ThreadUpdate, TokenUpdate,
+};
 use std::collections::VecDeque;
-use std::net::SocketAddr;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tokio::task::block_in_place as wait;
+use tokio::time::{delay_until, timeout};
 use warp::{
     http::{StatusCode, Uri},
     path::Tail,
     ws::{Message, WebSocket, Ws},
-    Filter, Reply,
+    Filter, Rejection, Reply,
 };
 
+/// Time to send a code of the second factor after a password.
+const SECOND_FACTOR_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time to finish a TLS handshake after a connection was accepted.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 wrapper!(LiveServer for LiveServerActor);
 
 impl LiveServer {
-    pub fn start(addr: SocketAddr, engine: Engine) -> Self {
-        let actor = LiveServerActor { addr, engine };
+    /// Serves plain HTTP or HTTPS if `tls` files are set.
+    pub fn start(addr: SocketAddr, tls: Option<TlsFiles>, engine: Engine) -> Self {
+        let actor = LiveServerActor { addr, tls, engine };
         meio::spawn(actor)
     }
 }
 
 pub struct LiveServerActor {
     addr: SocketAddr,
+    tls: Option<TlsFiles>,
     engine: Engine,
 }
 
@@ -49,16 +70,152 @@ impl Actor for LiveServerActor {
 impl LiveServerActor {
     async fn run(&mut self, _: Context<Self>) -> Result<(), Error> {
         let asset_handler = AssetHandler::new().await?;
-        let index = warp::path::end().map(|| warp::redirect(Uri::from_static("/index.html")));
-        let engine = self.engine.clone();
-        let live = warp::path("live")
-            .and(warp::ws())
-            .map(move |ws| LiveHandler::upgrade(ws, engine.clone()));
-        let assets = warp::path::tail().map(move |tail| asset_handler.handle(tail));
-        let routes = index.or(live).or(assets);
-        warp::serve(routes).run(self.addr).await;
+        if let Some(files) = self.tls.clone() {
+            self.run_tls(files, asset_handler).await?;
+        } else {
+            let routes = routes(self.engine.clone(), asset_handler, warp::addr::remote());
+            warp::serve(routes).run(self.addr).await;
+        }
         Ok(())
     }
+
+    /// Terminates TLS connections and serves them with the same routes.
+    async fn run_tls(&mut self, files: TlsFiles, asset_handler: AssetHandler) -> Result<(), Error> {
+        let resolver = Arc::new(wait(|| CertResolver::new(files))?);
+        tokio::spawn(tls::watch(resolver.clone()));
+        let acceptor = tls::acceptor(resolver);
+        let mut listener = TcpListener::bind(&self.addr).await?;
+        let mut incoming = listener.incoming().fuse();
+        while let Some(stream) = incoming.next().await {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    // A failed connection doesn't stop the server
+                    log::warn!("Can't accept a TLS connection: {}", err);
+                    continue;
+                }
+            };
+            let remote = stream.peer_addr().ok();
+            let acceptor = acceptor.clone();
+            let routes = routes(
+                self.engine.clone(),
+                asset_handler.clone(),
+                warp::any().map(move || remote),
+            );
+            tokio::spawn(async move {
+                let result: Result<(), Error> = async {
+                    let stream = timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await??;
+                    Http::new()
+                        .serve_connection(stream, warp::service(routes))
+                        .with_upgrades()
+                        .await?;
+                    Ok(())
+                }
+                .await;
+                if let Err(err) = result {
+                    log::debug!("TLS connection failed: {}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Routes of the server. `remote` provides an address of a client.
+fn routes<R>(
+    engine: Engine,
+    asset_handler: AssetHandler,
+    remote: R,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone
+where
+    R: Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone + Send + Sync + 'static,
+{
+    let index = warp::path::end().map(|| warp::redirect(Uri::from_static("/index.html")));
+    let live = warp::path("live")
+        .and(warp::ws())
+        .and(remote)
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |ws, addr, authorization: Option<String>| {
+            let key = authorization.as_deref().and_then(bearer_key);
+            LiveHandler::upgrade(ws, addr, key, engine.clone())
+        });
+    let assets = warp::path::tail().map(move |tail| asset_handler.handle(tail));
+    index.or(live).or(assets)
+}
+
+/// Converts a UNIX timestamp to an instant of the monotonic clock.
+fn instant_at(timestamp: Timestamp) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as Timestamp)
+        .unwrap_or_default();
+    let remaining = (timestamp - now).max(0) as u64;
+    Instant::now() + Duration::from_secs(remaining)
+}
+
+/// Extracts a key from the `Authorization: Bearer <key>` header.
+fn bearer_key(value: &str) -> Option<Key> {
+    let mut parts = value.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(key)) if scheme.eq_ignore_ascii_case("bearer") => {
+            Some(key.trim().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Distinguishes denied permissions from other failures.
+fn error_reaction(err: Error) -> Reaction {
+    match err.downcast_ref::<EngineError>() {
+        Some(EngineError::PermissionDenied { .. }) => Reaction::PermissionDenied {
+            reason: err.to_string(),
+        },
+        _ => Reaction::fail(err),
+    }
+}
+
+/// Returns `true` if a connection that logged in with a token
+/// of the scope can make the request.
+fn scope_allows(scope: TokenScope, request: &ClientToServer) -> bool {
+    match request {
+        // Logins replace the token
+        ClientToServer::CreateSession(_)
+        | ClientToServer::SecondFactor(_)
+        | ClientToServer::RestoreSession(_)
+        | ClientToServer::Register { .. } => true,
+        ClientToServer::Subscribe(_)
+        | ClientToServer::Unsubscribe(_)
+        | ClientToServer::FetchHistory { .. }
+        | ClientToServer::FetchMembers(_)
+        | ClientToServer::FetchChannels
+        | ClientToServer::FetchRevisions(_)
+        | ClientToServer::FetchThread { .. }
+        | ClientToServer::FetchProfile(_) => true,
+        ClientToServer::CreateChannel(_)
+        | ClientToServer::PostMessage { .. }
+        | ClientToServer::MarkRead { .. }
+        | ClientToServer::JoinChannel(_)
+        | ClientToServer::LeaveChannel(_)
+        | ClientToServer::InviteMember { .. }
+        | ClientToServer::KickMember { .. }
+        | ClientToServer::OpenDirect { .. }
+        | ClientToServer::EditMessage { .. }
+        | ClientToServer::DeleteMessage(_)
+        | ClientToServer::PostReply { .. }
+        | ClientToServer::FollowThread(_)
+        | ClientToServer::UnfollowThread(_)
+        | ClientToServer::AddReaction { .. }
+        | ClientToServer::RemoveReaction { .. }
+        | ClientToServer::UpdateProfile(_) => scope == TokenScope::Write,
+        // The account is managed with a session only
+        ClientToServer::Logout
+        | ClientToServer::EnrollTotp { .. }
+        | ClientToServer::ConfirmTotp { .. }
+        | ClientToServer::DisableTotp(_)
+        | ClientToServer::CreateToken { .. }
+        | ClientToServer::FetchTokens
+        | ClientToServer::RevokeToken(_) => false,
+    }
 }
 
 #[derive(Clone)]
@@ -90,40 +247,140 @@ impl AssetHandler {
 /// WebSocket handler for `LiveServerActor`.
 struct LiveHandler {
     engine: Engine,
+    /// Remote address used to throttle login attempts.
+    address: Option<IpAddr>,
     user_id: Option<UserId>,
+    session_id: Option<SessionId>,
+    /// Limits requests of a connection that logged in with an API token.
+    scope: Option<TokenScope>,
+    /// The connection is closed when its session or API token expires.
+    expires: Option<Instant>,
+    /// A user that passed the password step and has to send a second factor.
+    pending_user: Option<(UserId, Instant)>,
+    /// Sender for deltas routed by `Engine`.
+    sender: LiveSender,
+    /// Id of the connection registered in `Engine`.
+    connection_id: Option<ConnectionId>,
     // TODO: Use channel here
     queue: VecDeque<Delta>,
 }
 
 impl LiveHandler {
-    fn upgrade(ws: Ws, engine: Engine) -> impl Reply {
-        ws.on_upgrade(|weboscket| Self::handle(weboscket, engine))
+    /// `key` of the `Authorization` header logs in the connection immediately.
+    fn upgrade(ws: Ws, addr: Option<SocketAddr>, key: Option<Key>, engine: Engine) -> impl Reply {
+        let address = addr.map(|addr| addr.ip());
+        ws.on_upgrade(move |weboscket| Self::handle(weboscket, address, key, engine))
     }
 
-    async fn handle(websocket: WebSocket, engine: Engine) {
-        let this = Self {
+    async fn handle(
+        websocket: WebSocket,
+        address: Option<IpAddr>,
+        key: Option<Key>,
+        engine: Engine,
+    ) {
+        let (sender, deltas) = mpsc::unbounded_channel();
+        let mut this = Self {
             engine,
+            address,
             user_id: None,
+            session_id: None,
+            scope: None,
+            expires: None,
+            pending_user: None,
+            sender,
+            connection_id: None,
             queue: VecDeque::new(),
         };
-        if let Err(err) = this.routine(websocket).await {
+        if let Some(key) = key {
+            // The result is delivered as `LoginUpdate` deltas
+            let request = ClientToServer::RestoreSession(key);
+            if let Err(err) = this.process_request(request).await {
+                log::error!("Can't log in with a header: {}", err);
+            }
+        }
+        if let Err(err) = this.routine(websocket, deltas).await {
             log::warn!("LiveHandler error: {}", err);
         }
+        if let Err(err) = this.unregister().await {
+            log::error!("Can't unregister live connection: {}", err);
+        }
     }
 
-    async fn routine(mut self, websocket: WebSocket) -> Result<(), Error> {
+    async fn routine(
+        &mut self,
+        websocket: WebSocket,
+        mut deltas: mpsc::UnboundedReceiver<Delta>,
+    ) -> Result<(), Error> {
         log::trace!("Live WebSocket session started");
         let (mut tx, mut rx) = websocket.split();
-        while let Some(msg) = rx.next().await.transpose()? {
+        // Deltas of a login with the `Authorization` header
+        for delta in self.queue.drain(..) {
+            let bytes = serde_json::to_vec(&ServerToClient::Delta(Box::new(delta)))?;
+            tx.send(Message::binary(bytes)).await?;
+        }
+        loop {
+            let expires = self.expires;
+            let deadline = expires.unwrap_or_else(Instant::now);
+            let msg = tokio::select! {
+                msg = rx.next() => msg.transpose()?,
+                _ = delay_until(deadline.into()), if expires.is_some() => {
+                    log::debug!("Login of the live connection expired");
+                    let delta = Delta::LoginUpdate(LoginUpdate::LoggedOut);
+                    let bytes = serde_json::to_vec(&ServerToClient::Delta(Box::new(delta)))?;
+                    tx.send(Message::binary(bytes)).await?;
+                    tx.send(Message::close()).await?;
+                    break;
+                }
+                Some(delta) = deltas.recv() => {
+                    let logged_out = matches!(delta, Delta::LoginUpdate(LoginUpdate::LoggedOut));
+                    let response = ServerToClient::Delta(Box::new(delta));
+                    let bytes = serde_json::to_vec(&response)?;
+                    tx.send(Message::binary(bytes)).await?;
+                    if logged_out {
+                        // The session was closed and `Engine` dropped the connection
+                        tx.send(Message::close()).await?;
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let msg = {
+                match msg {
+                    Some(msg) => msg,
+                    None => break,
+                }
+            };
             if msg.is_text() || msg.is_binary() {
                 let request: ClientToServer = serde_json::from_slice(msg.as_bytes())?;
                 log::trace!("Received: {:?}", request);
+                if let Some(session_id) = self.session_id {
+                    // Requests keep the session alive until its lifetime ends
+                    match self.engine.touch_session(session_id).await? {
+                        Some(expires_at) => {
+                            self.expires = Some(instant_at(expires_at));
+                        }
+                        None => {
+                            log::debug!("Session of the live connection expired");
+                            let delta = Delta::LoginUpdate(LoginUpdate::LoggedOut);
+                            let bytes =
+                                serde_json::to_vec(&ServerToClient::Delta(Box::new(delta)))?;
+                            tx.send(Message::binary(bytes)).await?;
+                            tx.send(Message::close()).await?;
+                            break;
+                        }
+                    }
+                }
                 let reaction = self
                     .process_request(request)
                     .await
-                    .unwrap_or_else(Reaction::fail);
+                    .unwrap_or_else(error_reaction);
                 let mut messages = vec![ServerToClient::Reaction(reaction)];
-                messages.extend(self.queue.drain(..).map(ServerToClient::Delta));
+                let deltas = self
+                    .queue
+                    .drain(..)
+                    .map(Box::new)
+                    .map(ServerToClient::Delta);
+                messages.extend(deltas);
                 for response in messages {
                     let bytes = serde_json::to_vec(&response)?;
                     let message = Message::binary(bytes);
@@ -145,43 +402,210 @@ impl LiveHandler {
         self.queue.push_back(delta);
     }
 
+    /// Registers the connection in `Engine` to receive deltas for the user.
+    async fn register(
+        &mut self,
+        user_id: UserId,
+        login: Login,
+        scope: Option<TokenScope>,
+        expires_at: Option<Timestamp>,
+    ) -> Result<(), Error> {
+        self.unregister().await?;
+        let connection_id = self
+            .engine
+            .register_live(user_id, login, self.sender.clone())
+            .await?;
+        self.user_id = Some(user_id);
+        self.session_id = match login {
+            Login::Session(session_id) => Some(session_id),
+            Login::Token(_) => None,
+        };
+        self.scope = scope;
+        self.expires = expires_at.map(instant_at);
+        self.connection_id = Some(connection_id);
+        Ok(())
+    }
+
+    /// Creates a session for the authenticated user and sends its key.
+    async fn start_session(&mut self, user_id: UserId) -> Result<(), Error> {
+        // TODO: `Engine` have to send LoggedIn event to every `LiveHandler`
+        let (key, session) = self.engine.create_session(user_id).await?;
+        let expires_at = self.engine.touch_session(session.id).await?;
+        self.register(user_id, Login::Session(session.id), None, expires_at)
+            .await?;
+        let update = LoginUpdate::LoggedIn { key };
+        let delta = Delta::LoginUpdate(update);
+        self.schedule(delta);
+        self.schedule_channels(user_id).await?;
+        Ok(())
+    }
+
+    /// Logs in with an API token instead of a session key.
+    async fn restore_token(&mut self, key: Key) -> Result<Reaction, Error> {
+        match self.engine.use_token(key.clone()).await? {
+            Some(token) => {
+                self.register(
+                    token.user_id,
+                    Login::Token(token.id),
+                    Some(token.scope),
+                    token.expires_at,
+                )
+                .await?;
+                let update = LoginUpdate::LoggedIn { key };
+                let delta = Delta::LoginUpdate(update);
+                self.schedule(delta);
+                self.schedule_channels(token.user_id).await?;
+                Ok(Reaction::Success)
+            }
+            None => {
+                let update = LoginUpdate::LoginFail;
+                let delta = Delta::LoginUpdate(update);
+                self.schedule(delta);
+                Ok(Reaction::fail("Bad token."))
+            }
+        }
+    }
+
+    /// Schedules channels of the user with unread counters.
+    async fn schedule_channels(&mut self, user_id: UserId) -> Result<(), Error> {
+        let channels = self.engine.get_user_channels(user_id).await?;
+        let update = ChannelUpdate::ChannelsList {
+            channels: channels.into_iter().map(Into::into).collect(),
+        };
+        let delta = Delta::ChannelUpdate(update);
+        self.schedule(delta);
+        Ok(())
+    }
+
+    async fn unregister(&mut self) -> Result<(), Error> {
+        if let Some(connection_id) = self.connection_id.take() {
+            self.engine.unregister_live(connection_id).await?;
+        }
+        Ok(())
+    }
+
+    async fn find_user_id(&mut self, username: Username) -> Result<UserId, Error> {
+        let user = self.engine.find_user(username.clone()).await?;
+        user.map(|user| user.id)
+            .ok_or_else(|| EngineError::UserNotFound(username).into())
+    }
+
+    async fn ensure_member(&mut self, channel_id: ChannelId, user_id: UserId) -> Result<(), Error> {
+        if self.engine.is_member(channel_id, user_id).await? {
+            Ok(())
+        } else {
+            Err(EngineError::NotMember {
+                channel_id,
+                user_id,
+            }
+            .into())
+        }
+    }
+
     async fn process_request(&mut self, request: ClientToServer) -> Result<Reaction, Error> {
+        if let Some(scope) = self.scope {
+            if !scope_allows(scope, &request) {
+                let reason = "Not allowed with this token.".to_string();
+                return Ok(Reaction::PermissionDenied { reason });
+            }
+        }
         match request {
             ClientToServer::CreateSession(creds) => {
-                // TODO: `Engine` needs high level functions like `engine.create_session(creds)`
-                let user_res = self.engine.find_user(creds.username).await?;
-                match user_res {
-                    Some(user) if user.password == creds.password => {
-                        // TODO: `Engine` have to send LoggedIn event to every `LiveHandler`
-                        let key = self.engine.create_session(user.id).await?;
-                        self.user_id = Some(user.id);
-                        let update = LoginUpdate::LoggedIn { key };
-                        let delta = Delta::LoginUpdate(update);
-                        self.schedule(delta);
+                let auth = self.engine.authenticate(creds, self.address).await?;
+                match auth {
+                    Authentication::Accepted(user) => {
+                        self.start_session(user.id).await?;
                         Ok(Reaction::Success)
                     }
-                    Some(_) | None => {
+                    Authentication::Rejected => {
                         let update = LoginUpdate::LoginFail;
                         let delta = Delta::LoginUpdate(update);
                         self.schedule(delta);
                         Ok(Reaction::fail("Bad credentials."))
                     }
+                    Authentication::Disabled => {
+                        let update = LoginUpdate::LoginFail;
+                        let delta = Delta::LoginUpdate(update);
+                        self.schedule(delta);
+                        Ok(Reaction::fail("Account is disabled."))
+                    }
+                    Authentication::SecondFactor(user) => {
+                        self.pending_user = Some((user.id, Instant::now()));
+                        let update = LoginUpdate::SecondFactorRequired;
+                        let delta = Delta::LoginUpdate(update);
+                        self.schedule(delta);
+                        Ok(Reaction::Success)
+                    }
+                    Authentication::Locked { retry_after } => {
+                        let update = LoginUpdate::LoginLocked {
+                            retry_after: retry_after.as_secs() + 1,
+                        };
+                        let delta = Delta::LoginUpdate(update);
+                        self.schedule(delta);
+                        Ok(Reaction::fail("Too many login attempts."))
+                    }
+                }
+            }
+            ClientToServer::SecondFactor(code) => {
+                let pending = self
+                    .pending_user
+                    .take()
+                    .filter(|(_, since)| since.elapsed() < SECOND_FACTOR_TIMEOUT);
+                if let Some((user_id, since)) = pending {
+                    let auth = self
+                        .engine
+                        .verify_second_factor(user_id, code, self.address)
+                        .await?;
+                    match auth {
+                        Authentication::Accepted(user) => {
+                            self.start_session(user.id).await?;
+                            Ok(Reaction::Success)
+                        }
+                        Authentication::Disabled => {
+                            let update = LoginUpdate::LoginFail;
+                            let delta = Delta::LoginUpdate(update);
+                            self.schedule(delta);
+                            Ok(Reaction::fail("Account is disabled."))
+                        }
+                        Authentication::Locked { retry_after } => {
+                            let update = LoginUpdate::LoginLocked {
+                                retry_after: retry_after.as_secs() + 1,
+                            };
+                            let delta = Delta::LoginUpdate(update);
+                            self.schedule(delta);
+                            Ok(Reaction::fail("Too many login attempts."))
+                        }
+                        Authentication::Rejected | Authentication::SecondFactor(_) => {
+                            // The next attempt is possible until the timeout
+                            self.pending_user = Some((user_id, since));
+                            let update = LoginUpdate::SecondFactorFail;
+                            let delta = Delta::LoginUpdate(update);
+                            self.schedule(delta);
+                            Ok(Reaction::fail("Invalid code."))
+                        }
+                    }
+                } else {
+                    Ok(Reaction::fail("Can't check a second factor"))
                 }
             }
+            ClientToServer::RestoreSession(key) if key.starts_with(TOKEN_PREFIX) => {
+                self.restore_token(key).await
+            }
             ClientToServer::RestoreSession(key) => {
-                // TODO: Replace with `engine.restore_session(key)` method
-                let session_res = self.engine.find_session(key.clone()).await?;
-                // TODO: Check properly (with protection)
+                let session_res = self.engine.restore_session(key.clone()).await?;
                 match session_res {
-                    Some(session) if session.key == key => {
-                        // TODO: Update session (last_visit field)
-                        self.user_id = Some(session.user_id);
+                    Some(session) => {
+                        let expires_at = self.engine.touch_session(session.id).await?;
+                        let login = Login::Session(session.id);
+                        self.register(session.user_id, login, None, expires_at)
+                            .await?;
                         let update = LoginUpdate::LoggedIn { key };
                         let delta = Delta::LoginUpdate(update);
                         self.schedule(delta);
+                        self.schedule_channels(session.user_id).await?;
                         Ok(Reaction::Success)
                     }
-                    Some(_) | None => {
+                    None => {
                         // Don't share the reason
                         let update = LoginUpdate::LoginFail;
                         let delta = Delta::LoginUpdate(update);
@@ -190,22 +614,410 @@ impl LiveHandler {
                     }
                 }
             }
+            ClientToServer::Register { token, credentials } => {
+                match self.engine.register(token, credentials).await {
+                    Ok(user) => {
+                        self.start_session(user.id).await?;
+                        Ok(Reaction::Success)
+                    }
+                    Err(err) => {
+                        log::debug!("Registration failed: {}", err);
+                        // Doesn't reveal taken usernames or valid invites
+                        let reason = match err.downcast_ref::<EngineError>() {
+                            Some(EngineError::InvalidUsername(_))
+                            | Some(EngineError::EmptyPassword) => err.to_string(),
+                            _ => "Can't register with this invite and username.".to_string(),
+                        };
+                        let update = LoginUpdate::RegisterFail {
+                            reason: reason.clone(),
+                        };
+                        let delta = Delta::LoginUpdate(update);
+                        self.schedule(delta);
+                        Ok(Reaction::fail(reason))
+                    }
+                }
+            }
+            ClientToServer::Logout => {
+                if let (Some(session_id), Some(user_id)) = (self.session_id, self.user_id) {
+                    // `Engine` sends `LoggedOut` to every connection of the session
+                    self.engine
+                        .revoke_session(session_id, Initiator::User(user_id))
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't log out"))
+                }
+            }
             ClientToServer::CreateChannel(channel_name) => {
                 if let Some(user_id) = self.user_id {
                     self.engine
-                        .create_channel(channel_name.clone(), user_id)
+                        .create_channel(channel_name.clone(), user_id, Initiator::User(user_id))
                         .await?;
-                    /* TODO: Schedule
-                    Ok(ServerToClient::ChannelCreated(channel_name))
-                    */
                     Ok(Reaction::Success)
                 } else {
-                    /* TODO: Schedule Channel Added notification for all
-                     * (or use `EngineActor` or `LiveActor` to track and send that type of notifications)
-                     */
                     Ok(Reaction::fail("Can't create channel"))
                 }
             }
+            ClientToServer::PostMessage { channel, text } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .post_message(channel.into(), user_id, text)
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't post a message"))
+                }
+            }
+            ClientToServer::Subscribe(channel) => {
+                if let (Some(connection_id), Some(user_id)) = (self.connection_id, self.user_id) {
+                    self.engine
+                        .subscribe(connection_id, channel.into(), user_id)
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't subscribe to a channel"))
+                }
+            }
+            ClientToServer::Unsubscribe(channel) => {
+                if let Some(connection_id) = self.connection_id {
+                    self.engine
+                        .unsubscribe(connection_id, channel.into())
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't unsubscribe from a channel"))
+                }
+            }
+            ClientToServer::FetchHistory {
+                channel,
+                before,
+                limit,
+            } => {
+                if let Some(user_id) = self.user_id {
+                    let history = self
+                        .engine
+                        .fetch_history(channel.into(), user_id, before.map(Into::into), limit)
+                        .await?;
+                    let update = MessageUpdate::History {
+                        channel,
+                        messages: history.messages.into_iter().map(Into::into).collect(),
+                        next: history.next.map(Into::into),
+                    };
+                    let delta = Delta::MessageUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch history"))
+                }
+            }
+            ClientToServer::MarkRead { channel, message } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .mark_read(channel.into(), user_id, message.into())
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't mark a channel as read"))
+                }
+            }
+            ClientToServer::JoinChannel(channel) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .add_member(channel.into(), user_id, Initiator::User(user_id))
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't join a channel"))
+                }
+            }
+            ClientToServer::LeaveChannel(channel) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .remove_member(channel.into(), user_id, Initiator::User(user_id))
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't leave a channel"))
+                }
+            }
+            ClientToServer::InviteMember { channel, username } => {
+                if let Some(user_id) = self.user_id {
+                    let member_id = self.find_user_id(username).await?;
+                    self.engine
+                        .add_member(channel.into(), member_id, Initiator::User(user_id))
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't invite a member"))
+                }
+            }
+            ClientToServer::KickMember { channel, username } => {
+                if let Some(user_id) = self.user_id {
+                    let member_id = self.find_user_id(username).await?;
+                    self.engine
+                        .remove_member(channel.into(), member_id, Initiator::User(user_id))
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't kick a member"))
+                }
+            }
+            ClientToServer::FetchMembers(channel) => {
+                if let Some(user_id) = self.user_id {
+                    let channel_id = channel.into();
+                    self.ensure_member(channel_id, user_id).await?;
+                    let members = self.engine.get_members(channel_id).await?;
+                    let update = MemberUpdate::MembersList {
+                        channel,
+                        members: members.into_iter().map(|user| user.username).collect(),
+                    };
+                    let delta = Delta::MemberUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch members"))
+                }
+            }
+            ClientToServer::FetchChannels => {
+                if let Some(user_id) = self.user_id {
+                    self.schedule_channels(user_id).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch channels"))
+                }
+            }
+            ClientToServer::OpenDirect { usernames } => {
+                if let Some(user_id) = self.user_id {
+                    let mut user_ids = vec![user_id];
+                    for username in usernames {
+                        user_ids.push(self.find_user_id(username).await?);
+                    }
+                    let channel = self.engine.open_direct(user_ids).await?;
+                    let update = ChannelUpdate::DirectOpened {
+                        channel: channel.into(),
+                    };
+                    let delta = Delta::ChannelUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't open a direct channel"))
+                }
+            }
+            ClientToServer::EditMessage { message, text } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .edit_message(message.into(), user_id, text)
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't edit a message"))
+                }
+            }
+            ClientToServer::DeleteMessage(message) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.delete_message(message.into(), user_id).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't delete a message"))
+                }
+            }
+            ClientToServer::FetchRevisions(message) => {
+                if let Some(user_id) = self.user_id {
+                    let revisions = self.engine.get_revisions(message.into(), user_id).await?;
+                    let update = MessageUpdate::Revisions {
+                        message,
+                        revisions: revisions.into_iter().map(Into::into).collect(),
+                    };
+                    let delta = Delta::MessageUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch revisions"))
+                }
+            }
+            ClientToServer::PostReply { parent, text } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.post_reply(parent.into(), user_id, text).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't post a reply"))
+                }
+            }
+            ClientToServer::FetchThread {
+                message,
+                before,
+                limit,
+            } => {
+                if let Some(user_id) = self.user_id {
+                    let thread = self
+                        .engine
+                        .fetch_thread(message.into(), user_id, before.map(Into::into), limit)
+                        .await?;
+                    let update = ThreadUpdate::Thread {
+                        parent: thread.parent.into(),
+                        replies: thread
+                            .replies
+                            .messages
+                            .into_iter()
+                            .map(Into::into)
+                            .collect(),
+                        next: thread.replies.next.map(Into::into),
+                    };
+                    let delta = Delta::ThreadUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch a thread"))
+                }
+            }
+            ClientToServer::FollowThread(message) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.follow_thread(message.into(), user_id).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't follow a thread"))
+                }
+            }
+            ClientToServer::UnfollowThread(message) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.unfollow_thread(message.into(), user_id).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't unfollow a thread"))
+                }
+            }
+            ClientToServer::AddReaction { message, emoji } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .add_emoji(message.into(), user_id, emoji)
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't add a reaction"))
+                }
+            }
+            ClientToServer::RemoveReaction { message, emoji } => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .remove_emoji(message.into(), user_id, emoji)
+                        .await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't remove a reaction"))
+                }
+            }
+            ClientToServer::UpdateProfile(profile) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.update_profile(user_id, profile.into()).await?;
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't update a profile"))
+                }
+            }
+            ClientToServer::EnrollTotp { password } => {
+                if let Some(user_id) = self.user_id {
+                    let (secret, uri) = self.engine.enroll_totp(user_id, password).await?;
+                    let update = SecurityUpdate::TotpEnrollment { secret, uri };
+                    let delta = Delta::SecurityUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't enroll a second factor"))
+                }
+            }
+            ClientToServer::ConfirmTotp { code, password } => {
+                if let Some(user_id) = self.user_id {
+                    let recovery_codes = self.engine.confirm_totp(user_id, code, password).await?;
+                    let update = SecurityUpdate::TotpEnabled { recovery_codes };
+                    let delta = Delta::SecurityUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't enable a second factor"))
+                }
+            }
+            ClientToServer::DisableTotp(code) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine.disable_totp(user_id, code).await?;
+                    let update = SecurityUpdate::TotpDisabled;
+                    let delta = Delta::SecurityUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't disable a second factor"))
+                }
+            }
+            ClientToServer::FetchProfile(username) => {
+                if let Some(viewer_id) = self.user_id {
+                    let user_id = self.find_user_id(username.clone()).await?;
+                    let profile = self
+                        .engine
+                        .get_profile(user_id, Initiator::User(viewer_id))
+                        .await?;
+                    let update = ProfileUpdate::Profile {
+                        username,
+                        profile: profile.into(),
+                    };
+                    let delta = Delta::ProfileUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch a profile"))
+                }
+            }
+            ClientToServer::CreateToken {
+                name,
+                scope,
+                lifetime,
+            } => {
+                if let Some(user_id) = self.user_id {
+                    let (key, token) = self
+                        .engine
+                        .create_token(
+                            user_id,
+                            name,
+                            scope.into(),
+                            lifetime,
+                            Initiator::User(user_id),
+                        )
+                        .await?;
+                    let update = TokenUpdate::TokenCreated {
+                        token: token.into(),
+                        key,
+                    };
+                    let delta = Delta::TokenUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't create a token"))
+                }
+            }
+            ClientToServer::FetchTokens => {
+                if let Some(user_id) = self.user_id {
+                    let tokens = self.engine.get_tokens(user_id).await?;
+                    let update = TokenUpdate::TokensList {
+                        tokens: tokens.into_iter().map(Into::into).collect(),
+                    };
+                    let delta = Delta::TokenUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't fetch tokens"))
+                }
+            }
+            ClientToServer::RevokeToken(id) => {
+                if let Some(user_id) = self.user_id {
+                    self.engine
+                        .revoke_token(id.into(), Initiator::User(user_id))
+                        .await?;
+                    let update = TokenUpdate::TokenRevoked { id };
+                    let delta = Delta::TokenUpdate(update);
+                    self.schedule(delta);
+                    Ok(Reaction::Success)
+                } else {
+                    Ok(Reaction::fail("Can't revoke a token"))
+                }
+            }
         }
     }
 }
@@ -0,0 +1,192 @@
+//! This module contains tracking of failed logins.
+//! `EngineActor` uses it to slow down guessing of passwords
+//! for a username or from an address with exponential delays.
+
+use crate::db::types::Username;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Failures allowed without a lockout.
+const FREE_ATTEMPTS: u32 = 3;
+
+/// The first lockout period. It doubles with every next failure.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Failures are forgotten after this period without new ones.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Maximal amount of tracked targets. The oldest ones are dropped
+/// to keep memory bounded if many addresses are used.
+const MAX_TARGETS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    Username(Username),
+    Address(IpAddr),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Username(username) => write!(f, "user {}", username),
+            Self::Address(address) => write!(f, "address {}", address),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lockout {
+    pub target: Target,
+    pub failures: u32,
+    pub remaining: Duration,
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+}
+
+#[derive(Default)]
+pub struct Lockouts {
+    failures: HashMap<Target, Failures>,
+}
+
+impl Lockouts {
+    /// Returns the longest remaining lockout of the targets.
+    pub fn check(&mut self, targets: &[Target]) -> Option<Duration> {
+        let now = Instant::now();
+        self.forget(now);
+        targets
+            .iter()
+            .filter_map(|target| self.failures.get(target))
+            .filter_map(|failures| failures.remaining(now))
+            .max()
+    }
+
+    pub fn fail(&mut self, targets: &[Target]) {
+        let now = Instant::now();
+        for target in targets {
+            if !self.failures.contains_key(target) {
+                self.make_room(now);
+            }
+            let failures = self
+                .failures
+                .entry(target.clone())
+                .or_insert_with(|| Failures {
+                    count: 0,
+                    last: now,
+                    locked_until: None,
+                });
+            failures.count += 1;
+            failures.last = now;
+            if failures.count > FREE_ATTEMPTS {
+                let exp = (failures.count - FREE_ATTEMPTS - 1).min(16);
+                let delay = (BASE_DELAY * 2u32.pow(exp)).min(MAX_DELAY);
+                log::debug!("Login for {} locked for {:?}", target, delay);
+                failures.locked_until = Some(now + delay);
+            }
+        }
+    }
+
+    pub fn reset(&mut self, targets: &[Target]) {
+        for target in targets {
+            self.failures.remove(target);
+        }
+    }
+
+    /// Returns targets that are locked now.
+    pub fn locked(&mut self) -> Vec<Lockout> {
+        let now = Instant::now();
+        self.forget(now);
+        self.failures
+            .iter()
+            .filter_map(|(target, failures)| {
+                failures.remaining(now).map(|remaining| Lockout {
+                    target: target.clone(),
+                    failures: failures.count,
+                    remaining,
+                })
+            })
+            .collect()
+    }
+
+    pub fn clear_all(&mut self) {
+        self.failures.clear();
+    }
+
+    fn forget(&mut self, now: Instant) {
+        self.failures
+            .retain(|_, failures| now.duration_since(failures.last) < FORGET_AFTER);
+    }
+
+    fn make_room(&mut self, now: Instant) {
+        if self.failures.len() < MAX_TARGETS {
+            return;
+        }
+        self.forget(now);
+        if self.failures.len() < MAX_TARGETS {
+            return;
+        }
+        let oldest = self
+            .failures
+            .iter()
+            .min_by_key(|(_, failures)| failures.last)
+            .map(|(target, _)| target.clone());
+        if let Some(target) = oldest {
+            log::debug!("Too many lockouts, forgetting {}", target);
+            self.failures.remove(&target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_after_failures() {
+        let mut lockouts = Lockouts::default();
+        let targets = vec![
+            Target::Username("alice".into()),
+            Target::Address([127, 0, 0, 1].into()),
+        ];
+        for _ in 0..FREE_ATTEMPTS {
+            assert!(lockouts.check(&targets).is_none());
+            lockouts.fail(&targets);
+        }
+        assert!(lockouts.check(&targets).is_none());
+        lockouts.fail(&targets);
+        assert!(lockouts.check(&targets).is_some());
+        assert_eq!(lockouts.locked().len(), 2);
+        lockouts.reset(&targets[..1]);
+        assert!(lockouts.check(&targets[..1]).is_none());
+        assert!(lockouts.check(&targets).is_some());
+        lockouts.clear_all();
+        assert!(lockouts.locked().is_empty());
+    }
+
+    #[test]
+    fn limited_targets() {
+        let mut lockouts = Lockouts::default();
+        for n in 0..MAX_TARGETS + 10 {
+            let target = Target::Username(format!("user{}", n));
+            lockouts.fail(&[target]);
+        }
+        assert_eq!(lockouts.failures.len(), MAX_TARGETS);
+        let first = Target::Username("user0".into());
+        assert!(!lockouts.failures.contains_key(&first));
+    }
+}
@@ -2,7 +2,13 @@ mod ctrl_server;
 pub use ctrl_server::CtrlServer;
 
 mod engine;
-pub use engine::Engine;
+pub use engine::{Authentication, Engine, EngineError, SessionLimits};
 
 mod live_server;
 pub use live_server::LiveServer;
+
+mod lockouts;
+
+mod permissions;
+
+mod registry;
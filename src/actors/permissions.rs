@@ -0,0 +1,114 @@
+//! This module contains rules of permissions.
+//! `EngineActor` checks them before changes requested by users.
+//! Requests of the ctrl interface are always allowed, because
+//! it's available to the server's administrator only.
+
+use crate::db::types::{ChannelId, MemberRole, UserId, UserRole};
+use std::fmt;
+
+/// Who requested a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Initiator {
+    Ctrl,
+    User(UserId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    CreateChannel,
+    DeleteChannel(ChannelId),
+    JoinChannel(ChannelId),
+    /// Add other users to a channel.
+    AddMembers(ChannelId),
+    /// Remove other users from a channel.
+    RemoveMembers(ChannelId),
+    /// Edit and delete messages of other users.
+    ModerateMessages(ChannelId),
+    /// Change roles of members of a channel.
+    ManageMembers(ChannelId),
+    /// Change roles of users in the workspace.
+    ManageRoles,
+    /// See emails in profiles of other users.
+    ViewEmails,
+}
+
+impl Permission {
+    pub fn channel_id(&self) -> Option<ChannelId> {
+        match self {
+            Self::DeleteChannel(channel_id)
+            | Self::JoinChannel(channel_id)
+            | Self::AddMembers(channel_id)
+            | Self::RemoveMembers(channel_id)
+            | Self::ModerateMessages(channel_id)
+            | Self::ManageMembers(channel_id) => Some(*channel_id),
+            Self::CreateChannel | Self::ManageRoles | Self::ViewEmails => None,
+        }
+    }
+
+    /// Checks the permission for a user with the role
+    /// and the role in the channel of the permission
+    /// (`None` if the user is not a member).
+    pub fn allowed(&self, role: UserRole, member: Option<MemberRole>) -> bool {
+        match role {
+            UserRole::Owner => return true,
+            UserRole::Admin => return *self != Self::ManageRoles,
+            UserRole::Member | UserRole::Guest => {}
+        }
+        let guest = role == UserRole::Guest;
+        match self {
+            Self::CreateChannel | Self::JoinChannel(_) => !guest,
+            Self::AddMembers(_) => !guest && member.is_some(),
+            Self::DeleteChannel(_)
+            | Self::RemoveMembers(_)
+            | Self::ModerateMessages(_)
+            | Self::ManageMembers(_) => member == Some(MemberRole::Owner),
+            Self::ManageRoles | Self::ViewEmails => false,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateChannel => write!(f, "create channels"),
+            Self::DeleteChannel(channel_id) => write!(f, "delete channel {:?}", channel_id),
+            Self::JoinChannel(channel_id) => write!(f, "join channel {:?}", channel_id),
+            Self::AddMembers(channel_id) => write!(f, "add members to channel {:?}", channel_id),
+            Self::RemoveMembers(channel_id) => {
+                write!(f, "remove members from channel {:?}", channel_id)
+            }
+            Self::ModerateMessages(channel_id) => {
+                write!(f, "moderate messages in channel {:?}", channel_id)
+            }
+            Self::ManageMembers(channel_id) => {
+                write!(f, "manage members of channel {:?}", channel_id)
+            }
+            Self::ManageRoles => write!(f, "manage roles"),
+            Self::ViewEmails => write!(f, "view emails"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_permissions() {
+        let channel_id = ChannelId::from(1);
+        let moderate = Permission::ModerateMessages(channel_id);
+        assert!(moderate.allowed(UserRole::Admin, None));
+        assert!(moderate.allowed(UserRole::Member, Some(MemberRole::Owner)));
+        assert!(!moderate.allowed(UserRole::Member, Some(MemberRole::Member)));
+        let join = Permission::JoinChannel(channel_id);
+        assert!(join.allowed(UserRole::Member, None));
+        assert!(!join.allowed(UserRole::Guest, None));
+        let add = Permission::AddMembers(channel_id);
+        assert!(add.allowed(UserRole::Member, Some(MemberRole::Member)));
+        assert!(!add.allowed(UserRole::Member, None));
+        assert!(!Permission::ManageRoles.allowed(UserRole::Admin, None));
+        assert!(Permission::ManageRoles.allowed(UserRole::Owner, None));
+        assert!(Permission::ViewEmails.allowed(UserRole::Admin, None));
+        assert!(!Permission::ViewEmails.allowed(UserRole::Member, None));
+    }
+}
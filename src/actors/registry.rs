@@ -0,0 +1,154 @@
+//! This module contains a registry of live connections.
+//! `EngineActor` uses it to route deltas to every connection
+//! of the users that are members of a channel or to connections
+//! that explicitly subscribed to a channel.
+
+use crate::db::types::{ChannelId, SessionId, TokenId, UserId};
+use protocol::{Delta, LoginUpdate};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+pub type ConnectionId = u64;
+
+pub type LiveSender = mpsc::UnboundedSender<Delta>;
+
+/// Credentials a connection logged in with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Login {
+    Session(SessionId),
+    Token(TokenId),
+}
+
+struct Connection {
+    user_id: UserId,
+    login: Login,
+    sender: LiveSender,
+    subscriptions: HashSet<ChannelId>,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    next_id: ConnectionId,
+    connections: HashMap<ConnectionId, Connection>,
+}
+
+impl Registry {
+    pub fn register(&mut self, user_id: UserId, login: Login, sender: LiveSender) -> ConnectionId {
+        self.next_id += 1;
+        let id = self.next_id;
+        log::trace!("Registering connection {} of user {:?}", id, user_id);
+        let connection = Connection {
+            user_id,
+            login,
+            sender,
+            subscriptions: HashSet::new(),
+        };
+        self.connections.insert(id, connection);
+        id
+    }
+
+    pub fn unregister(&mut self, id: ConnectionId) {
+        log::trace!("Unregistering connection {}", id);
+        self.connections.remove(&id);
+    }
+
+    fn disconnect(&mut self, id: ConnectionId) {
+        if let Some(connection) = self.connections.remove(&id) {
+            log::trace!("Disconnecting connection {}", id);
+            let delta = Delta::LoginUpdate(LoginUpdate::LoggedOut);
+            connection.sender.send(delta).ok();
+        }
+    }
+
+    pub fn subscribe(&mut self, id: ConnectionId, channel_id: ChannelId) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.subscriptions.insert(channel_id);
+        }
+    }
+
+    pub fn unsubscribe(&mut self, id: ConnectionId, channel_id: ChannelId) {
+        if let Some(connection) = self.connections.get_mut(&id) {
+            connection.subscriptions.remove(&channel_id);
+        }
+    }
+
+    /// Unsubscribes all connections of the user from a channel.
+    pub fn unsubscribe_user(&mut self, user_id: UserId, channel_id: ChannelId) {
+        for connection in self.connections.values_mut() {
+            if connection.user_id == user_id {
+                connection.subscriptions.remove(&channel_id);
+            }
+        }
+    }
+
+    /// Sends `LoggedOut` to connections of the sessions and removes
+    /// them from the registry. Connections close themselves after that.
+    pub fn disconnect_sessions(&mut self, sessions: &[SessionId]) {
+        let logins: HashSet<_> = sessions.iter().cloned().map(Login::Session).collect();
+        self.disconnect_where(|conn| logins.contains(&conn.login));
+    }
+
+    /// Disconnects connections that logged in with the token.
+    pub fn disconnect_token(&mut self, token_id: TokenId) {
+        self.disconnect_where(|conn| conn.login == Login::Token(token_id));
+    }
+
+    /// Disconnects connections of the user that logged in with tokens.
+    pub fn disconnect_tokens(&mut self, user_id: UserId) {
+        self.disconnect_where(|conn| {
+            conn.user_id == user_id && matches!(conn.login, Login::Token(_))
+        });
+    }
+
+    fn disconnect_where<F>(&mut self, filter: F)
+    where
+        F: Fn(&Connection) -> bool,
+    {
+        let ids: Vec<_> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| filter(conn))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.disconnect(id);
+        }
+    }
+
+    /// Returns `true` if the user has at least one live connection.
+    pub fn is_online(&self, user_id: UserId) -> bool {
+        self.connections
+            .values()
+            .any(|conn| conn.user_id == user_id)
+    }
+
+    /// Sends a delta to all connections of the users.
+    pub fn send_to_users(&mut self, users: &[UserId], delta: Delta) {
+        let users: HashSet<_> = users.iter().collect();
+        self.send_where(delta, |conn| users.contains(&conn.user_id));
+    }
+
+    /// Sends a delta to connections of the members of a channel
+    /// and to connections subscribed to that channel.
+    pub fn send_to_channel(&mut self, channel_id: ChannelId, members: &[UserId], delta: Delta) {
+        let members: HashSet<_> = members.iter().collect();
+        self.send_where(delta, |conn| {
+            members.contains(&conn.user_id) || conn.subscriptions.contains(&channel_id)
+        });
+    }
+
+    fn send_where<F>(&mut self, delta: Delta, filter: F)
+    where
+        F: Fn(&Connection) -> bool,
+    {
+        let mut closed = Vec::new();
+        for (id, connection) in self.connections.iter() {
+            if filter(connection) && connection.sender.send(delta.clone()).is_err() {
+                closed.push(*id);
+            }
+        }
+        for id in closed {
+            self.unregister(id);
+        }
+    }
+}
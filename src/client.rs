@@ -1,4 +1,5 @@
-use crate::control::Controller;
+use crate::config::Config;
+use crate::control::{socket_path, Controller};
 use crate::opts::*;
 use anyhow::Error;
 
@@ -12,12 +13,71 @@ impl Client {
     }
 
     pub async fn run(self) -> Result<(), Error> {
-        let mut controller = Controller::connect("127.0.0.1:3020").await?;
+        let config = Config::load(self.opts.config.as_deref())?;
+        let target = self
+            .opts
+            .ctrl
+            .clone()
+            .unwrap_or_else(|| socket_path(&config.data_dir).to_string_lossy().into_owned());
+        let mut controller = Controller::connect(&target, &config.data_dir).await?;
         match self.opts.subcmd {
             SubCommand::User(user_command) => match user_command.subcmd {
                 UserSubCommand::Create(cmd) => {
                     controller.create_user(cmd.username, cmd.password).await?;
                 }
+                UserSubCommand::Role(cmd) => {
+                    controller.set_user_role(cmd.username, cmd.role).await?;
+                }
+                UserSubCommand::List => {
+                    println!("Users:");
+                    let users = controller.get_users().await?;
+                    for user in users {
+                        let status = if user.disabled { " (disabled)" } else { "" };
+                        println!("{} {}{}", user.username, user.role, status);
+                    }
+                }
+                UserSubCommand::Show(cmd) => {
+                    let (user, channels) = controller.get_user(cmd.username).await?;
+                    println!("Id: {}", user.id);
+                    println!("Username: {}", user.username);
+                    println!("Role: {}", user.role);
+                    println!("Disabled: {}", user.disabled);
+                    println!("Channels:");
+                    for channel in channels {
+                        println!("{}", channel);
+                    }
+                }
+                UserSubCommand::Passwd(cmd) => {
+                    controller
+                        .update_password(cmd.username, cmd.password)
+                        .await?;
+                }
+                UserSubCommand::Rename(cmd) => {
+                    controller
+                        .rename_user(cmd.username, cmd.new_username)
+                        .await?;
+                }
+                UserSubCommand::Disable(cmd) => {
+                    controller.disable_user(cmd.username).await?;
+                }
+                UserSubCommand::Enable(cmd) => {
+                    controller.enable_user(cmd.username).await?;
+                }
+                UserSubCommand::Delete(cmd) => {
+                    controller.delete_user(cmd.username).await?;
+                }
+            },
+            SubCommand::Invite(invite_command) => match invite_command.subcmd {
+                InviteSubCommand::Create(cmd) => {
+                    let lifetime = cmd.expires.map(|days| i64::from(days) * 24 * 60 * 60);
+                    let token = controller.create_invite(cmd.uses, lifetime).await?;
+                    println!("Token: {}", token);
+                    println!(
+                        "Link: {}/index.html?invite={}",
+                        cmd.url.trim_end_matches('/'),
+                        token
+                    );
+                }
             },
             SubCommand::Channel(channel_command) => match channel_command.subcmd {
                 ChannelSubCommand::Create(cmd) => {
@@ -33,6 +93,106 @@ impl Client {
                 ChannelSubCommand::Delete(cmd) => {
                     controller.delete_channel(cmd.channel).await?;
                 }
+                ChannelSubCommand::AddMember(cmd) => {
+                    controller.add_member(cmd.channel, cmd.username).await?;
+                }
+                ChannelSubCommand::RemoveMember(cmd) => {
+                    controller.remove_member(cmd.channel, cmd.username).await?;
+                }
+                ChannelSubCommand::Members(cmd) => {
+                    println!("Members:");
+                    let members = controller.get_members(cmd.channel).await?;
+                    for member in members {
+                        println!("{}", member);
+                    }
+                }
+                ChannelSubCommand::Role(cmd) => {
+                    controller
+                        .set_member_role(cmd.channel, cmd.username, cmd.role)
+                        .await?;
+                }
+            },
+            SubCommand::Session(session_command) => match session_command.subcmd {
+                SessionSubCommand::List(cmd) => {
+                    println!("Sessions:");
+                    let sessions = controller.get_sessions(cmd.username).await?;
+                    for session in sessions {
+                        println!(
+                            "{} created: {} last seen: {}",
+                            session.id, session.created_at, session.last_seen
+                        );
+                    }
+                }
+                SessionSubCommand::Revoke(cmd) => {
+                    controller.revoke_session(cmd.session).await?;
+                }
+                SessionSubCommand::RevokeAll(cmd) => {
+                    controller.revoke_sessions(cmd.username).await?;
+                }
+            },
+            SubCommand::Token(token_command) => match token_command.subcmd {
+                TokenSubCommand::Create(cmd) => {
+                    let lifetime = cmd.expires.map(|days| i64::from(days) * 24 * 60 * 60);
+                    let (key, token) = controller
+                        .create_api_token(cmd.username, cmd.name, cmd.scope, lifetime)
+                        .await?;
+                    println!("Id: {}", token.id);
+                    println!("Token: {}", key);
+                }
+                TokenSubCommand::List(cmd) => {
+                    println!("Tokens:");
+                    let tokens = controller.get_api_tokens(cmd.username).await?;
+                    for token in tokens {
+                        let last_used = token
+                            .last_used
+                            .map(|time| time.to_string())
+                            .unwrap_or_else(|| "never".into());
+                        let expires = token
+                            .expires_at
+                            .map(|time| format!(" expires: {}", time))
+                            .unwrap_or_default();
+                        println!(
+                            "{} {} {} created: {} last used: {}{}",
+                            token.id, token.name, token.scope, token.created_at, last_used, expires
+                        );
+                    }
+                }
+                TokenSubCommand::Revoke(cmd) => {
+                    controller.revoke_api_token(cmd.token).await?;
+                }
+            },
+            SubCommand::Lockout(lockout_command) => match lockout_command.subcmd {
+                LockoutSubCommand::List => {
+                    println!("Lockouts:");
+                    let lockouts = controller.get_lockouts().await?;
+                    for lockout in lockouts {
+                        println!(
+                            "{} failures: {} remaining: {}s",
+                            lockout.target, lockout.failures, lockout.remaining
+                        );
+                    }
+                }
+                LockoutSubCommand::Clear(cmd) => {
+                    controller.clear_lockouts(cmd.user, cmd.address).await?;
+                }
+            },
+            SubCommand::Audit(audit_command) => match audit_command.subcmd {
+                AuditSubCommand::List(cmd) => {
+                    println!("Audit records:");
+                    let records = controller
+                        .get_audit(cmd.since, cmd.actor, cmd.action)
+                        .await?;
+                    for record in records {
+                        let details = record
+                            .details
+                            .map(|details| format!(" ({})", details))
+                            .unwrap_or_default();
+                        println!(
+                            "{} {} {} {}{}",
+                            record.created_at, record.actor, record.action, record.target, details
+                        );
+                    }
+                }
             },
             _ => {
                 unreachable!();
@@ -0,0 +1,347 @@
+//! Settings of a server. Every value has a default, so a config file
+//! is optional. Values of the file are overridden by environment
+//! variables and then by options of the command line.
+
+use crate::db::DATA_DIR;
+use anyhow::Error;
+use log::LevelFilter;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Path to a config file if `--config` is not set.
+const CONFIG_ENV: &str = "TODY_CONFIG";
+
+const DATA_DIR_ENV: &str = "TODY_DATA_DIR";
+
+const LOG_LEVEL_ENV: &str = "TODY_LOG_LEVEL";
+
+const LIVE_ADDRESS_ENV: &str = "TODY_LIVE_ADDRESS";
+
+const CTRL_ADDRESS_ENV: &str = "TODY_CTRL_ADDRESS";
+
+const SESSION_IDLE_ENV: &str = "TODY_SESSION_IDLE";
+
+const SESSION_LIFETIME_ENV: &str = "TODY_SESSION_LIFETIME";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("can't read config {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid value of {var}: {value}")]
+    InvalidEnv { var: &'static str, value: String },
+    #[error("invalid log level: {0}")]
+    InvalidLogLevel(String),
+    #[error("{0} must be positive")]
+    InvalidLimit(&'static str),
+    #[error("both tls_cert and tls_key are required")]
+    IncompleteTls,
+    #[error("{name} is not a file: {path}")]
+    MissingFile { name: &'static str, path: PathBuf },
+    #[error("data directory {path} is not usable: {reason}")]
+    UnusableDataDir { path: PathBuf, reason: &'static str },
+    #[error("live and ctrl can't listen on the same address: {0}")]
+    SameAddress(SocketAddr),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory with the database, the admin token and the ctrl socket.
+    pub data_dir: PathBuf,
+    /// Used if `RUST_LOG` is not set.
+    pub log_level: String,
+    pub live: LiveConfig,
+    pub ctrl: CtrlConfig,
+    pub limits: LimitsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from(DATA_DIR),
+            log_level: "info".into(),
+            live: LiveConfig::default(),
+            ctrl: CtrlConfig::default(),
+            limits: LimitsConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveConfig {
+    /// Address of the web app and the live WebSocket.
+    pub address: SocketAddr,
+    /// PEM file with a certificate chain to serve HTTPS.
+    pub tls_cert: Option<PathBuf>,
+    /// PEM file with a private key of the certificate.
+    pub tls_key: Option<PathBuf>,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            address: ([127, 0, 0, 1], 3030).into(),
+            tls_cert: None,
+            tls_key: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CtrlConfig {
+    /// TCP address in addition to the Unix socket in the data directory.
+    pub address: Option<SocketAddr>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Seconds before an unused session expires.
+    pub session_idle: i64,
+    /// Seconds before a session expires since a login.
+    pub session_lifetime: i64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            session_idle: 14 * 24 * 60 * 60,
+            session_lifetime: 90 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file at `path` or at `TODY_CONFIG` (defaults if none is set)
+    /// and applies overrides of environment variables.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from));
+        let mut config = {
+            if let Some(path) = path {
+                Self::read(&path)?
+            } else {
+                Self::default()
+            }
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(value) = env::var_os(DATA_DIR_ENV) {
+            self.data_dir = value.into();
+        }
+        if let Ok(value) = env::var(LOG_LEVEL_ENV) {
+            self.log_level = value;
+        }
+        if let Some(address) = parse_env(LIVE_ADDRESS_ENV)? {
+            self.live.address = address;
+        }
+        if let Some(address) = parse_env(CTRL_ADDRESS_ENV)? {
+            self.ctrl.address = Some(address);
+        }
+        if let Some(seconds) = parse_env(SESSION_IDLE_ENV)? {
+            self.limits.session_idle = seconds;
+        }
+        if let Some(seconds) = parse_env(SESSION_LIFETIME_ENV)? {
+            self.limits.session_lifetime = seconds;
+        }
+        Ok(())
+    }
+
+    /// Checks values that can't be checked by types.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        self.log_level_filter()?;
+        if self.limits.session_idle <= 0 {
+            return Err(ConfigError::InvalidLimit("session_idle"));
+        }
+        if self.limits.session_lifetime <= 0 {
+            return Err(ConfigError::InvalidLimit("session_lifetime"));
+        }
+        if self.live.tls_cert.is_some() != self.live.tls_key.is_some() {
+            return Err(ConfigError::IncompleteTls);
+        }
+        let tls_files = [
+            ("tls_cert", &self.live.tls_cert),
+            ("tls_key", &self.live.tls_key),
+        ];
+        for &(name, path) in tls_files.iter() {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    let path = path.clone();
+                    return Err(ConfigError::MissingFile { name, path });
+                }
+            }
+        }
+        self.check_data_dir()?;
+        if self.ctrl.address == Some(self.live.address) {
+            return Err(ConfigError::SameAddress(self.live.address));
+        }
+        Ok(())
+    }
+
+    /// The directory is created on start, so a missing one is usable
+    /// if its nearest existing parent is a writable directory.
+    fn check_data_dir(&self) -> Result<(), ConfigError> {
+        let existing = self
+            .data_dir
+            .ancestors()
+            .map(|path| {
+                if path.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    path
+                }
+            })
+            .find(|path| fs::symlink_metadata(path).is_ok());
+        let reason = match existing.map(fs::metadata) {
+            Some(Ok(metadata)) if !metadata.is_dir() => "not a directory",
+            Some(Ok(metadata)) if metadata.permissions().readonly() => "read-only",
+            Some(Ok(_)) => return Ok(()),
+            _ => "can't be accessed",
+        };
+        Err(ConfigError::UnusableDataDir {
+            path: self.data_dir.clone(),
+            reason,
+        })
+    }
+
+    pub fn log_level_filter(&self) -> Result<LevelFilter, ConfigError> {
+        self.log_level
+            .parse()
+            .map_err(|_| ConfigError::InvalidLogLevel(self.log_level.clone()))
+    }
+}
+
+/// Loads a config like `run` does and prints resulting settings.
+pub fn check(path: Option<&Path>) -> Result<(), Error> {
+    let config = Config::load(path)?;
+    config.check()?;
+    println!("Data directory: {}", config.data_dir.display());
+    println!("Log level: {}", config.log_level);
+    println!("Live address: {}", config.live.address);
+    if let (Some(cert), Some(key)) = (&config.live.tls_cert, &config.live.tls_key) {
+        println!("TLS: {} {}", cert.display(), key.display());
+    }
+    if let Some(address) = config.ctrl.address {
+        println!("Ctrl address: {}", address);
+    }
+    println!("Session idle: {}s", config.limits.session_idle);
+    println!("Session lifetime: {}s", config.limits.session_lifetime);
+    println!("Config is valid.");
+    Ok(())
+}
+
+fn parse_env<T: FromStr>(var: &'static str) -> Result<Option<T>, ConfigError> {
+    match env::var(var) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidEnv { var, value }),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() -> Result<(), Error> {
+        let config: Config = toml::from_str(
+            r#"
+            data_dir = "/var/lib/tody-chat"
+            log_level = "debug"
+
+            [live]
+            address = "192.168.1.10:3030"
+
+            [ctrl]
+            address = "127.0.0.1:3020"
+
+            [limits]
+            session_idle = 3600
+            "#,
+        )?;
+        config.check()?;
+        assert_eq!(config.data_dir, Path::new("/var/lib/tody-chat"));
+        assert_eq!(config.log_level_filter()?, LevelFilter::Debug);
+        assert_eq!(config.live.address, "192.168.1.10:3030".parse()?);
+        assert_eq!(config.ctrl.address, Some("127.0.0.1:3020".parse()?));
+        assert_eq!(config.limits.session_idle, 3600);
+        // Missing values are defaults
+        let default = LimitsConfig::default();
+        assert_eq!(config.limits.session_lifetime, default.session_lifetime);
+        assert_eq!(config.live.tls_cert, None);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid() -> Result<(), Error> {
+        assert!(toml::from_str::<Config>("port = 80").is_err());
+        assert!(toml::from_str::<Config>("[live]\naddress = \"localhost\"").is_err());
+        let config: Config = toml::from_str("log_level = \"loud\"")?;
+        assert!(config.check().is_err());
+        let config: Config = toml::from_str("[limits]\nsession_idle = 0")?;
+        assert!(config.check().is_err());
+        let config: Config = toml::from_str("[live]\ntls_cert = \"cert.pem\"")?;
+        assert!(config.check().is_err());
+        let config: Config = toml::from_str("[ctrl]\naddress = \"127.0.0.1:3030\"")?;
+        assert!(config.check().is_err());
+        Config::default().check()?;
+        Ok(())
+    }
+
+    #[test]
+    fn check_files() -> Result<(), Error> {
+        let dir = env::temp_dir().join(format!("tody-config-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let file = dir.join("cert.pem");
+        fs::write(&file, "")?;
+        let mut config = Config {
+            data_dir: dir.join("data"),
+            ..Default::default()
+        };
+        config.check()?;
+        config.data_dir = file.join("data");
+        assert!(config.check().is_err());
+        config.data_dir = file.clone();
+        assert!(config.check().is_err());
+        config.data_dir = dir.clone();
+        config.live.tls_cert = Some(file.clone());
+        config.live.tls_key = Some(dir.join("key.pem"));
+        assert!(config.check().is_err());
+        config.live.tls_key = Some(file);
+        config.check()?;
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,14 @@
-use crate::db::types::{ChannelName, Password, Username};
+use crate::db::types::{
+    AuditAction, ChannelName, MemberRole, Password, Timestamp, TokenScope, UserRole, Username,
+};
 use crate::network::{wrap, CodecError, NetworkConnection, ProtocolCodec};
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use thiserror::Error;
+use tokio::fs;
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
@@ -11,8 +16,26 @@ pub type ClientProtocol = ProtocolCodec<ClientToController, ControllerToClient>;
 
 pub type ControllerProtocol = ProtocolCodec<ControllerToClient, ClientToController>;
 
+/// Name of a file in the data directory with the admin token.
+const TOKEN_FILE: &str = "admin.token";
+
+pub fn token_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(TOKEN_FILE)
+}
+
+/// Name of the Unix socket in the data directory that the server listens on.
+const SOCKET_FILE: &str = "ctrl.sock";
+
+pub fn socket_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(SOCKET_FILE)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientToController {
+    /// The first message of every connection.
+    Authenticate {
+        token: String,
+    },
     CreateUser {
         username: Username,
         password: Password,
@@ -21,6 +44,30 @@ pub enum ClientToController {
         username: Username,
         password: Password,
     },
+    GetUsers,
+    GetUser {
+        username: Username,
+    },
+    RenameUser {
+        username: Username,
+        new_username: Username,
+    },
+    /// Refuses logins of a user and closes its sessions.
+    DisableUser {
+        username: Username,
+    },
+    EnableUser {
+        username: Username,
+    },
+    DeleteUser {
+        username: Username,
+    },
+    /// Creates an invite for `uses` registrations that expires
+    /// after `lifetime` seconds.
+    CreateInvite {
+        uses: u32,
+        lifetime: Option<i64>,
+    },
     CreateChannel {
         channel: ChannelName,
         username: Username,
@@ -29,24 +76,207 @@ pub enum ClientToController {
     DeleteChannel {
         channel: ChannelName,
     },
+    AddMember {
+        channel: ChannelName,
+        username: Username,
+    },
+    RemoveMember {
+        channel: ChannelName,
+        username: Username,
+    },
+    GetMembers {
+        channel: ChannelName,
+    },
+    SetUserRole {
+        username: Username,
+        role: UserRole,
+    },
+    SetMemberRole {
+        channel: ChannelName,
+        username: Username,
+        role: MemberRole,
+    },
+    GetSessions {
+        username: Username,
+    },
+    RevokeSession {
+        session: u32,
+    },
+    RevokeSessions {
+        username: Username,
+    },
+    /// Creates an API token that expires after `lifetime` seconds.
+    CreateApiToken {
+        username: Username,
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    },
+    GetApiTokens {
+        username: Username,
+    },
+    RevokeApiToken {
+        token: u32,
+    },
+    GetLockouts,
+    /// Clears lockouts of a username and an address or all of them
+    /// if both are not set.
+    ClearLockouts {
+        username: Option<String>,
+        address: Option<IpAddr>,
+    },
+    /// Requests audit records. Empty fields match all records.
+    GetAudit {
+        since: Option<Timestamp>,
+        actor: Option<String>,
+        action: Option<AuditAction>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ControllerToClient {
-    UserCreated { username: Username },
-    PasswordUpdated { username: Username },
-    ChannelCreated { channel: ChannelName },
-    ChannelsList { channels: Vec<ChannelName> },
-    ChannelDeleted { channel: ChannelName },
+    Authenticated,
+    UserCreated {
+        username: Username,
+    },
+    PasswordUpdated {
+        username: Username,
+    },
+    UsersList {
+        users: Vec<UserInfo>,
+    },
+    UserDetails {
+        user: UserInfo,
+        channels: Vec<ChannelName>,
+    },
+    UserRenamed {
+        username: Username,
+        new_username: Username,
+    },
+    UserDisabled {
+        username: Username,
+    },
+    UserEnabled {
+        username: Username,
+    },
+    UserDeleted {
+        username: Username,
+    },
+    InviteCreated {
+        token: String,
+    },
+    ChannelCreated {
+        channel: ChannelName,
+    },
+    ChannelsList {
+        channels: Vec<ChannelName>,
+    },
+    ChannelDeleted {
+        channel: ChannelName,
+    },
+    MemberAdded {
+        channel: ChannelName,
+        username: Username,
+    },
+    MemberRemoved {
+        channel: ChannelName,
+        username: Username,
+    },
+    MembersList {
+        channel: ChannelName,
+        members: Vec<Username>,
+    },
+    UserRoleSet {
+        username: Username,
+        role: UserRole,
+    },
+    MemberRoleSet {
+        channel: ChannelName,
+        username: Username,
+        role: MemberRole,
+    },
+    SessionsList {
+        username: Username,
+        sessions: Vec<SessionInfo>,
+    },
+    SessionRevoked {
+        session: u32,
+    },
+    SessionsRevoked {
+        username: Username,
+    },
+    ApiTokenCreated {
+        /// The key is shown only once.
+        key: String,
+        token: ApiTokenInfo,
+    },
+    ApiTokensList {
+        username: Username,
+        tokens: Vec<ApiTokenInfo>,
+    },
+    ApiTokenRevoked {
+        token: u32,
+    },
+    LockoutsList {
+        lockouts: Vec<LockoutInfo>,
+    },
+    LockoutsCleared,
+    AuditList {
+        records: Vec<AuditInfo>,
+    },
     Fail(String),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserInfo {
+    pub id: u32,
+    pub username: Username,
+    pub role: UserRole,
+    pub disabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionInfo {
+    pub id: u32,
+    pub created_at: Timestamp,
+    pub last_seen: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiTokenInfo {
+    pub id: u32,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: Timestamp,
+    pub last_used: Option<Timestamp>,
+    pub expires_at: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockoutInfo {
+    pub target: String,
+    pub failures: u32,
+    /// Seconds remaining
+    pub remaining: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditInfo {
+    pub created_at: Timestamp,
+    pub actor: String,
+    pub action: AuditAction,
+    pub target: String,
+    pub details: Option<String>,
+}
+
 #[derive(Error, Debug)]
 pub enum ControllerError {
     #[error("unexpected response: {0:?}")]
     UnexpectedResponse(ControllerToClient),
     #[error("no response")]
     NoResponse,
+    #[error("authentication failed: {0}")]
+    AuthenticationFailed(String),
     #[error("io error {0}")]
     IoError(#[from] std::io::Error),
     #[error("codec error {0}")]
@@ -62,10 +292,44 @@ pub struct Controller {
 }
 
 impl Controller {
-    pub async fn connect(address: &str) -> Result<Self, ControllerError> {
-        let stream = TcpStream::connect(address).await?;
-        let connection = wrap(stream);
-        Ok(Self { connection })
+    /// Connects to a server and authenticates with the admin token
+    /// from the data directory. The target is either a TCP address
+    /// or a path to a Unix socket.
+    pub async fn connect(target: &str, data_dir: &Path) -> Result<Self, ControllerError> {
+        let token = fs::read_to_string(token_path(data_dir)).await?;
+        let connection = if let Ok(addr) = target.parse::<SocketAddr>() {
+            wrap(TcpStream::connect(addr).await?)
+        } else {
+            Self::connect_local(target).await?
+        };
+        let mut this = Self { connection };
+        let msg = ClientToController::Authenticate {
+            token: token.trim().to_owned(),
+        };
+        match this.interact(msg).await? {
+            ControllerToClient::Authenticated => Ok(this),
+            ControllerToClient::Fail(reason) => Err(ControllerError::AuthenticationFailed(reason)),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    #[cfg(unix)]
+    async fn connect_local(
+        path: &str,
+    ) -> Result<NetworkConnection<ClientProtocol>, ControllerError> {
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        Ok(wrap(stream))
+    }
+
+    #[cfg(not(unix))]
+    async fn connect_local(
+        _path: &str,
+    ) -> Result<NetworkConnection<ClientProtocol>, ControllerError> {
+        let err = std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "unix sockets are not supported on this platform",
+        );
+        Err(err.into())
     }
 
     async fn interact(
@@ -108,6 +372,84 @@ impl Controller {
         }
     }
 
+    pub async fn get_users(&mut self) -> Result<Vec<UserInfo>, ControllerError> {
+        let msg = ClientToController::GetUsers;
+        match self.interact(msg).await? {
+            ControllerToClient::UsersList { users } => Ok(users),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    /// Returns a user with names of its channels.
+    pub async fn get_user(
+        &mut self,
+        username: Username,
+    ) -> Result<(UserInfo, Vec<ChannelName>), ControllerError> {
+        let msg = ClientToController::GetUser { username };
+        match self.interact(msg).await? {
+            ControllerToClient::UserDetails { user, channels } => Ok((user, channels)),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn rename_user(
+        &mut self,
+        username: Username,
+        new_username: Username,
+    ) -> Result<(), ControllerError> {
+        let expected = new_username.clone();
+        let msg = ClientToController::RenameUser {
+            username,
+            new_username,
+        };
+        match self.interact(msg).await? {
+            ControllerToClient::UserRenamed { new_username, .. } if new_username == expected => {
+                Ok(())
+            }
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn disable_user(&mut self, username: Username) -> Result<(), ControllerError> {
+        let expected = username.clone();
+        let msg = ClientToController::DisableUser { username };
+        match self.interact(msg).await? {
+            ControllerToClient::UserDisabled { username } if username == expected => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn enable_user(&mut self, username: Username) -> Result<(), ControllerError> {
+        let expected = username.clone();
+        let msg = ClientToController::EnableUser { username };
+        match self.interact(msg).await? {
+            ControllerToClient::UserEnabled { username } if username == expected => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn delete_user(&mut self, username: Username) -> Result<(), ControllerError> {
+        let expected = username.clone();
+        let msg = ClientToController::DeleteUser { username };
+        match self.interact(msg).await? {
+            ControllerToClient::UserDeleted { username } if username == expected => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    /// Returns a token of a new invite.
+    pub async fn create_invite(
+        &mut self,
+        uses: u32,
+        lifetime: Option<i64>,
+    ) -> Result<String, ControllerError> {
+        let msg = ClientToController::CreateInvite { uses, lifetime };
+        match self.interact(msg).await? {
+            ControllerToClient::InviteCreated { token } => Ok(token),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
     pub async fn create_channel(
         &mut self,
         channel: ChannelName,
@@ -136,4 +478,172 @@ impl Controller {
             other => Err(ControllerError::UnexpectedResponse(other)),
         }
     }
+
+    pub async fn add_member(
+        &mut self,
+        channel: ChannelName,
+        username: Username,
+    ) -> Result<(), ControllerError> {
+        let msg = ClientToController::AddMember { channel, username };
+        match self.interact(msg).await? {
+            ControllerToClient::MemberAdded { .. } => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn remove_member(
+        &mut self,
+        channel: ChannelName,
+        username: Username,
+    ) -> Result<(), ControllerError> {
+        let msg = ClientToController::RemoveMember { channel, username };
+        match self.interact(msg).await? {
+            ControllerToClient::MemberRemoved { .. } => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn get_members(
+        &mut self,
+        channel: ChannelName,
+    ) -> Result<Vec<Username>, ControllerError> {
+        let msg = ClientToController::GetMembers { channel };
+        match self.interact(msg).await? {
+            ControllerToClient::MembersList { members, .. } => Ok(members),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn set_user_role(
+        &mut self,
+        username: Username,
+        role: UserRole,
+    ) -> Result<(), ControllerError> {
+        let expected = username.clone();
+        let msg = ClientToController::SetUserRole { username, role };
+        match self.interact(msg).await? {
+            ControllerToClient::UserRoleSet { username, .. } if username == expected => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn set_member_role(
+        &mut self,
+        channel: ChannelName,
+        username: Username,
+        role: MemberRole,
+    ) -> Result<(), ControllerError> {
+        let expected = username.clone();
+        let msg = ClientToController::SetMemberRole {
+            channel,
+            username,
+            role,
+        };
+        match self.interact(msg).await? {
+            ControllerToClient::MemberRoleSet { username, .. } if username == expected => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn get_sessions(
+        &mut self,
+        username: Username,
+    ) -> Result<Vec<SessionInfo>, ControllerError> {
+        let msg = ClientToController::GetSessions { username };
+        match self.interact(msg).await? {
+            ControllerToClient::SessionsList { sessions, .. } => Ok(sessions),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn revoke_session(&mut self, session: u32) -> Result<(), ControllerError> {
+        let msg = ClientToController::RevokeSession { session };
+        match self.interact(msg).await? {
+            ControllerToClient::SessionRevoked { .. } => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn revoke_sessions(&mut self, username: Username) -> Result<(), ControllerError> {
+        let msg = ClientToController::RevokeSessions { username };
+        match self.interact(msg).await? {
+            ControllerToClient::SessionsRevoked { .. } => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn create_api_token(
+        &mut self,
+        username: Username,
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    ) -> Result<(String, ApiTokenInfo), ControllerError> {
+        let msg = ClientToController::CreateApiToken {
+            username,
+            name,
+            scope,
+            lifetime,
+        };
+        match self.interact(msg).await? {
+            ControllerToClient::ApiTokenCreated { key, token } => Ok((key, token)),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn get_api_tokens(
+        &mut self,
+        username: Username,
+    ) -> Result<Vec<ApiTokenInfo>, ControllerError> {
+        let msg = ClientToController::GetApiTokens { username };
+        match self.interact(msg).await? {
+            ControllerToClient::ApiTokensList { tokens, .. } => Ok(tokens),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn revoke_api_token(&mut self, token: u32) -> Result<(), ControllerError> {
+        let msg = ClientToController::RevokeApiToken { token };
+        match self.interact(msg).await? {
+            ControllerToClient::ApiTokenRevoked { .. } => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn get_lockouts(&mut self) -> Result<Vec<LockoutInfo>, ControllerError> {
+        let msg = ClientToController::GetLockouts;
+        match self.interact(msg).await? {
+            ControllerToClient::LockoutsList { lockouts } => Ok(lockouts),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn clear_lockouts(
+        &mut self,
+        username: Option<String>,
+        address: Option<IpAddr>,
+    ) -> Result<(), ControllerError> {
+        let msg = ClientToController::ClearLockouts { username, address };
+        match self.interact(msg).await? {
+            ControllerToClient::LockoutsCleared => Ok(()),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
+
+    pub async fn get_audit(
+        &mut self,
+        since: Option<Timestamp>,
+        actor: Option<String>,
+        action: Option<AuditAction>,
+    ) -> Result<Vec<AuditInfo>, ControllerError> {
+        let msg = ClientToController::GetAudit {
+            since,
+            actor,
+            action,
+        };
+        match self.interact(msg).await? {
+            ControllerToClient::AuditList { records } => Ok(records),
+            other => Err(ControllerError::UnexpectedResponse(other)),
+        }
+    }
 }
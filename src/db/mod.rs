@@ -5,6 +5,10 @@
 pub mod types;
 
 mod v0001;
-pub use v0001::{Channel, Dba, DbaError, Session, User};
+pub use v0001::{
+    ApiToken, AuditFilter, AuditRecord, Channel, Dba, DbaError, Message, Profile, Revision,
+    Session, Totp, User,
+};
 
+/// Data directory if it's not configured.
 pub const DATA_DIR: &str = "data";
@@ -1,16 +1,19 @@
 // TODO: Use wrapper over primitives
 
 use rusqlite::{
-    types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef},
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
     Result, ToSql,
 };
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 /// Internal alias
 type Id = u32;
 
 macro_rules! id {
     ( $name:ident ) => {
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
         pub struct $name(Id);
 
         impl ToSql for $name {
@@ -24,13 +27,229 @@ macro_rules! id {
                 Id::column_result(value).map(Self)
             }
         }
+
+        impl From<Id> for $name {
+            fn from(id: Id) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<$name> for Id {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
     };
 }
 
 id!(UserId);
 id!(SessionId);
 id!(ChannelId);
+id!(MessageId);
+id!(TokenId);
 
 pub type Username = String;
 pub type Password = String;
 pub type ChannelName = String;
+pub type Emoji = String;
+/// Hex encoded SHA-256 digest of a session key or an API token.
+pub type KeyHash = String;
+/// Seconds since the Unix epoch.
+pub type Timestamp = i64;
+
+/// Kind of a conversation stored in the `channels` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    /// Named channel.
+    Channel,
+    /// Direct conversation between a fixed set of users.
+    Direct,
+}
+
+impl ToSql for ChannelKind {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        let value: i64 = match self {
+            Self::Channel => 0,
+            Self::Direct => 1,
+        };
+        Ok(ToSqlOutput::from(value))
+    }
+}
+
+impl FromSql for ChannelKind {
+    fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+        match i64::column_result(value)? {
+            0 => Ok(Self::Channel),
+            1 => Ok(Self::Direct),
+            other => Err(FromSqlError::OutOfRange(other)),
+        }
+    }
+}
+
+impl From<ChannelKind> for protocol::ChannelKind {
+    fn from(kind: ChannelKind) -> Self {
+        match kind {
+            ChannelKind::Channel => Self::Channel,
+            ChannelKind::Direct => Self::Direct,
+        }
+    }
+}
+
+/// Implements storing of an enum as an integer code
+/// and parsing it from a text name.
+macro_rules! coded {
+    ( $name:ident, $kind:expr, { $( $variant:ident = $value:expr, $text:expr; )* } ) => {
+        impl ToSql for $name {
+            fn to_sql(&self) -> Result<ToSqlOutput> {
+                let value: i64 = match self {
+                    $( Self::$variant => $value, )*
+                };
+                Ok(ToSqlOutput::from(value))
+            }
+        }
+
+        impl FromSql for $name {
+            fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+                match i64::column_result(value)? {
+                    $( $value => Ok(Self::$variant), )*
+                    other => Err(FromSqlError::OutOfRange(other)),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let text = match self {
+                    $( Self::$variant => $text, )*
+                };
+                f.write_str(text)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s {
+                    $( $text => Ok(Self::$variant), )*
+                    other => Err(format!("unknown {}: {}", $kind, other)),
+                }
+            }
+        }
+    };
+}
+
+/// Role of a user in the workspace.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    /// Has all permissions including changing roles.
+    Owner,
+    /// Can manage and moderate all channels.
+    Admin,
+    Member,
+    /// Can only take part in channels where invited.
+    Guest,
+}
+
+coded!(UserRole, "role", {
+    Owner = 0, "owner";
+    Admin = 1, "admin";
+    Member = 2, "member";
+    Guest = 3, "guest";
+});
+
+/// Role of a user in a channel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberRole {
+    /// Can manage members, moderate and delete the channel.
+    Owner,
+    Member,
+}
+
+coded!(MemberRole, "role", {
+    Owner = 0, "owner";
+    Member = 1, "member";
+});
+
+/// Administrative change recorded to the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    CreateUser,
+    /// A user signed up with an invite.
+    Register,
+    UpdatePassword,
+    RenameUser,
+    DisableUser,
+    EnableUser,
+    DeleteUser,
+    SetUserRole,
+    CreateInvite,
+    CreateChannel,
+    DeleteChannel,
+    AddMember,
+    RemoveMember,
+    SetMemberRole,
+    RevokeSession,
+    RevokeSessions,
+    ClearLockouts,
+    EnableTotp,
+    DisableTotp,
+    CreateToken,
+    RevokeToken,
+}
+
+coded!(AuditAction, "action", {
+    CreateUser = 0, "create-user";
+    Register = 1, "register";
+    UpdatePassword = 2, "update-password";
+    RenameUser = 3, "rename-user";
+    DisableUser = 4, "disable-user";
+    EnableUser = 5, "enable-user";
+    DeleteUser = 6, "delete-user";
+    SetUserRole = 7, "set-user-role";
+    CreateInvite = 8, "create-invite";
+    CreateChannel = 9, "create-channel";
+    DeleteChannel = 10, "delete-channel";
+    AddMember = 11, "add-member";
+    RemoveMember = 12, "remove-member";
+    SetMemberRole = 13, "set-member-role";
+    RevokeSession = 14, "revoke-session";
+    RevokeSessions = 15, "revoke-sessions";
+    ClearLockouts = 16, "clear-lockouts";
+    EnableTotp = 17, "enable-totp";
+    DisableTotp = 18, "disable-totp";
+    CreateToken = 19, "create-token";
+    RevokeToken = 20, "revoke-token";
+});
+
+/// Requests allowed for connections that logged in with an API token.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Fetching and subscribing only.
+    Read,
+    /// Reading, posting and managing channels, but not the account.
+    Write,
+}
+
+coded!(TokenScope, "scope", {
+    Read = 0, "read";
+    Write = 1, "write";
+});
+
+impl From<TokenScope> for protocol::TokenScope {
+    fn from(scope: TokenScope) -> Self {
+        match scope {
+            TokenScope::Read => Self::Read,
+            TokenScope::Write => Self::Write,
+        }
+    }
+}
+
+impl From<protocol::TokenScope> for TokenScope {
+    fn from(scope: protocol::TokenScope) -> Self {
+        match scope {
+            protocol::TokenScope::Read => Self::Read,
+            protocol::TokenScope::Write => Self::Write,
+        }
+    }
+}
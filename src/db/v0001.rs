@@ -1,7 +1,12 @@
-use crate::db::types::{ChannelId, ChannelName, Password, SessionId, UserId, Username};
-use protocol::Key;
-use rusqlite::{params, Connection, Row};
+use crate::db::types::{
+    AuditAction, ChannelId, ChannelKind, ChannelName, Emoji, KeyHash, MemberRole, MessageId,
+    Password, SessionId, Timestamp, TokenId, TokenScope, UserId, UserRole, Username,
+};
+use protocol::{ChannelInfo, MessageInfo, ProfileInfo, RevisionInfo};
+use rusqlite::{params, Connection, Row, ToSql};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Debug, Clone)]
@@ -9,11 +14,24 @@ pub struct User {
     pub id: UserId,
     pub username: Username,
     pub password: Password,
+    pub role: UserRole,
+    /// Disabled users can't log in.
+    pub disabled: bool,
+    /// Deleted users are kept as authors of messages only.
+    pub deleted: bool,
 }
 
 impl User {
     const SELECT_BY_NAME: &'static str =
-        "SELECT id, username, password, email FROM users WHERE username = ?";
+        "SELECT id, username, password, role, disabled, deleted FROM users WHERE username = ?";
+    const SELECT_BY_ID: &'static str =
+        "SELECT id, username, password, role, disabled, deleted FROM users WHERE id = ?";
+    const SELECT_ALL: &'static str = "SELECT id, username, password, role, disabled, deleted
+        FROM users WHERE deleted = 0 ORDER BY username";
+    const SELECT_BY_CHANNEL: &'static str =
+        "SELECT users.id, username, password, users.role, disabled, users.deleted
+        FROM users JOIN members ON members.user_id = users.id
+        WHERE members.channel_id = ?";
 }
 
 impl TryFrom<&Row<'_>> for User {
@@ -24,19 +42,83 @@ impl TryFrom<&Row<'_>> for User {
             id: row.get(0)?,
             username: row.get(1)?,
             password: row.get(2)?,
+            role: row.get(3)?,
+            disabled: row.get(4)?,
+            deleted: row.get(5)?,
         })
     }
 }
 
+/// Optional fields of a user that are visible to other users.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    /// Visible to the user and admins only.
+    pub email: Option<String>,
+    pub timezone: Option<String>,
+    pub status: Option<String>,
+    pub avatar: Option<String>,
+}
+
+impl Profile {
+    const SELECT_BY_USER: &'static str =
+        "SELECT display_name, email, timezone, status, avatar FROM users WHERE id = ?";
+}
+
+impl TryFrom<&Row<'_>> for Profile {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            display_name: row.get(0)?,
+            email: row.get(1)?,
+            timezone: row.get(2)?,
+            status: row.get(3)?,
+            avatar: row.get(4)?,
+        })
+    }
+}
+
+impl From<Profile> for ProfileInfo {
+    fn from(profile: Profile) -> Self {
+        Self {
+            display_name: profile.display_name,
+            email: profile.email,
+            timezone: profile.timezone,
+            status: profile.status,
+            avatar: profile.avatar,
+        }
+    }
+}
+
+impl From<ProfileInfo> for Profile {
+    fn from(info: ProfileInfo) -> Self {
+        Self {
+            display_name: info.display_name,
+            email: info.email,
+            timezone: info.timezone,
+            status: info.status,
+            avatar: info.avatar,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Session {
     pub id: SessionId,
-    pub key: Key,
+    pub key_hash: KeyHash,
     pub user_id: UserId,
+    pub created_at: Timestamp,
+    pub last_seen: Timestamp,
 }
 
 impl Session {
-    const SELECT_BY_KEY: &'static str = "SELECT id, key, user_id FROM sessions WHERE key = ?";
+    const SELECT_BY_KEY_HASH: &'static str =
+        "SELECT id, key_hash, user_id, created_at, last_seen FROM sessions WHERE key_hash = ?";
+    const SELECT_BY_USER: &'static str =
+        "SELECT id, key_hash, user_id, created_at, last_seen FROM sessions WHERE user_id = ?";
+    const SELECT_BY_ID: &'static str =
+        "SELECT id, key_hash, user_id, created_at, last_seen FROM sessions WHERE id = ?";
 }
 
 impl TryFrom<&Row<'_>> for Session {
@@ -45,38 +127,424 @@ impl TryFrom<&Row<'_>> for Session {
     fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
         Ok(Self {
             id: row.get(0)?,
-            key: row.get(1)?,
+            key_hash: row.get(1)?,
+            user_id: row.get(2)?,
+            created_at: row.get(3)?,
+            last_seen: row.get(4)?,
+        })
+    }
+}
+
+/// A long-lived credential of a user for bots and scripts.
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: TokenId,
+    pub key_hash: KeyHash,
+    pub user_id: UserId,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: Timestamp,
+    pub last_used: Option<Timestamp>,
+    /// The token never expires if not set.
+    pub expires_at: Option<Timestamp>,
+}
+
+impl ApiToken {
+    const SELECT_BY_KEY_HASH: &'static str =
+        "SELECT id, key_hash, user_id, name, scope, created_at, last_used, expires_at
+        FROM tokens
+        WHERE key_hash = ? AND (expires_at IS NULL OR expires_at > strftime('%s', 'now'))";
+    const SELECT_BY_ID: &'static str =
+        "SELECT id, key_hash, user_id, name, scope, created_at, last_used, expires_at
+        FROM tokens WHERE id = ?";
+    const SELECT_BY_USER: &'static str =
+        "SELECT id, key_hash, user_id, name, scope, created_at, last_used, expires_at
+        FROM tokens WHERE user_id = ? ORDER BY id";
+}
+
+impl TryFrom<&Row<'_>> for ApiToken {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            key_hash: row.get(1)?,
             user_id: row.get(2)?,
+            name: row.get(3)?,
+            scope: row.get(4)?,
+            created_at: row.get(5)?,
+            last_used: row.get(6)?,
+            expires_at: row.get(7)?,
+        })
+    }
+}
+
+impl From<ApiToken> for protocol::TokenInfo {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            id: token.id.into(),
+            name: token.name,
+            scope: token.scope.into(),
+            created_at: token.created_at,
+            last_used: token.last_used,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+/// Second factor settings of a user.
+#[derive(Debug, Clone)]
+pub struct Totp {
+    /// Base32 encoded secret.
+    pub secret: String,
+    /// Not enabled until the first valid code confirms enrollment.
+    pub enabled: bool,
+    /// The last accepted time step to refuse replays.
+    pub last_step: Option<i64>,
+}
+
+impl Totp {
+    const SELECT_BY_USER: &'static str =
+        "SELECT secret, enabled, last_step FROM totp WHERE user_id = ?";
+}
+
+impl TryFrom<&Row<'_>> for Totp {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            secret: row.get(0)?,
+            enabled: row.get(1)?,
+            last_step: row.get(2)?,
+        })
+    }
+}
+
+/// A record of the append-only audit log.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub created_at: Timestamp,
+    /// Id of an initiator. Empty for `CTRL_ACTOR`.
+    pub actor_id: Option<UserId>,
+    /// Username of an initiator at the time of the change or `CTRL_ACTOR`.
+    pub actor: String,
+    pub action: AuditAction,
+    pub target: String,
+    pub details: Option<String>,
+}
+
+impl AuditRecord {
+    /// Actor of changes made with the ctrl interface.
+    /// It can't clash with usernames.
+    pub const CTRL_ACTOR: &'static str = "[ctrl]";
+
+    const SELECT_FILTERED: &'static str =
+        "SELECT created_at, actor_id, actor, action, target, details FROM audit
+        WHERE (?1 IS NULL OR created_at >= ?1)
+            AND (?2 IS NULL OR actor = ?2
+                OR actor_id = (SELECT id FROM users WHERE username = ?2))
+            AND (?3 IS NULL OR action = ?3)
+        ORDER BY id";
+}
+
+impl TryFrom<&Row<'_>> for AuditRecord {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            created_at: row.get(0)?,
+            actor_id: row.get(1)?,
+            actor: row.get(2)?,
+            action: row.get(3)?,
+            target: row.get(4)?,
+            details: row.get(5)?,
         })
     }
 }
 
+/// Conditions to select audit records. Empty fields match all records.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub since: Option<Timestamp>,
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub id: ChannelId,
     pub channel: ChannelName,
+    pub kind: ChannelKind,
+    /// Shown to users. Usernames of members for direct channels.
+    pub title: String,
+    /// Filled by `Dba` for channels of a member.
+    pub unread: u32,
+    /// Filled by `Dba` for channels of a member.
+    pub mentions: u32,
 }
 
 impl Channel {
-    const SELECT_BY_NAME: &'static str = "SELECT id, name FROM channels WHERE name = ?";
-    const SELECT_ALL: &'static str = "SELECT id, name FROM channels WHERE deleted = 0";
+    const SELECT_BY_NAME: &'static str =
+        "SELECT id, name, kind FROM channels WHERE name = ? AND deleted = 0";
+    const SELECT_BY_ID: &'static str =
+        "SELECT id, name, kind FROM channels WHERE id = ? AND deleted = 0";
+    const SELECT_ALL: &'static str =
+        "SELECT id, name, kind FROM channels WHERE deleted = 0 AND kind = 0";
+    const SELECT_BY_MEMBER: &'static str = "SELECT channels.id, name, kind
+        FROM channels JOIN members ON members.channel_id = channels.id
+        WHERE members.user_id = ? AND deleted = 0";
 }
 
 impl TryFrom<&Row<'_>> for Channel {
     type Error = rusqlite::Error;
 
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        let channel: ChannelName = row.get(1)?;
+        Ok(Self {
+            id: row.get(0)?,
+            title: channel.clone(),
+            channel,
+            kind: row.get(2)?,
+            unread: 0,
+            mentions: 0,
+        })
+    }
+}
+
+impl From<Channel> for ChannelInfo {
+    fn from(channel: Channel) -> Self {
+        Self {
+            id: channel.id.into(),
+            title: channel.title,
+            kind: channel.kind.into(),
+            unread: channel.unread,
+            mentions: channel.mentions,
+        }
+    }
+}
+
+/// Reading progress of a member in a channel.
+#[derive(Debug, Clone)]
+pub struct ReadState {
+    pub last_read: Option<MessageId>,
+    /// Top-level messages of other users after the marker.
+    pub unread: u32,
+    /// Mentions of the member after the marker including replies.
+    pub mentions: u32,
+}
+
+impl ReadState {
+    const SELECT_BY_MEMBER: &'static str = "SELECT last_read,
+        (SELECT COUNT(*) FROM messages
+            WHERE messages.channel_id = members.channel_id
+            AND messages.author_id != members.user_id
+            AND messages.parent_id IS NULL AND messages.deleted = 0
+            AND messages.id > IFNULL(members.last_read, 0)),
+        (SELECT COUNT(*) FROM mentions
+            JOIN messages ON messages.id = mentions.message_id
+            WHERE mentions.user_id = members.user_id
+            AND messages.channel_id = members.channel_id
+            AND messages.deleted = 0
+            AND messages.id > IFNULL(members.last_read, 0))
+        FROM members WHERE channel_id = ? AND user_id = ?";
+}
+
+impl TryFrom<&Row<'_>> for ReadState {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            last_read: row.get(0)?,
+            unread: row.get(1)?,
+            mentions: row.get(2)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: MessageId,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub author: Username,
+    pub body: String,
+    pub created_at: Timestamp,
+    pub edited_at: Option<Timestamp>,
+    /// Parent message if it's a reply in a thread.
+    pub parent_id: Option<MessageId>,
+    pub reply_count: u32,
+    pub last_reply_at: Option<Timestamp>,
+    /// Filled by `Dba` from the `reactions` table.
+    pub reactions: Vec<EmojiCount>,
+}
+
+impl Message {
+    const SELECT_BY_ID: &'static str =
+        "SELECT messages.id, channel_id, author_id, username, body, created_at, edited_at,
+            parent_id, reply_count, last_reply_at
+        FROM messages JOIN users ON users.id = messages.author_id
+        WHERE messages.id = ? AND messages.deleted = 0";
+    const SELECT_LATEST: &'static str =
+        "SELECT messages.id, channel_id, author_id, username, body, created_at, edited_at,
+            parent_id, reply_count, last_reply_at
+        FROM messages JOIN users ON users.id = messages.author_id
+        WHERE channel_id = ? AND parent_id IS NULL AND messages.deleted = 0
+        ORDER BY messages.id DESC LIMIT ?";
+    const SELECT_BEFORE: &'static str =
+        "SELECT messages.id, channel_id, author_id, username, body, created_at, edited_at,
+            parent_id, reply_count, last_reply_at
+        FROM messages JOIN users ON users.id = messages.author_id
+        WHERE channel_id = ? AND parent_id IS NULL AND messages.id < ? AND messages.deleted = 0
+        ORDER BY messages.id DESC LIMIT ?";
+    const SELECT_REPLIES_LATEST: &'static str =
+        "SELECT messages.id, channel_id, author_id, username, body, created_at, edited_at,
+            parent_id, reply_count, last_reply_at
+        FROM messages JOIN users ON users.id = messages.author_id
+        WHERE parent_id = ? AND messages.deleted = 0
+        ORDER BY messages.id DESC LIMIT ?";
+    const SELECT_REPLIES_BEFORE: &'static str =
+        "SELECT messages.id, channel_id, author_id, username, body, created_at, edited_at,
+            parent_id, reply_count, last_reply_at
+        FROM messages JOIN users ON users.id = messages.author_id
+        WHERE parent_id = ? AND messages.id < ? AND messages.deleted = 0
+        ORDER BY messages.id DESC LIMIT ?";
+}
+
+impl TryFrom<&Row<'_>> for Message {
+    type Error = rusqlite::Error;
+
     fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
         Ok(Self {
             id: row.get(0)?,
-            channel: row.get(1)?,
+            channel_id: row.get(1)?,
+            author_id: row.get(2)?,
+            author: row.get(3)?,
+            body: row.get(4)?,
+            created_at: row.get(5)?,
+            edited_at: row.get(6)?,
+            parent_id: row.get(7)?,
+            reply_count: row.get(8)?,
+            last_reply_at: row.get(9)?,
+            reactions: Vec::new(),
+        })
+    }
+}
+
+impl From<Message> for MessageInfo {
+    fn from(message: Message) -> Self {
+        Self {
+            id: message.id.into(),
+            channel: message.channel_id.into(),
+            author: message.author,
+            text: message.body,
+            created_at: message.created_at,
+            edited_at: message.edited_at,
+            parent: message.parent_id.map(Into::into),
+            reply_count: message.reply_count,
+            last_reply_at: message.last_reply_at,
+            reactions: message.reactions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Amount of users that added an emoji to a message.
+#[derive(Debug, Clone)]
+pub struct EmojiCount {
+    pub emoji: Emoji,
+    pub count: u32,
+}
+
+impl EmojiCount {
+    const SELECT_BY_MESSAGE: &'static str = "SELECT emoji, COUNT(*) FROM reactions
+        WHERE message_id = ?
+        GROUP BY emoji ORDER BY MIN(id)";
+}
+
+impl TryFrom<&Row<'_>> for EmojiCount {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            emoji: row.get(0)?,
+            count: row.get(1)?,
+        })
+    }
+}
+
+impl From<EmojiCount> for protocol::EmojiCount {
+    fn from(count: EmojiCount) -> Self {
+        Self {
+            emoji: count.emoji,
+            count: count.count,
+        }
+    }
+}
+
+/// Previous version of an edited message.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub editor: Username,
+    pub body: String,
+    pub created_at: Timestamp,
+}
+
+impl Revision {
+    const SELECT_BY_MESSAGE: &'static str = "SELECT username, body, created_at
+        FROM revisions JOIN users ON users.id = revisions.editor_id
+        WHERE message_id = ?
+        ORDER BY revisions.id";
+}
+
+impl TryFrom<&Row<'_>> for Revision {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            editor: row.get(0)?,
+            body: row.get(1)?,
+            created_at: row.get(2)?,
         })
     }
 }
 
+impl From<Revision> for RevisionInfo {
+    fn from(revision: Revision) -> Self {
+        Self {
+            editor: revision.editor,
+            text: revision.body,
+            created_at: revision.created_at,
+        }
+    }
+}
+
+/// Upgrades of tables created by earlier versions of the schema.
+/// A database of version `N` runs migrations from index `N`.
+/// They skip tables that don't exist yet, `create_tables` makes them.
+const MIGRATIONS: &[fn(&Connection) -> Result<(), DbaError>] = &[
+    unique_members,
+    channel_kinds,
+    message_edits,
+    message_threads,
+    read_markers,
+    session_times,
+    session_key_hashes,
+    roles,
+    user_states,
+    profiles,
+    audit_actors,
+    deleted_channel_names,
+];
+
+/// Version of the schema stored in `PRAGMA user_version`.
+/// Databases created before versions were tracked have `0`.
+const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
 #[derive(Error, Debug)]
 pub enum DbaError {
     #[error("db error: {0}")]
     DbError(#[from] rusqlite::Error),
+    #[error("database schema version {0} is newer than supported")]
+    NewerSchema(i32),
 }
 
 pub struct Dba {
@@ -84,21 +552,36 @@ pub struct Dba {
 }
 
 impl Dba {
+    /// Opens the database file in the data directory.
     #[cfg(not(test))]
-    pub fn open() -> Result<Self, DbaError> {
-        let path = format!("{}/v0001.db3", super::DATA_DIR);
+    pub fn open(data_dir: &Path) -> Result<Self, DbaError> {
+        let path = data_dir.join("v0001.db3");
         let conn = Connection::open(path)?;
         Ok(Self { conn })
     }
 
     #[cfg(test)]
-    pub fn open() -> Result<Self, DbaError> {
+    pub fn open(_data_dir: &Path) -> Result<Self, DbaError> {
         let conn = Connection::open_in_memory()?;
         Ok(Self { conn })
     }
 
     pub fn initialize(&mut self) -> Result<(), DbaError> {
+        let version: i32 = self
+            .conn
+            .query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(DbaError::NewerSchema(version));
+        }
+        // Existing tables are upgraded before new tables and indexes are created
+        let tx = self.conn.transaction()?;
+        for migrate in MIGRATIONS.iter().skip(version as usize) {
+            migrate(&tx)?;
+        }
+        tx.commit()?;
         self.create_tables()?;
+        self.conn
+            .execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
         Ok(())
     }
 
@@ -106,8 +589,18 @@ impl Dba {
         log::debug!("Creating tables...");
         self.create_users_table()?;
         self.create_sessions_table()?;
+        self.create_tokens_table()?;
+        self.create_invites_table()?;
         self.create_channels_table()?;
         self.create_members_table()?;
+        self.create_messages_table()?;
+        self.create_revisions_table()?;
+        self.create_followers_table()?;
+        self.create_reactions_table()?;
+        self.create_mentions_table()?;
+        self.create_audit_table()?;
+        self.create_totp_table()?;
+        self.create_recovery_codes_table()?;
         Ok(())
     }
 
@@ -118,7 +611,14 @@ impl Dba {
                 id INTEGER PRIMARY KEY,
                 username TEXT NOT NULL UNIQUE,
                 password TEXT,
-                email TEXT
+                email TEXT,
+                display_name TEXT,
+                timezone TEXT,
+                status TEXT,
+                avatar TEXT,
+                role INTEGER NOT NULL DEFAULT 2,
+                disabled INTEGER NOT NULL DEFAULT 0,
+                deleted INTEGER NOT NULL DEFAULT 0
             )",
             params![],
         )?;
@@ -130,8 +630,30 @@ impl Dba {
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS sessions (
                 id INTEGER PRIMARY KEY,
-                key TEXT NOT NULL UNIQUE,
+                key_hash TEXT NOT NULL UNIQUE,
+                user_id INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
+        )?;
+        Ok(())
+    }
+
+    fn create_tokens_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating tokens table");
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS tokens (
+                id INTEGER PRIMARY KEY,
+                key_hash TEXT NOT NULL UNIQUE,
                 user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                scope INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used INTEGER,
+                expires_at INTEGER,
                 FOREIGN KEY (user_id)
                     REFERENCES users (id)
             )",
@@ -140,12 +662,28 @@ impl Dba {
         Ok(())
     }
 
+    fn create_invites_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating invites table");
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS invites (
+                id INTEGER PRIMARY KEY,
+                token_hash TEXT NOT NULL UNIQUE,
+                uses_left INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER
+            )",
+            params![],
+        )?;
+        Ok(())
+    }
+
     fn create_channels_table(&mut self) -> Result<(), DbaError> {
         log::debug!("Creating channels table");
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS channels (
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
+                kind INTEGER NOT NULL DEFAULT 0,
                 deleted INTEGER NOT NULL
             )",
             params![],
@@ -160,6 +698,9 @@ impl Dba {
                 id INTEGER PRIMARY KEY,
                 channel_id INTEGER NOT NULL,
                 user_id INTEGER NOT NULL,
+                role INTEGER NOT NULL DEFAULT 1,
+                last_read INTEGER,
+                UNIQUE (channel_id, user_id),
                 FOREIGN KEY (channel_id)
                     REFERENCES channels (id),
                 FOREIGN KEY (user_id)
@@ -170,108 +711,1291 @@ impl Dba {
         Ok(())
     }
 
-    pub fn create_user(&mut self, username: Username) -> Result<(), DbaError> {
-        log::trace!("Creating user: {}", username);
+    fn create_messages_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating messages table");
         self.conn.execute(
-            "INSERT INTO users (username, password) VALUES (?, '')",
-            params![&username],
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY,
+                channel_id INTEGER NOT NULL,
+                author_id INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                edited_at INTEGER,
+                deleted INTEGER NOT NULL DEFAULT 0,
+                parent_id INTEGER,
+                reply_count INTEGER NOT NULL DEFAULT 0,
+                last_reply_at INTEGER,
+                FOREIGN KEY (channel_id)
+                    REFERENCES channels (id),
+                FOREIGN KEY (author_id)
+                    REFERENCES users (id),
+                FOREIGN KEY (parent_id)
+                    REFERENCES messages (id)
+            )",
+            params![],
+        )?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS messages_by_channel
+                ON messages (channel_id, id)",
+            params![],
+        )?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS messages_by_parent
+                ON messages (parent_id, id)",
+            params![],
         )?;
         Ok(())
     }
 
-    pub fn set_password(&mut self, user_id: UserId, password: Password) -> Result<(), DbaError> {
-        log::trace!("Setting password for user: {:?}", user_id);
+    fn create_revisions_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating revisions table");
         self.conn.execute(
-            "UPDATE users SET password = ? WHERE id = ?",
-            params![&password, &user_id],
+            "CREATE TABLE IF NOT EXISTS revisions (
+                id INTEGER PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                editor_id INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (message_id)
+                    REFERENCES messages (id),
+                FOREIGN KEY (editor_id)
+                    REFERENCES users (id)
+            )",
+            params![],
         )?;
         Ok(())
     }
 
-    pub fn get_user(&mut self, name: Username) -> Result<User, DbaError> {
-        log::trace!("Getting user: {}", name);
-        let value = self
-            .conn
-            .query_row(User::SELECT_BY_NAME, params![&name], |row| {
-                User::try_from(row)
-            });
-        log::trace!("Find user result: {:?}", value);
-        value.map_err(DbaError::from)
-    }
-
-    pub fn create_session(&mut self, user_id: UserId, key: Key) -> Result<(), DbaError> {
-        log::trace!("Creating session for: {:?}", user_id);
+    fn create_followers_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating followers table");
         self.conn.execute(
-            "INSERT INTO sessions (user_id, key) VALUES (?, ?)",
-            params![&user_id, &key],
+            "CREATE TABLE IF NOT EXISTS followers (
+                id INTEGER PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                UNIQUE (message_id, user_id),
+                FOREIGN KEY (message_id)
+                    REFERENCES messages (id),
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
         )?;
         Ok(())
     }
 
-    pub fn get_session(&mut self, key: Key) -> Result<Session, DbaError> {
-        log::trace!("Getting sessions: {}", key);
-        let value = self
-            .conn
-            .query_row(Session::SELECT_BY_KEY, params![&key], |row| {
-                Session::try_from(row)
-            });
-        log::trace!("Find sessions result: {:?}", value);
-        value.map_err(DbaError::from)
-    }
-
-    pub fn create_channel(&mut self, name: ChannelName) -> Result<(), DbaError> {
-        log::trace!("Creating channel named: {}", name);
+    fn create_reactions_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating reactions table");
         self.conn.execute(
-            "INSERT INTO channels (name, deleted) VALUES (?, 0)",
-            params![&name],
+            "CREATE TABLE IF NOT EXISTS reactions (
+                id INTEGER PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                emoji TEXT NOT NULL,
+                UNIQUE (message_id, user_id, emoji),
+                FOREIGN KEY (message_id)
+                    REFERENCES messages (id),
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
         )?;
         Ok(())
     }
 
-    pub fn get_channel(&mut self, name: ChannelName) -> Result<Channel, DbaError> {
-        log::trace!("Getting channel: {}", name);
-        let value = self
-            .conn
-            .query_row(Channel::SELECT_BY_NAME, params![&name], |row| {
-                Channel::try_from(row)
-            });
-        log::trace!("Find channel result: {:?}", value);
-        value.map_err(DbaError::from)
+    fn create_mentions_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating mentions table");
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS mentions (
+                id INTEGER PRIMARY KEY,
+                message_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                UNIQUE (message_id, user_id),
+                FOREIGN KEY (message_id)
+                    REFERENCES messages (id),
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
+        )?;
+        Ok(())
     }
 
-    pub fn add_member(&mut self, channel_id: ChannelId, user_id: UserId) -> Result<(), DbaError> {
-        log::trace!("Add user {:?} to channel {:?}", user_id, channel_id);
+    fn create_audit_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating audit table");
         self.conn.execute(
-            "INSERT INTO members (channel_id, user_id) VALUES (?, ?)",
-            params![&channel_id, user_id],
+            "CREATE TABLE IF NOT EXISTS audit (
+                id INTEGER PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                actor_id INTEGER,
+                actor TEXT NOT NULL,
+                action INTEGER NOT NULL,
+                target TEXT NOT NULL,
+                details TEXT
+            )",
+            params![],
+        )?;
+        // Records can't be changed or removed once written
+        self.conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS audit_no_update BEFORE UPDATE ON audit
+            BEGIN
+                SELECT RAISE(ABORT, 'audit log is append-only');
+            END",
+            params![],
+        )?;
+        self.conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS audit_no_delete BEFORE DELETE ON audit
+            BEGIN
+                SELECT RAISE(ABORT, 'audit log is append-only');
+            END",
+            params![],
         )?;
         Ok(())
     }
 
-    pub fn get_channels(&mut self) -> Result<Vec<Channel>, DbaError> {
-        log::trace!("Getting channels");
+    fn create_totp_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating totp table");
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS totp (
+                user_id INTEGER PRIMARY KEY,
+                secret TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 0,
+                last_step INTEGER,
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
+        )?;
+        Ok(())
+    }
+
+    fn create_recovery_codes_table(&mut self) -> Result<(), DbaError> {
+        log::debug!("Creating recovery_codes table");
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS recovery_codes (
+                id INTEGER PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                code_hash TEXT NOT NULL,
+                UNIQUE (user_id, code_hash),
+                FOREIGN KEY (user_id)
+                    REFERENCES users (id)
+            )",
+            params![],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_user(&mut self, username: Username) -> Result<(), DbaError> {
+        log::trace!("Creating user: {}", username);
+        self.conn.execute(
+            "INSERT INTO users (username, password) VALUES (?, '')",
+            params![&username],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_password(&mut self, user_id: UserId, password: Password) -> Result<(), DbaError> {
+        log::trace!("Setting password for user: {:?}", user_id);
+        self.conn.execute(
+            "UPDATE users SET password = ? WHERE id = ?",
+            params![&password, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_user_role(&mut self, user_id: UserId, role: UserRole) -> Result<(), DbaError> {
+        log::trace!("Setting role of user {:?}: {}", user_id, role);
+        self.conn.execute(
+            "UPDATE users SET role = ? WHERE id = ?",
+            params![&role, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_user(&mut self, name: Username) -> Result<User, DbaError> {
+        log::trace!("Getting user: {}", name);
+        let value = self
+            .conn
+            .query_row(User::SELECT_BY_NAME, params![&name], |row| {
+                User::try_from(row)
+            });
+        log::trace!("Find user result: {:?}", value);
+        value.map_err(DbaError::from)
+    }
+
+    pub fn get_user_by_id(&mut self, id: UserId) -> Result<User, DbaError> {
+        log::trace!("Getting user by id: {:?}", id);
+        let value = self
+            .conn
+            .query_row(User::SELECT_BY_ID, params![&id], |row| User::try_from(row));
+        log::trace!("Find user result: {:?}", value);
+        value.map_err(DbaError::from)
+    }
+
+    /// Returns all users except deleted.
+    pub fn get_users(&mut self) -> Result<Vec<User>, DbaError> {
+        log::trace!("Getting users");
+        let mut stmt = self.conn.prepare(User::SELECT_ALL)?;
+        let results = stmt.query_map(params![], |row| User::try_from(row))?;
+        let mut users = Vec::new();
+        for result in results {
+            users.push(result?);
+        }
+        Ok(users)
+    }
+
+    pub fn rename_user(&mut self, user_id: UserId, username: Username) -> Result<(), DbaError> {
+        log::trace!("Renaming user {:?} to {}", user_id, username);
+        self.conn.execute(
+            "UPDATE users SET username = ? WHERE id = ?",
+            params![&username, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_user_disabled(&mut self, user_id: UserId, disabled: bool) -> Result<(), DbaError> {
+        log::trace!("Setting disabled flag of user {:?}: {}", user_id, disabled);
+        self.conn.execute(
+            "UPDATE users SET disabled = ? WHERE id = ?",
+            params![&disabled, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_profile(&mut self, user_id: UserId) -> Result<Profile, DbaError> {
+        log::trace!("Getting profile of user {:?}", user_id);
+        let profile = self
+            .conn
+            .query_row(Profile::SELECT_BY_USER, params![&user_id], |row| {
+                Profile::try_from(row)
+            })?;
+        Ok(profile)
+    }
+
+    pub fn set_profile(&mut self, user_id: UserId, profile: &Profile) -> Result<(), DbaError> {
+        log::trace!("Setting profile of user {:?}", user_id);
+        self.conn.execute(
+            "UPDATE users SET display_name = ?, email = ?, timezone = ?, status = ?, avatar = ?
+                WHERE id = ?",
+            params![
+                &profile.display_name,
+                &profile.email,
+                &profile.timezone,
+                &profile.status,
+                &profile.avatar,
+                &user_id
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes sessions and memberships of a user and frees the username.
+    /// The record remains as an author of messages.
+    pub fn delete_user(&mut self, user_id: UserId) -> Result<(), DbaError> {
+        log::trace!("Deleting user {:?}", user_id);
+        let tables = &[
+            "sessions",
+            "tokens",
+            "members",
+            "followers",
+            "reactions",
+            "mentions",
+            "totp",
+            "recovery_codes",
+        ];
+        for table in tables {
+            let query = format!("DELETE FROM {} WHERE user_id = ?", table);
+            self.conn.execute(&query, params![&user_id])?;
+        }
+        // The password is not a valid hash, so it never matches
+        self.conn.execute(
+            "UPDATE users SET username = '[deleted ' || id || ']', password = '$argon2id$deleted',
+                email = NULL, display_name = NULL, timezone = NULL, status = NULL, avatar = NULL,
+                disabled = 1, deleted = 1
+                WHERE id = ?",
+            params![&user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Starts a new enrollment. Replaces a previous secret and recovery codes.
+    pub fn set_totp_secret(&mut self, user_id: UserId, secret: &str) -> Result<(), DbaError> {
+        log::trace!("Setting TOTP secret of user {:?}", user_id);
+        self.conn.execute(
+            "DELETE FROM recovery_codes WHERE user_id = ?",
+            params![&user_id],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO totp (user_id, secret, enabled, last_step)
+                VALUES (?, ?, 0, NULL)",
+            params![&user_id, secret],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_totp(&mut self, user_id: UserId) -> Result<Totp, DbaError> {
+        log::trace!("Getting TOTP of user {:?}", user_id);
+        let totp = self
+            .conn
+            .query_row(Totp::SELECT_BY_USER, params![&user_id], |row| {
+                Totp::try_from(row)
+            })?;
+        Ok(totp)
+    }
+
+    pub fn enable_totp(&mut self, user_id: UserId) -> Result<(), DbaError> {
+        log::trace!("Enabling TOTP of user {:?}", user_id);
+        self.conn.execute(
+            "UPDATE totp SET enabled = 1 WHERE user_id = ?",
+            params![&user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_totp_step(&mut self, user_id: UserId, step: i64) -> Result<(), DbaError> {
+        self.conn.execute(
+            "UPDATE totp SET last_step = ? WHERE user_id = ?",
+            params![&step, &user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the second factor with recovery codes.
+    pub fn delete_totp(&mut self, user_id: UserId) -> Result<(), DbaError> {
+        log::trace!("Deleting TOTP of user {:?}", user_id);
+        self.conn.execute(
+            "DELETE FROM recovery_codes WHERE user_id = ?",
+            params![&user_id],
+        )?;
+        self.conn
+            .execute("DELETE FROM totp WHERE user_id = ?", params![&user_id])?;
+        Ok(())
+    }
+
+    pub fn add_recovery_code(
+        &mut self,
+        user_id: UserId,
+        code_hash: KeyHash,
+    ) -> Result<(), DbaError> {
+        self.conn.execute(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)",
+            params![&user_id, &code_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a recovery code and returns `true` if it existed.
+    pub fn use_recovery_code(
+        &mut self,
+        user_id: UserId,
+        code_hash: KeyHash,
+    ) -> Result<bool, DbaError> {
+        let used = self.conn.execute(
+            "DELETE FROM recovery_codes WHERE user_id = ? AND code_hash = ?",
+            params![&user_id, &code_hash],
+        )?;
+        Ok(used > 0)
+    }
+
+    pub fn add_audit_record(
+        &mut self,
+        actor_id: Option<UserId>,
+        actor: &str,
+        action: AuditAction,
+        target: &str,
+        details: Option<&str>,
+    ) -> Result<(), DbaError> {
+        log::trace!("Audit: {} {} {}", actor, action, target);
+        self.conn.execute(
+            "INSERT INTO audit (created_at, actor_id, actor, action, target, details)
+                VALUES (strftime('%s', 'now'), ?, ?, ?, ?, ?)",
+            params![&actor_id, actor, &action, target, details],
+        )?;
+        Ok(())
+    }
+
+    /// Returns audit records from the oldest to the newest.
+    pub fn get_audit_records(
+        &mut self,
+        filter: &AuditFilter,
+    ) -> Result<Vec<AuditRecord>, DbaError> {
+        log::trace!("Getting audit records: {:?}", filter);
+        let mut stmt = self.conn.prepare(AuditRecord::SELECT_FILTERED)?;
+        let results = stmt.query_map(
+            params![&filter.since, &filter.actor, &filter.action],
+            |row| AuditRecord::try_from(row),
+        )?;
+        let mut records = Vec::new();
+        for result in results {
+            records.push(result?);
+        }
+        Ok(records)
+    }
+
+    pub fn create_session(&mut self, user_id: UserId, key_hash: KeyHash) -> Result<(), DbaError> {
+        log::trace!("Creating session for: {:?}", user_id);
+        self.conn.execute(
+            "INSERT INTO sessions (user_id, key_hash, created_at, last_seen)
+                VALUES (?, ?, strftime('%s', 'now'), strftime('%s', 'now'))",
+            params![&user_id, &key_hash],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_session(&mut self, key_hash: KeyHash) -> Result<Session, DbaError> {
+        log::trace!("Getting session by key hash");
+        let value = self
+            .conn
+            .query_row(Session::SELECT_BY_KEY_HASH, params![&key_hash], |row| {
+                Session::try_from(row)
+            });
+        log::trace!("Find sessions result: {:?}", value);
+        value.map_err(DbaError::from)
+    }
+
+    pub fn get_session_by_id(&mut self, id: SessionId) -> Result<Session, DbaError> {
+        log::trace!("Getting session {:?}", id);
+        self.conn
+            .query_row(Session::SELECT_BY_ID, params![&id], |row| {
+                Session::try_from(row)
+            })
+            .map_err(DbaError::from)
+    }
+
+    pub fn get_user_sessions(&mut self, user_id: UserId) -> Result<Vec<Session>, DbaError> {
+        log::trace!("Getting sessions of {:?}", user_id);
+        let mut stmt = self.conn.prepare(Session::SELECT_BY_USER)?;
+        let results = stmt.query_map(params![&user_id], |row| Session::try_from(row))?;
+        let mut sessions = Vec::new();
+        for result in results {
+            sessions.push(result?);
+        }
+        Ok(sessions)
+    }
+
+    pub fn touch_session(&mut self, id: SessionId) -> Result<(), DbaError> {
+        self.conn.execute(
+            "UPDATE sessions SET last_seen = strftime('%s', 'now') WHERE id = ?",
+            params![&id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns `false` if there was no such session.
+    pub fn delete_session(&mut self, id: SessionId) -> Result<bool, DbaError> {
+        log::trace!("Deleting session {:?}", id);
+        let deleted = self
+            .conn
+            .execute("DELETE FROM sessions WHERE id = ?", params![&id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Removes sessions that were idle or lived longer than
+    /// the specified amounts of seconds.
+    pub fn delete_expired_sessions(&mut self, idle: i64, lifetime: i64) -> Result<(), DbaError> {
+        let deleted = self.conn.execute(
+            "DELETE FROM sessions
+                WHERE last_seen <= strftime('%s', 'now') - ?
+                OR created_at <= strftime('%s', 'now') - ?",
+            params![&idle, &lifetime],
+        )?;
+        log::trace!("Expired sessions deleted: {}", deleted);
+        Ok(())
+    }
+
+    /// Stores a token that expires after `lifetime` seconds (never if `None`).
+    pub fn create_token(
+        &mut self,
+        user_id: UserId,
+        key_hash: KeyHash,
+        name: &str,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    ) -> Result<(), DbaError> {
+        log::trace!("Creating token for: {:?}", user_id);
+        self.conn.execute(
+            "INSERT INTO tokens (user_id, key_hash, name, scope, created_at, expires_at)
+                VALUES (?, ?, ?, ?, strftime('%s', 'now'), strftime('%s', 'now') + ?)",
+            params![&user_id, &key_hash, name, &scope, &lifetime],
+        )?;
+        Ok(())
+    }
+
+    /// Returns a token by the key hash if it's not expired.
+    pub fn get_token(&mut self, key_hash: KeyHash) -> Result<ApiToken, DbaError> {
+        log::trace!("Getting token by key hash");
+        self.conn
+            .query_row(ApiToken::SELECT_BY_KEY_HASH, params![&key_hash], |row| {
+                ApiToken::try_from(row)
+            })
+            .map_err(DbaError::from)
+    }
+
+    pub fn get_token_by_id(&mut self, id: TokenId) -> Result<ApiToken, DbaError> {
+        log::trace!("Getting token {:?}", id);
+        self.conn
+            .query_row(ApiToken::SELECT_BY_ID, params![&id], |row| {
+                ApiToken::try_from(row)
+            })
+            .map_err(DbaError::from)
+    }
+
+    pub fn get_user_tokens(&mut self, user_id: UserId) -> Result<Vec<ApiToken>, DbaError> {
+        log::trace!("Getting tokens of {:?}", user_id);
+        let mut stmt = self.conn.prepare(ApiToken::SELECT_BY_USER)?;
+        let results = stmt.query_map(params![&user_id], |row| ApiToken::try_from(row))?;
+        let mut tokens = Vec::new();
+        for result in results {
+            tokens.push(result?);
+        }
+        Ok(tokens)
+    }
+
+    pub fn touch_token(&mut self, id: TokenId) -> Result<(), DbaError> {
+        log::trace!("Touching token {:?}", id);
+        self.conn.execute(
+            "UPDATE tokens SET last_used = strftime('%s', 'now') WHERE id = ?",
+            params![&id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns `false` if there was no such token.
+    pub fn delete_token(&mut self, id: TokenId) -> Result<bool, DbaError> {
+        log::trace!("Deleting token {:?}", id);
+        let deleted = self
+            .conn
+            .execute("DELETE FROM tokens WHERE id = ?", params![&id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Stores an invite that expires after `lifetime` seconds (never if `None`).
+    pub fn create_invite(
+        &mut self,
+        token_hash: KeyHash,
+        uses: u32,
+        lifetime: Option<i64>,
+    ) -> Result<(), DbaError> {
+        log::trace!("Creating invite for {} uses", uses);
+        self.conn.execute(
+            "INSERT INTO invites (token_hash, uses_left, created_at, expires_at)
+                VALUES (?, ?, strftime('%s', 'now'), strftime('%s', 'now') + ?)",
+            params![&token_hash, &uses, &lifetime],
+        )?;
+        Ok(())
+    }
+
+    /// Takes a use of an invite and creates a user in a single transaction.
+    /// Returns `None` if the invite is invalid or expired.
+    pub fn create_invited_user(
+        &mut self,
+        username: Username,
+        password: Password,
+        token_hash: KeyHash,
+    ) -> Result<Option<User>, DbaError> {
+        log::trace!("Creating invited user: {}", username);
+        let tx = self.conn.transaction()?;
+        let changed = tx.execute(
+            "UPDATE invites SET uses_left = uses_left - 1
+                WHERE token_hash = ? AND uses_left > 0
+                AND (expires_at IS NULL OR expires_at > strftime('%s', 'now'))",
+            params![&token_hash],
+        )?;
+        if changed == 0 {
+            return Ok(None);
+        }
+        tx.execute(
+            "INSERT INTO users (username, password) VALUES (?, ?)",
+            params![&username, &password],
+        )?;
+        tx.commit()?;
+        self.get_user(username).map(Some)
+    }
+
+    pub fn create_channel(&mut self, name: ChannelName, kind: ChannelKind) -> Result<(), DbaError> {
+        log::trace!("Creating channel named: {}", name);
+        self.conn.execute(
+            "INSERT INTO channels (name, kind, deleted) VALUES (?, ?, 0)",
+            params![&name, &kind],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_channel(&mut self, name: ChannelName) -> Result<Channel, DbaError> {
+        log::trace!("Getting channel: {}", name);
+        let value = self
+            .conn
+            .query_row(Channel::SELECT_BY_NAME, params![&name], |row| {
+                Channel::try_from(row)
+            });
+        log::trace!("Find channel result: {:?}", value);
+        value.map_err(DbaError::from)
+    }
+
+    /// Returns a channel by id if it wasn't deleted.
+    pub fn get_channel_by_id(&mut self, id: ChannelId) -> Result<Channel, DbaError> {
+        log::trace!("Getting channel by id: {:?}", id);
+        let value = self
+            .conn
+            .query_row(Channel::SELECT_BY_ID, params![&id], |row| {
+                Channel::try_from(row)
+            });
+        log::trace!("Find channel result: {:?}", value);
+        value.map_err(DbaError::from)
+    }
+
+    pub fn add_member(&mut self, channel_id: ChannelId, user_id: UserId) -> Result<(), DbaError> {
+        log::trace!("Add user {:?} to channel {:?}", user_id, channel_id);
+        self.conn.execute(
+            "INSERT INTO members (channel_id, user_id) VALUES (?, ?)",
+            params![&channel_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_member(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<(), DbaError> {
+        log::trace!("Remove user {:?} from channel {:?}", user_id, channel_id);
+        self.conn.execute(
+            "DELETE FROM members WHERE channel_id = ? AND user_id = ?",
+            params![&channel_id, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_member_role(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        role: MemberRole,
+    ) -> Result<(), DbaError> {
+        log::trace!(
+            "Setting role of user {:?} in channel {:?}: {}",
+            user_id,
+            channel_id,
+            role
+        );
+        self.conn.execute(
+            "UPDATE members SET role = ? WHERE channel_id = ? AND user_id = ?",
+            params![&role, &channel_id, &user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns `QueryReturnedNoRows` if the user is not a member.
+    pub fn get_member_role(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<MemberRole, DbaError> {
+        log::trace!(
+            "Getting role of user {:?} in channel {:?}",
+            user_id,
+            channel_id
+        );
+        let role = self.conn.query_row(
+            "SELECT role FROM members WHERE channel_id = ? AND user_id = ?",
+            params![&channel_id, &user_id],
+            |row| row.get(0),
+        )?;
+        Ok(role)
+    }
+
+    pub fn get_members(&mut self, channel_id: ChannelId) -> Result<Vec<User>, DbaError> {
+        log::trace!("Getting members of channel {:?}", channel_id);
+        let mut stmt = self.conn.prepare(User::SELECT_BY_CHANNEL)?;
+        let results = stmt.query_map(params![&channel_id], |row| User::try_from(row))?;
+        let mut members = Vec::new();
+        for result in results {
+            members.push(result?);
+        }
+        Ok(members)
+    }
+
+    pub fn is_member(&mut self, channel_id: ChannelId, user_id: UserId) -> Result<bool, DbaError> {
+        log::trace!("Checking user {:?} in channel {:?}", user_id, channel_id);
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM members WHERE channel_id = ? AND user_id = ?",
+            params![&channel_id, &user_id],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    pub fn get_member_ids(&mut self, channel_id: ChannelId) -> Result<Vec<UserId>, DbaError> {
+        log::trace!("Getting members of channel {:?}", channel_id);
+        let mut stmt = self
+            .conn
+            .prepare("SELECT user_id FROM members WHERE channel_id = ?")?;
+        let results = stmt.query_map(params![&channel_id], |row| row.get(0))?;
+        let mut members = Vec::new();
+        for result in results {
+            members.push(result?);
+        }
+        Ok(members)
+    }
+
+    pub fn get_channels(&mut self) -> Result<Vec<Channel>, DbaError> {
+        log::trace!("Getting channels");
         let mut stmt = self.conn.prepare(Channel::SELECT_ALL)?;
         let results = stmt.query_map(params![], |row| Channel::try_from(row))?;
         let mut channels = Vec::new();
         for result in results {
-            channels.push(result?);
+            channels.push(result?);
+        }
+        Ok(channels)
+    }
+
+    /// Returns all channels and direct conversations of a user.
+    pub fn get_user_channels(&mut self, user_id: UserId) -> Result<Vec<Channel>, DbaError> {
+        log::trace!("Getting channels of user {:?}", user_id);
+        let mut channels = Vec::new();
+        {
+            let mut stmt = self.conn.prepare(Channel::SELECT_BY_MEMBER)?;
+            let results = stmt.query_map(params![&user_id], |row| Channel::try_from(row))?;
+            for result in results {
+                channels.push(result?);
+            }
+        }
+        for channel in channels.iter_mut() {
+            let state = self.get_read_state(channel.id, user_id)?;
+            channel.unread = state.unread;
+            channel.mentions = state.mentions;
+            if channel.kind == ChannelKind::Direct {
+                channel.title = self.get_direct_title(channel.id)?;
+            }
+        }
+        Ok(channels)
+    }
+
+    /// Returns usernames of members of a direct channel separated by commas.
+    pub fn get_direct_title(&mut self, channel_id: ChannelId) -> Result<String, DbaError> {
+        log::trace!("Getting title of direct channel {:?}", channel_id);
+        let title = self.conn.query_row(
+            "SELECT group_concat(username, ', ') FROM (
+                SELECT username FROM users JOIN members ON members.user_id = users.id
+                WHERE members.channel_id = ? ORDER BY username
+            )",
+            params![&channel_id],
+            |row| row.get::<_, Option<String>>(0),
+        )?;
+        Ok(title.unwrap_or_default())
+    }
+
+    /// Moves the read marker forward only.
+    pub fn mark_read(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        message_id: MessageId,
+    ) -> Result<(), DbaError> {
+        log::trace!(
+            "User {:?} read {:?} up to {:?}",
+            user_id,
+            channel_id,
+            message_id
+        );
+        self.conn.execute(
+            "UPDATE members SET last_read = ?1
+                WHERE channel_id = ?2 AND user_id = ?3 AND IFNULL(last_read, 0) < ?1",
+            params![&message_id, &channel_id, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_read_state(
+        &mut self,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Result<ReadState, DbaError> {
+        let mut stmt = self.conn.prepare_cached(ReadState::SELECT_BY_MEMBER)?;
+        let state = stmt.query_row(params![&channel_id, &user_id], |row| {
+            ReadState::try_from(row)
+        })?;
+        Ok(state)
+    }
+
+    /// Marks a channel as deleted and frees its name for new channels.
+    pub fn delete_channel(&mut self, name: ChannelName) -> Result<(), DbaError> {
+        log::trace!("Deleting channel named: {}", name);
+        self.conn.execute(
+            "UPDATE channels SET name = '[deleted ' || id || ']', deleted = 1
+                WHERE name = ? AND deleted = 0",
+            params![&name],
+        )?;
+        Ok(())
+    }
+
+    pub fn create_message(
+        &mut self,
+        channel_id: ChannelId,
+        author_id: UserId,
+        body: String,
+    ) -> Result<MessageId, DbaError> {
+        log::trace!("Creating message in channel {:?}", channel_id);
+        self.conn.execute(
+            "INSERT INTO messages (channel_id, author_id, body, created_at)
+                VALUES (?, ?, ?, strftime('%s', 'now'))",
+            params![&channel_id, &author_id, &body],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        let id = MessageId::from(id as u32);
+        Ok(id)
+    }
+
+    pub fn get_message(&mut self, id: MessageId) -> Result<Message, DbaError> {
+        log::trace!("Getting message: {:?}", id);
+        let value = self
+            .conn
+            .query_row(Message::SELECT_BY_ID, params![&id], |row| {
+                Message::try_from(row)
+            });
+        log::trace!("Find message result: {:?}", value);
+        let mut message = value?;
+        message.reactions = self.get_emoji_counts(message.id)?;
+        Ok(message)
+    }
+
+    /// Keeps the current body of a message as a revision and replaces it.
+    pub fn edit_message(
+        &mut self,
+        id: MessageId,
+        editor_id: UserId,
+        body: String,
+    ) -> Result<(), DbaError> {
+        log::trace!("Editing message: {:?}", id);
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO revisions (message_id, editor_id, body, created_at)
+                SELECT id, ?, body, strftime('%s', 'now') FROM messages WHERE id = ?",
+            params![&editor_id, &id],
+        )?;
+        tx.execute(
+            "UPDATE messages SET body = ?, edited_at = strftime('%s', 'now') WHERE id = ?",
+            params![&body, &id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Marks a message as deleted and updates counters of a thread if it's a reply.
+    pub fn delete_message(&mut self, id: MessageId) -> Result<(), DbaError> {
+        log::trace!("Deleting message: {:?}", id);
+        let tx = self.conn.transaction()?;
+        tx.execute("UPDATE messages SET deleted = 1 WHERE id = ?", params![&id])?;
+        tx.execute(
+            "UPDATE messages SET reply_count = reply_count - 1,
+                last_reply_at = (SELECT MAX(created_at) FROM messages AS replies
+                    WHERE replies.parent_id = messages.id AND replies.deleted = 0)
+                WHERE id = (SELECT parent_id FROM messages WHERE id = ?)",
+            params![&id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_revisions(&mut self, id: MessageId) -> Result<Vec<Revision>, DbaError> {
+        log::trace!("Getting revisions of message: {:?}", id);
+        let mut stmt = self.conn.prepare(Revision::SELECT_BY_MESSAGE)?;
+        let results = stmt.query_map(params![&id], |row| Revision::try_from(row))?;
+        let mut revisions = Vec::new();
+        for result in results {
+            revisions.push(result?);
         }
-        Ok(channels)
+        Ok(revisions)
     }
 
-    pub fn delete_channel(&mut self, name: ChannelName) -> Result<(), DbaError> {
-        log::trace!("Deleting channel named: {}", name);
+    /// Creates a reply in the thread of a parent message
+    /// and updates counters of the parent.
+    pub fn create_reply(
+        &mut self,
+        parent_id: MessageId,
+        author_id: UserId,
+        body: String,
+    ) -> Result<MessageId, DbaError> {
+        log::trace!("Creating reply to message {:?}", parent_id);
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO messages (channel_id, author_id, body, created_at, parent_id)
+                SELECT channel_id, ?, ?, strftime('%s', 'now'), id FROM messages WHERE id = ?",
+            params![&author_id, &body, &parent_id],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "UPDATE messages SET reply_count = reply_count + 1,
+                last_reply_at = (SELECT created_at FROM messages WHERE id = ?)
+                WHERE id = ?",
+            params![&id, &parent_id],
+        )?;
+        tx.commit()?;
+        let id = MessageId::from(id as u32);
+        Ok(id)
+    }
+
+    /// Returns up to `limit` messages older than `before` starting from the newest.
+    /// Replies in threads are not included.
+    pub fn get_messages(
+        &mut self,
+        channel_id: ChannelId,
+        before: Option<MessageId>,
+        limit: u32,
+    ) -> Result<Vec<Message>, DbaError> {
+        log::trace!(
+            "Getting messages of channel {:?} before {:?}",
+            channel_id,
+            before
+        );
+        if let Some(before) = before {
+            self.query_messages(
+                Message::SELECT_BEFORE,
+                params![&channel_id, &before, &limit],
+            )
+        } else {
+            self.query_messages(Message::SELECT_LATEST, params![&channel_id, &limit])
+        }
+    }
+
+    /// Returns up to `limit` replies to a message older than `before`
+    /// starting from the newest.
+    pub fn get_replies(
+        &mut self,
+        parent_id: MessageId,
+        before: Option<MessageId>,
+        limit: u32,
+    ) -> Result<Vec<Message>, DbaError> {
+        log::trace!("Getting replies to {:?} before {:?}", parent_id, before);
+        if let Some(before) = before {
+            self.query_messages(
+                Message::SELECT_REPLIES_BEFORE,
+                params![&parent_id, &before, &limit],
+            )
+        } else {
+            self.query_messages(Message::SELECT_REPLIES_LATEST, params![&parent_id, &limit])
+        }
+    }
+
+    fn query_messages(
+        &mut self,
+        query: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<Message>, DbaError> {
+        let mut messages = Vec::new();
+        {
+            let mut stmt = self.conn.prepare(query)?;
+            let results = stmt.query_map(params, |row| Message::try_from(row))?;
+            for result in results {
+                messages.push(result?);
+            }
+        }
+        let ids: Vec<_> = messages.iter().map(|message| message.id).collect();
+        let mut reactions = self.get_page_emoji_counts(&ids)?;
+        for message in messages.iter_mut() {
+            message.reactions = reactions.remove(&message.id).unwrap_or_default();
+        }
+        Ok(messages)
+    }
+
+    pub fn add_emoji(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        emoji: Emoji,
+    ) -> Result<(), DbaError> {
+        log::trace!("User {:?} reacts {} to {:?}", user_id, emoji, message_id);
         self.conn.execute(
-            "UPDATE channels SET deleted = 1 WHERE name = ?",
-            params![&name],
+            "INSERT OR IGNORE INTO reactions (message_id, user_id, emoji) VALUES (?, ?, ?)",
+            params![&message_id, &user_id, &emoji],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_emoji(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+        emoji: Emoji,
+    ) -> Result<(), DbaError> {
+        log::trace!("User {:?} removes {} from {:?}", user_id, emoji, message_id);
+        self.conn.execute(
+            "DELETE FROM reactions WHERE message_id = ? AND user_id = ? AND emoji = ?",
+            params![&message_id, &user_id, &emoji],
+        )?;
+        Ok(())
+    }
+
+    /// Returns amounts of every emoji added to a message.
+    pub fn get_emoji_counts(&mut self, message_id: MessageId) -> Result<Vec<EmojiCount>, DbaError> {
+        let mut stmt = self.conn.prepare_cached(EmojiCount::SELECT_BY_MESSAGE)?;
+        let results = stmt.query_map(params![&message_id], |row| EmojiCount::try_from(row))?;
+        let mut counts = Vec::new();
+        for result in results {
+            counts.push(result?);
+        }
+        Ok(counts)
+    }
+
+    /// Returns counts of emoji of many messages with a single query.
+    fn get_page_emoji_counts(
+        &mut self,
+        message_ids: &[MessageId],
+    ) -> Result<HashMap<MessageId, Vec<EmojiCount>>, DbaError> {
+        let mut counts: HashMap<_, Vec<_>> = HashMap::new();
+        if message_ids.is_empty() {
+            return Ok(counts);
+        }
+        let placeholders = vec!["?"; message_ids.len()].join(", ");
+        let query = format!(
+            "SELECT message_id, emoji, COUNT(*) FROM reactions
+            WHERE message_id IN ({})
+            GROUP BY message_id, emoji ORDER BY MIN(id)",
+            placeholders
+        );
+        let mut stmt = self.conn.prepare(&query)?;
+        let results = stmt.query_map(message_ids, |row| {
+            let message_id: MessageId = row.get(0)?;
+            let count = EmojiCount {
+                emoji: row.get(1)?,
+                count: row.get(2)?,
+            };
+            Ok((message_id, count))
+        })?;
+        for result in results {
+            let (message_id, count) = result?;
+            counts.entry(message_id).or_default().push(count);
+        }
+        Ok(counts)
+    }
+
+    pub fn follow_thread(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<(), DbaError> {
+        log::trace!("User {:?} follows thread {:?}", user_id, message_id);
+        self.conn.execute(
+            "INSERT OR IGNORE INTO followers (message_id, user_id) VALUES (?, ?)",
+            params![&message_id, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn unfollow_thread(
+        &mut self,
+        message_id: MessageId,
+        user_id: UserId,
+    ) -> Result<(), DbaError> {
+        log::trace!("User {:?} unfollows thread {:?}", user_id, message_id);
+        self.conn.execute(
+            "DELETE FROM followers WHERE message_id = ? AND user_id = ?",
+            params![&message_id, &user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_follower_ids(&mut self, message_id: MessageId) -> Result<Vec<UserId>, DbaError> {
+        log::trace!("Getting followers of thread {:?}", message_id);
+        let mut stmt = self
+            .conn
+            .prepare("SELECT user_id FROM followers WHERE message_id = ?")?;
+        let results = stmt.query_map(params![&message_id], |row| row.get(0))?;
+        let mut followers = Vec::new();
+        for result in results {
+            followers.push(result?);
+        }
+        Ok(followers)
+    }
+
+    pub fn add_mention(&mut self, message_id: MessageId, user_id: UserId) -> Result<(), DbaError> {
+        log::trace!("User {:?} mentioned in {:?}", user_id, message_id);
+        self.conn.execute(
+            "INSERT OR IGNORE INTO mentions (message_id, user_id) VALUES (?, ?)",
+            params![&message_id, &user_id],
         )?;
         Ok(())
     }
+
+    pub fn get_mentioned_ids(&mut self, message_id: MessageId) -> Result<Vec<UserId>, DbaError> {
+        log::trace!("Getting mentioned users of {:?}", message_id);
+        let mut stmt = self
+            .conn
+            .prepare("SELECT user_id FROM mentions WHERE message_id = ?")?;
+        let results = stmt.query_map(params![&message_id], |row| row.get(0))?;
+        let mut user_ids = Vec::new();
+        for result in results {
+            user_ids.push(result?);
+        }
+        Ok(user_ids)
+    }
+}
+
+/// Members of a channel are unique.
+fn unique_members(conn: &Connection) -> Result<(), DbaError> {
+    if table_columns(conn, "members")?.is_empty() {
+        return Ok(());
+    }
+    conn.execute(
+        "DELETE FROM members WHERE id NOT IN
+            (SELECT MIN(id) FROM members GROUP BY channel_id, user_id)",
+        params![],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS members_unique ON members (channel_id, user_id)",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Channels have kinds to tell direct conversations.
+fn channel_kinds(conn: &Connection) -> Result<(), DbaError> {
+    add_column(conn, "channels", "kind", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+/// Messages can be edited and deleted.
+fn message_edits(conn: &Connection) -> Result<(), DbaError> {
+    add_column(conn, "messages", "edited_at", "INTEGER")?;
+    add_column(conn, "messages", "deleted", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+/// Messages can have threads of replies.
+fn message_threads(conn: &Connection) -> Result<(), DbaError> {
+    add_column(
+        conn,
+        "messages",
+        "parent_id",
+        "INTEGER REFERENCES messages (id)",
+    )?;
+    add_column(
+        conn,
+        "messages",
+        "reply_count",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column(conn, "messages", "last_reply_at", "INTEGER")?;
+    Ok(())
+}
+
+/// Members have read markers. Existing messages are not counted as unread.
+fn read_markers(conn: &Connection) -> Result<(), DbaError> {
+    let messages_exist = !table_columns(conn, "messages")?.is_empty();
+    if add_column(conn, "members", "last_read", "INTEGER")? && messages_exist {
+        conn.execute(
+            "UPDATE members SET last_read =
+                (SELECT MAX(id) FROM messages WHERE messages.channel_id = members.channel_id)",
+            params![],
+        )?;
+    }
+    Ok(())
+}
+
+/// Sessions have times of the login and of the last visit.
+fn session_times(conn: &Connection) -> Result<(), DbaError> {
+    if add_column(conn, "sessions", "created_at", "INTEGER NOT NULL DEFAULT 0")? {
+        conn.execute(
+            "UPDATE sessions SET created_at = strftime('%s', 'now')",
+            params![],
+        )?;
+    }
+    if add_column(conn, "sessions", "last_seen", "INTEGER NOT NULL DEFAULT 0")? {
+        conn.execute("UPDATE sessions SET last_seen = created_at", params![])?;
+    }
+    Ok(())
+}
+
+/// Sessions keep hashes of keys. Sessions with plain keys are dropped.
+fn session_key_hashes(conn: &Connection) -> Result<(), DbaError> {
+    if table_columns(conn, "sessions")?
+        .iter()
+        .any(|name| name == "key")
+    {
+        conn.execute("DELETE FROM sessions", params![])?;
+        conn.execute(
+            "ALTER TABLE sessions RENAME COLUMN key TO key_hash",
+            params![],
+        )?;
+    }
+    Ok(())
+}
+
+/// Users and members have roles.
+fn roles(conn: &Connection) -> Result<(), DbaError> {
+    add_column(conn, "users", "role", "INTEGER NOT NULL DEFAULT 2")?;
+    add_column(conn, "members", "role", "INTEGER NOT NULL DEFAULT 1")?;
+    Ok(())
+}
+
+/// Users can be disabled and deleted.
+fn user_states(conn: &Connection) -> Result<(), DbaError> {
+    add_column(conn, "users", "disabled", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "users", "deleted", "INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+/// Users have profiles.
+fn profiles(conn: &Connection) -> Result<(), DbaError> {
+    for column in ["display_name", "timezone", "status", "avatar"].iter() {
+        add_column(conn, "users", column, "TEXT")?;
+    }
+    Ok(())
+}
+
+/// Audit records keep ids of actors. Older records keep only usernames:
+/// the log is append-only and users could be renamed since then.
+fn audit_actors(conn: &Connection) -> Result<(), DbaError> {
+    add_column(conn, "audit", "actor_id", "INTEGER")?;
+    Ok(())
+}
+
+/// Deleted channels don't keep names, so the names can be taken again.
+fn deleted_channel_names(conn: &Connection) -> Result<(), DbaError> {
+    if table_columns(conn, "channels")?.is_empty() {
+        return Ok(());
+    }
+    conn.execute(
+        "UPDATE channels SET name = '[deleted ' || id || ']' WHERE deleted = 1",
+        params![],
+    )?;
+    Ok(())
+}
+
+/// Returns names of columns of a table. Empty if the table doesn't exist.
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, DbaError> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let results = stmt.query_map(params![], |row| row.get(1))?;
+    let mut columns = Vec::new();
+    for result in results {
+        columns.push(result?);
+    }
+    Ok(columns)
+}
+
+/// Adds a column to an existing table if it's missing. Returns `true` if added.
+fn add_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, DbaError> {
+    let columns = table_columns(conn, table)?;
+    if columns.is_empty() || columns.iter().any(|name| name == column) {
+        return Ok(false);
+    }
+    log::debug!("Adding column {} to {} table", column, table);
+    conn.execute(
+        &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+        params![],
+    )?;
+    Ok(true)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::DATA_DIR;
     use std::ops::{Deref, DerefMut};
 
     struct TestDba {
@@ -294,7 +2018,7 @@ mod tests {
 
     impl TestDba {
         fn new() -> Result<Self, DbaError> {
-            let mut dba = Dba::open()?;
+            let mut dba = Dba::open(Path::new(DATA_DIR))?;
             dba.initialize()?;
             let this = Self { dba };
             Ok(this)
@@ -308,16 +2032,17 @@ mod tests {
             Ok(record)
         }
 
-        fn create_test_channel(&mut self) -> Result<Id, DbaError> {
+        fn create_test_channel(&mut self) -> Result<ChannelId, DbaError> {
             let channel = ChannelName::from("channel-1");
-            self.dba.create_channel(channel.clone())?;
+            self.dba
+                .create_channel(channel.clone(), ChannelKind::Channel)?;
             let record = self.dba.get_channel(channel.clone())?;
             Ok(record.id)
         }
     }
 
     fn dba() -> Result<Dba, DbaError> {
-        let mut dba = Dba::open()?;
+        let mut dba = Dba::open(Path::new(DATA_DIR))?;
         dba.initialize()?;
         Ok(dba)
     }
@@ -346,13 +2071,20 @@ mod tests {
     #[test]
     fn session_check() -> Result<(), DbaError> {
         let username = Username::from("username");
-        let key = Key::from("key");
+        let key_hash = KeyHash::from("hash");
         let mut dba = dba()?;
         dba.create_user(username.clone())?;
         let user = dba.get_user(username.clone())?;
-        dba.create_session(user.id, key.clone())?;
-        let session = dba.get_session(key.clone())?;
-        assert_eq!(session.key, key);
+        dba.create_session(user.id, key_hash.clone())?;
+        let session = dba.get_session(key_hash.clone())?;
+        assert_eq!(session.key_hash, key_hash);
+        assert_eq!(session.created_at, session.last_seen);
+        dba.touch_session(session.id)?;
+        dba.delete_expired_sessions(3600, 3600)?;
+        assert_eq!(dba.get_user_sessions(user.id)?.len(), 1);
+        assert_eq!(dba.get_session_by_id(session.id)?.key_hash, key_hash);
+        dba.delete_expired_sessions(3600, 0)?;
+        assert!(dba.get_user_sessions(user.id)?.is_empty());
         Ok(())
     }
 
@@ -360,7 +2092,35 @@ mod tests {
     fn channel_check() -> Result<(), DbaError> {
         let channel = ChannelName::from("channel-1");
         let mut dba = TestDba::new()?;
-        dba.create_channel(channel)?;
+        dba.create_channel(channel, ChannelKind::Channel)?;
+        Ok(())
+    }
+
+    #[test]
+    fn direct_channels() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        dba.add_member(channel_id, user.id)?;
+        let direct = ChannelName::from("@1");
+        dba.create_channel(direct.clone(), ChannelKind::Direct)?;
+        let direct = dba.get_channel(direct)?;
+        assert_eq!(direct.kind, ChannelKind::Direct);
+        dba.add_member(direct.id, user.id)?;
+        let channels = dba.get_channels()?;
+        assert_eq!(channels.len(), 1);
+        let channels = dba.get_user_channels(user.id)?;
+        assert_eq!(channels.len(), 2);
+        let direct = channels
+            .iter()
+            .find(|channel| channel.kind == ChannelKind::Direct)
+            .expect("direct channel");
+        assert_eq!(direct.title, "username");
+        dba.delete_channel("channel-1".into())?;
+        assert!(dba.get_channel("channel-1".into()).is_err());
+        // The name of a deleted channel is free
+        dba.create_channel("channel-1".into(), ChannelKind::Channel)?;
+        assert!(dba.get_channel("channel-1".into()).is_ok());
         Ok(())
     }
 
@@ -372,7 +2132,485 @@ mod tests {
         // TODO: Replace Id with separated ChannelId and UserId.
         // It's possible to confuse them today.
         dba.add_member(channel_id, user.id)?;
-        // TODO: Check member.
+        let members = dba.get_member_ids(channel_id)?;
+        assert_eq!(members, vec![user.id]);
+        assert!(dba.add_member(channel_id, user.id).is_err());
+        let members = dba.get_members(channel_id)?;
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].username, user.username);
+        dba.remove_member(channel_id, user.id)?;
+        assert!(!dba.is_member(channel_id, user.id)?);
+        Ok(())
+    }
+
+    #[test]
+    fn message_posting() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        dba.add_member(channel_id, user.id)?;
+        assert!(dba.is_member(channel_id, user.id)?);
+        let body = String::from("Hello!");
+        let id = dba.create_message(channel_id, user.id, body.clone())?;
+        let message = dba.get_message(id)?;
+        assert_eq!(message.author, user.username);
+        assert_eq!(message.body, body);
+        Ok(())
+    }
+
+    #[test]
+    fn message_history() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        let mut ids = Vec::new();
+        for n in 0..5 {
+            let id = dba.create_message(channel_id, user.id, n.to_string())?;
+            ids.push(id);
+        }
+        let page = dba.get_messages(channel_id, None, 2)?;
+        let page_ids: Vec<_> = page.iter().map(|m| m.id).collect();
+        assert_eq!(page_ids, vec![ids[4], ids[3]]);
+        let page = dba.get_messages(channel_id, Some(ids[3]), 10)?;
+        let page_ids: Vec<_> = page.iter().map(|m| m.id).collect();
+        assert_eq!(page_ids, vec![ids[2], ids[1], ids[0]]);
+        Ok(())
+    }
+
+    #[test]
+    fn message_editing() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        let id = dba.create_message(channel_id, user.id, "first".into())?;
+        dba.edit_message(id, user.id, "second".into())?;
+        let message = dba.get_message(id)?;
+        assert_eq!(message.body, "second");
+        assert!(message.edited_at.is_some());
+        let revisions = dba.get_revisions(id)?;
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].body, "first");
+        dba.delete_message(id)?;
+        assert!(dba.get_message(id).is_err());
+        assert!(dba.get_messages(channel_id, None, 10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn thread_replies() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        let parent_id = dba.create_message(channel_id, user.id, "question".into())?;
+        let reply_id = dba.create_reply(parent_id, user.id, "answer".into())?;
+        let parent = dba.get_message(parent_id)?;
+        assert_eq!(parent.reply_count, 1);
+        assert!(parent.last_reply_at.is_some());
+        let reply = dba.get_message(reply_id)?;
+        assert_eq!(reply.parent_id, Some(parent_id));
+        assert_eq!(reply.channel_id, channel_id);
+        let messages = dba.get_messages(channel_id, None, 10)?;
+        assert_eq!(messages.len(), 1);
+        let replies = dba.get_replies(parent_id, None, 10)?;
+        assert_eq!(replies.len(), 1);
+        let deleted_id = dba.create_reply(parent_id, user.id, "oops".into())?;
+        assert_eq!(dba.get_message(parent_id)?.reply_count, 2);
+        dba.delete_message(deleted_id)?;
+        let parent = dba.get_message(parent_id)?;
+        assert_eq!(parent.reply_count, 1);
+        assert_eq!(
+            parent.last_reply_at,
+            Some(dba.get_message(reply_id)?.created_at)
+        );
+        dba.follow_thread(parent_id, user.id)?;
+        dba.follow_thread(parent_id, user.id)?;
+        assert_eq!(dba.get_follower_ids(parent_id)?, vec![user.id]);
+        dba.unfollow_thread(parent_id, user.id)?;
+        assert!(dba.get_follower_ids(parent_id)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn emoji_reactions() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        let id = dba.create_message(channel_id, user.id, "news".into())?;
+        let emoji = Emoji::from("+1");
+        dba.add_emoji(id, user.id, emoji.clone())?;
+        dba.add_emoji(id, user.id, emoji.clone())?;
+        let message = dba.get_message(id)?;
+        assert_eq!(message.reactions.len(), 1);
+        assert_eq!(message.reactions[0].emoji, emoji);
+        assert_eq!(message.reactions[0].count, 1);
+        let other_id = dba.create_message(channel_id, user.id, "more".into())?;
+        dba.add_emoji(other_id, user.id, "tada".into())?;
+        let messages = dba.get_messages(channel_id, None, 10)?;
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            assert_eq!(message.reactions.len(), 1);
+        }
+        dba.remove_emoji(other_id, user.id, "tada".into())?;
+        dba.remove_emoji(id, user.id, emoji)?;
+        let messages = dba.get_messages(channel_id, None, 10)?;
+        assert!(messages[0].reactions.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn message_mentions() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        let channel_id = dba.create_test_channel()?;
+        let id = dba.create_message(channel_id, user.id, "@user".into())?;
+        assert!(dba.get_mentioned_ids(id)?.is_empty());
+        dba.add_mention(id, user.id)?;
+        dba.add_mention(id, user.id)?;
+        assert_eq!(dba.get_mentioned_ids(id)?, vec![user.id]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_markers() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        dba.create_user("reader".into())?;
+        let reader = dba.get_user("reader".into())?;
+        let channel_id = dba.create_test_channel()?;
+        dba.add_member(channel_id, user.id)?;
+        dba.add_member(channel_id, reader.id)?;
+        let first = dba.create_message(channel_id, user.id, "first".into())?;
+        let second = dba.create_message(channel_id, user.id, "second".into())?;
+        dba.add_mention(second, reader.id)?;
+        let channels = dba.get_user_channels(reader.id)?;
+        assert_eq!(channels[0].unread, 2);
+        assert_eq!(channels[0].mentions, 1);
+        assert_eq!(dba.get_read_state(channel_id, user.id)?.unread, 0);
+        dba.mark_read(channel_id, reader.id, second)?;
+        dba.mark_read(channel_id, reader.id, first)?;
+        let state = dba.get_read_state(channel_id, reader.id)?;
+        assert_eq!(state.last_read, Some(second));
+        assert_eq!(state.unread, 0);
+        assert_eq!(state.mentions, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn roles() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        assert_eq!(user.role, UserRole::Member);
+        dba.set_user_role(user.id, UserRole::Admin)?;
+        assert_eq!(dba.get_user_by_id(user.id)?.role, UserRole::Admin);
+        let channel_id = dba.create_test_channel()?;
+        assert!(dba.get_member_role(channel_id, user.id).is_err());
+        dba.add_member(channel_id, user.id)?;
+        assert_eq!(
+            dba.get_member_role(channel_id, user.id)?,
+            MemberRole::Member
+        );
+        dba.set_member_role(channel_id, user.id, MemberRole::Owner)?;
+        assert_eq!(dba.get_member_role(channel_id, user.id)?, MemberRole::Owner);
+        Ok(())
+    }
+
+    #[test]
+    fn invites() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        dba.create_invite("twice".into(), 2, None)?;
+        dba.create_invite("expired".into(), 1, Some(-1))?;
+        let mut register = |username: &str, invite: &str| {
+            dba.create_invited_user(username.into(), "hash".into(), invite.into())
+        };
+        assert!(register("alice", "twice")?.is_some());
+        // A taken username doesn't take a use
+        assert!(register("alice", "twice").is_err());
+        assert!(register("bob", "twice")?.is_some());
+        assert!(register("carol", "twice")?.is_none());
+        assert!(register("carol", "expired")?.is_none());
+        assert!(register("carol", "unknown")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn user_admin() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        assert!(!user.disabled);
+        dba.set_user_disabled(user.id, true)?;
+        assert!(dba.get_user_by_id(user.id)?.disabled);
+        dba.rename_user(user.id, "renamed".into())?;
+        assert_eq!(dba.get_user("renamed".into())?.id, user.id);
+        let channel_id = dba.create_test_channel()?;
+        dba.add_member(channel_id, user.id)?;
+        dba.create_session(user.id, "hash".into())?;
+        assert_eq!(dba.get_users()?.len(), 1);
+        dba.delete_user(user.id)?;
+        assert!(dba.get_users()?.is_empty());
+        assert!(dba.get_user("renamed".into()).is_err());
+        let deleted = dba.get_user_by_id(user.id)?;
+        assert!(deleted.deleted);
+        assert!(!deleted.password.is_empty());
+        assert!(!dba.is_member(channel_id, user.id)?);
+        assert!(dba.get_user_sessions(user.id)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn profiles() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        assert_eq!(dba.get_profile(user.id)?, Profile::default());
+        let profile = Profile {
+            display_name: Some("Test User".into()),
+            email: Some("test@example.com".into()),
+            timezone: Some("Europe/Berlin".into()),
+            status: Some("On vacation".into()),
+            avatar: Some("https://example.com/avatar.png".into()),
+        };
+        dba.set_profile(user.id, &profile)?;
+        assert_eq!(dba.get_profile(user.id)?, profile);
+        dba.delete_user(user.id)?;
+        assert_eq!(dba.get_profile(user.id)?, Profile::default());
+        Ok(())
+    }
+
+    #[test]
+    fn audit_log() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        dba.add_audit_record(
+            None,
+            AuditRecord::CTRL_ACTOR,
+            AuditAction::CreateUser,
+            "alice",
+            None,
+        )?;
+        dba.add_audit_record(
+            Some(user.id),
+            &user.username,
+            AuditAction::CreateChannel,
+            "general",
+            None,
+        )?;
+        dba.add_audit_record(
+            None,
+            AuditRecord::CTRL_ACTOR,
+            AuditAction::SetUserRole,
+            "alice",
+            Some("role: admin"),
+        )?;
+        let all = dba.get_audit_records(&AuditFilter::default())?;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2].details.as_deref(), Some("role: admin"));
+        let filter = AuditFilter {
+            actor: Some(AuditRecord::CTRL_ACTOR.into()),
+            ..Default::default()
+        };
+        assert_eq!(dba.get_audit_records(&filter)?.len(), 2);
+        let filter = AuditFilter {
+            action: Some(AuditAction::CreateChannel),
+            ..Default::default()
+        };
+        let records = dba.get_audit_records(&filter)?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].actor_id, Some(user.id));
+        assert_eq!(records[0].actor, user.username);
+        // Records of a renamed user are found by the new username
+        dba.rename_user(user.id, "carol".into())?;
+        let filter = AuditFilter {
+            actor: Some("carol".into()),
+            ..Default::default()
+        };
+        assert_eq!(dba.get_audit_records(&filter)?.len(), 1);
+        let filter = AuditFilter {
+            since: Some(all[0].created_at + 3600),
+            ..Default::default()
+        };
+        assert!(dba.get_audit_records(&filter)?.is_empty());
+        assert!(dba.conn.execute("DELETE FROM audit", params![]).is_err());
+        assert!(dba
+            .conn
+            .execute("UPDATE audit SET actor = 'bob'", params![])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn second_factor() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        assert!(dba.get_totp(user.id).is_err());
+        dba.set_totp_secret(user.id, "SECRET")?;
+        dba.add_recovery_code(user.id, "code-hash".into())?;
+        let totp = dba.get_totp(user.id)?;
+        assert!(!totp.enabled);
+        assert_eq!(totp.last_step, None);
+        dba.enable_totp(user.id)?;
+        dba.set_totp_step(user.id, 42)?;
+        let totp = dba.get_totp(user.id)?;
+        assert!(totp.enabled);
+        assert_eq!(totp.last_step, Some(42));
+        assert!(dba.use_recovery_code(user.id, "code-hash".into())?);
+        assert!(!dba.use_recovery_code(user.id, "code-hash".into())?);
+        // A new enrollment resets codes
+        dba.add_recovery_code(user.id, "other-hash".into())?;
+        dba.set_totp_secret(user.id, "NEW")?;
+        assert!(!dba.get_totp(user.id)?.enabled);
+        assert!(!dba.use_recovery_code(user.id, "other-hash".into())?);
+        dba.delete_totp(user.id)?;
+        assert!(dba.get_totp(user.id).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn api_tokens() -> Result<(), DbaError> {
+        let mut dba = TestDba::new()?;
+        let user = dba.create_test_user()?;
+        dba.create_token(user.id, "hash".into(), "bot", TokenScope::Read, None)?;
+        let token = dba.get_token("hash".into())?;
+        assert_eq!(token.name, "bot");
+        assert_eq!(token.scope, TokenScope::Read);
+        assert_eq!(token.last_used, None);
+        dba.touch_token(token.id)?;
+        assert!(dba.get_token_by_id(token.id)?.last_used.is_some());
+        // Expired tokens can't be used, but are listed
+        dba.create_token(user.id, "old".into(), "old", TokenScope::Write, Some(0))?;
+        assert!(dba.get_token("old".into()).is_err());
+        assert_eq!(dba.get_user_tokens(user.id)?.len(), 2);
+        assert!(dba.delete_token(token.id)?);
+        assert!(!dba.delete_token(token.id)?);
+        dba.delete_user(user.id)?;
+        assert!(dba.get_user_tokens(user.id)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_baseline() -> Result<(), DbaError> {
+        fn count(dba: &Dba, sql: &str) -> Result<u32, DbaError> {
+            Ok(dba.conn.query_row(sql, params![], |row| row.get(0))?)
+        }
+
+        let conn = Connection::open_in_memory()?;
+        // Tables of a database created before schema versions were tracked
+        conn.execute_batch(
+            "CREATE TABLE users (
+                id INTEGER PRIMARY KEY,
+                username TEXT NOT NULL UNIQUE,
+                password TEXT,
+                email TEXT
+            );
+            CREATE TABLE sessions (
+                id INTEGER PRIMARY KEY,
+                key TEXT NOT NULL UNIQUE,
+                user_id INTEGER NOT NULL
+            );
+            CREATE TABLE channels (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                deleted INTEGER NOT NULL
+            );
+            CREATE TABLE members (
+                id INTEGER PRIMARY KEY,
+                channel_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY,
+                channel_id INTEGER NOT NULL,
+                author_id INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            INSERT INTO users (username, password) VALUES ('alice', 'secret'), ('bob', '');
+            INSERT INTO sessions (key, user_id) VALUES ('plain-key', 1);
+            INSERT INTO channels (name, deleted) VALUES ('general', 0);
+            INSERT INTO members (channel_id, user_id) VALUES (1, 1), (1, 2), (1, 2);
+            INSERT INTO messages (channel_id, author_id, body, created_at)
+                VALUES (1, 1, 'hello', 1), (1, 1, 'world', 2);",
+        )?;
+        let mut dba = Dba { conn };
+        dba.initialize()?;
+        let version: i32 = dba
+            .conn
+            .query_row("PRAGMA user_version", params![], |row| row.get(0))?;
+        assert_eq!(version, SCHEMA_VERSION);
+        let bob = dba.get_user("bob".into())?;
+        let channel = dba.get_channel("general".into())?;
+        // Duplicates of members are removed
+        assert_eq!(count(&dba, "SELECT COUNT(*) FROM members")?, 2);
+        assert!(dba.add_member(channel.id, bob.id).is_err());
+        assert_eq!(channel.kind, ChannelKind::Channel);
+        let sql = "SELECT COUNT(*) FROM messages WHERE edited_at IS NULL AND deleted = 0";
+        assert_eq!(count(&dba, sql)?, 2);
+        let sql = "SELECT COUNT(*) FROM messages
+            WHERE parent_id IS NULL AND reply_count = 0 AND last_reply_at IS NULL";
+        assert_eq!(count(&dba, sql)?, 2);
+        assert_eq!(dba.get_read_state(channel.id, bob.id)?.unread, 0);
+        // Sessions with plain keys are dropped
+        assert_eq!(count(&dba, "SELECT COUNT(*) FROM sessions")?, 0);
+        let alice = dba.get_user("alice".into())?;
+        dba.create_session(alice.id, "key-hash".into())?;
+        assert!(dba.get_session("key-hash".into()).is_ok());
+        assert_eq!(count(&dba, "SELECT COUNT(*) FROM users WHERE role = 2")?, 2);
+        assert_eq!(
+            count(&dba, "SELECT COUNT(*) FROM members WHERE role = 1")?,
+            2
+        );
+        let sql = "SELECT COUNT(*) FROM users WHERE disabled = 0 AND deleted = 0";
+        assert_eq!(count(&dba, sql)?, 2);
+        assert_eq!(dba.get_profile(alice.id)?, Profile::default());
+        // Migrated databases are opened again without changes
+        dba.initialize()?;
+        dba.conn.execute_batch("PRAGMA user_version = 100")?;
+        assert!(dba.initialize().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_audit_actors() -> Result<(), DbaError> {
+        let conn = Connection::open_in_memory()?;
+        // The audit table of version 10 without ids of actors
+        conn.execute_batch(
+            "CREATE TABLE audit (
+                id INTEGER PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                actor TEXT NOT NULL,
+                action INTEGER NOT NULL,
+                target TEXT NOT NULL,
+                details TEXT
+            );
+            INSERT INTO audit (created_at, actor, action, target)
+                VALUES (1, '[ctrl]', 0, 'alice');
+            PRAGMA user_version = 10;",
+        )?;
+        let mut dba = Dba { conn };
+        dba.initialize()?;
+        let records = dba.get_audit_records(&AuditFilter::default())?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].actor_id, None);
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_deleted_channel_names() -> Result<(), DbaError> {
+        let conn = Connection::open_in_memory()?;
+        // Deleted channels of version 11 keep their names
+        conn.execute_batch(
+            "CREATE TABLE channels (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                kind INTEGER NOT NULL DEFAULT 0,
+                deleted INTEGER NOT NULL
+            );
+            INSERT INTO channels (name, deleted) VALUES ('general', 1), ('random', 0);
+            PRAGMA user_version = 11;",
+        )?;
+        let mut dba = Dba { conn };
+        dba.initialize()?;
+        dba.create_channel("general".into(), ChannelKind::Channel)?;
+        assert!(dba.get_channel("general".into()).is_ok());
+        assert!(dba.get_channel("random".into()).is_ok());
         Ok(())
     }
 }
@@ -1,11 +1,32 @@
+use crate::db::types::KeyHash;
 use protocol::Key;
 use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
 use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// Length of a session key: 43 alphanumeric chars give 256 bits.
+const KEY_LEN: usize = 43;
 
 pub fn alphanumeric(len: usize) -> String {
     thread_rng().sample_iter(&Alphanumeric).take(len).collect()
 }
 
+/// Generates a session key using the OS random generator.
 pub fn generate_key() -> Key {
-    alphanumeric(30)
+    OsRng.sample_iter(&Alphanumeric).take(KEY_LEN).collect()
+}
+
+/// Prefix of API tokens to tell them from session keys.
+pub const TOKEN_PREFIX: &str = "tody_";
+
+/// Generates an API token using the OS random generator.
+pub fn generate_token() -> Key {
+    format!("{}{}", TOKEN_PREFIX, generate_key())
+}
+
+/// Hashes a session key to keep it in a database.
+/// Keys have enough entropy, so salt is not required.
+pub fn hash_key(key: &str) -> KeyHash {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
 }
@@ -3,25 +3,35 @@
 mod actors;
 mod assets;
 mod client;
+mod config;
 mod control;
 mod db;
 mod generators;
+mod mentions;
 mod network;
 mod opts;
+mod passwords;
 mod server;
+mod tls;
+mod totp;
 
 use anyhow::Error;
 use clap::Clap;
 use client::Client;
-use opts::{Opts, SubCommand};
+use opts::{ConfigSubCommand, Opts, SubCommand};
 use server::Server;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let opts: Opts = Opts::parse();
-    if let SubCommand::Run = opts.subcmd {
-        Server::new(opts).run().await
-    } else {
-        Client::new(opts).run().await
+    match opts.subcmd {
+        SubCommand::Run(_) => Server::new(opts).run().await,
+        SubCommand::Config(ref cmd) => match cmd.subcmd {
+            ConfigSubCommand::Check(ref check) => {
+                let path = check.config.as_deref().or_else(|| opts.config.as_deref());
+                config::check(path)
+            }
+        },
+        _ => Client::new(opts).run().await,
     }
 }
@@ -0,0 +1,88 @@
+//! Parser of mentions in messages.
+
+use crate::db::types::Username;
+use std::collections::HashSet;
+
+/// Mentions all members of a channel.
+const CHANNEL: &str = "channel";
+/// Mentions members of a channel that are online.
+const HERE: &str = "here";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Mentions {
+    pub usernames: HashSet<Username>,
+    pub channel: bool,
+    pub here: bool,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Checks that users can mention the name.
+pub fn is_valid_username(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(is_name_char)
+        && !name.ends_with('.')
+        && name != CHANNEL
+        && name != HERE
+}
+
+/// Finds `@username`, `@channel` and `@here` mentions in a text.
+/// A mention has to start at the beginning of a word.
+pub fn parse(text: &str) -> Mentions {
+    let mut mentions = Mentions::default();
+    let mut prev = None;
+    for (idx, c) in text.char_indices() {
+        let starts_word = prev.map(|p| !is_name_char(p) && p != '@').unwrap_or(true);
+        prev = Some(c);
+        if c != '@' || !starts_word {
+            continue;
+        }
+        let rest = &text[idx + c.len_utf8()..];
+        let end = rest
+            .find(|c| !is_name_char(c))
+            .unwrap_or_else(|| rest.len());
+        // Dots at the end belong to a sentence
+        let name = rest[..end].trim_end_matches('.');
+        match name {
+            "" => {}
+            CHANNEL => mentions.channel = true,
+            HERE => mentions.here = true,
+            name => {
+                mentions.usernames.insert(name.to_owned());
+            }
+        }
+    }
+    mentions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mentions() {
+        let mentions = parse("@alice, please ask @bob.smith and @channel.");
+        assert!(mentions.usernames.contains("alice"));
+        assert!(mentions.usernames.contains("bob.smith"));
+        assert_eq!(mentions.usernames.len(), 2);
+        assert!(mentions.channel);
+        assert!(!mentions.here);
+    }
+
+    #[test]
+    fn valid_usernames() {
+        assert!(is_valid_username("bob.smith"));
+        assert!(!is_valid_username("bob."));
+        assert!(!is_valid_username("bob smith"));
+        assert!(!is_valid_username("here"));
+        assert!(!is_valid_username(""));
+    }
+
+    #[test]
+    fn ignore_emails() {
+        let mentions = parse("write to alice@example.com or @@here @");
+        assert_eq!(mentions, Mentions::default());
+    }
+}
@@ -6,15 +6,22 @@ use std::convert::TryInto;
 use std::marker::PhantomData;
 use std::mem;
 use thiserror::Error;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{Decoder, Encoder, Framed};
 
-pub type NetworkConnection<T> = Fuse<Framed<TcpStream, T>>;
+/// A stream of a connection: TCP or Unix domain socket.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin {}
 
-pub fn wrap<T, I>(stream: TcpStream) -> NetworkConnection<T>
+impl<S> Transport for S where S: AsyncRead + AsyncWrite + Send + Unpin {}
+
+pub type NetworkConnection<T> = Fuse<Framed<Box<dyn Transport>, T>>;
+
+pub fn wrap<T, I, S>(stream: S) -> NetworkConnection<T>
 where
     T: Encoder<I> + Decoder + Default,
+    S: Transport + 'static,
 {
+    let stream: Box<dyn Transport> = Box::new(stream);
     Framed::new(stream, T::default()).fuse()
 }
 
@@ -1,7 +1,20 @@
+use crate::db::types::{AuditAction, MemberRole, Timestamp, TokenScope, UserRole};
 use clap::Clap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 
 #[derive(Clap)]
 pub struct Opts {
+    #[clap(
+        long = "ctrl",
+        about = "Unix socket path or TCP address of a server to manage"
+    )]
+    pub ctrl: Option<String>,
+    #[clap(
+        long = "config",
+        about = "TOML file with settings (TODY_CONFIG if not set)"
+    )]
+    pub config: Option<PathBuf>,
     #[clap(subcommand)]
     pub subcmd: SubCommand,
 }
@@ -9,11 +22,97 @@ pub struct Opts {
 #[derive(Clap)]
 pub enum SubCommand {
     #[clap(name = "run", about = "Start a server")]
-    Run,
+    Run(RunCommand),
+    #[clap(name = "config", about = "Validate settings of a server")]
+    Config(ConfigCommand),
     #[clap(name = "user", about = "Manage user accounts")]
     User(UserCommand),
+    #[clap(name = "invite", about = "Manage invites for registration")]
+    Invite(InviteCommand),
     #[clap(name = "channel", about = "Manage channels")]
     Channel(ChannelCommand),
+    #[clap(name = "session", about = "Manage sessions of users")]
+    Session(SessionCommand),
+    #[clap(name = "token", about = "Manage API tokens of users")]
+    Token(TokenCommand),
+    #[clap(name = "lockout", about = "Manage lockouts of failed logins")]
+    Lockout(LockoutCommand),
+    #[clap(name = "audit", about = "Inspect the log of administrative changes")]
+    Audit(AuditCommand),
+}
+
+/// Options override values of the config.
+#[derive(Clap)]
+pub struct RunCommand {
+    #[clap(
+        long = "config",
+        about = "TOML file with settings (TODY_CONFIG if not set)"
+    )]
+    pub config: Option<PathBuf>,
+    #[clap(
+        long = "data-dir",
+        about = "Directory with the database, the admin token and the ctrl socket"
+    )]
+    pub data_dir: Option<PathBuf>,
+    #[clap(
+        long = "log-level",
+        about = "error, warn, info, debug or trace (if RUST_LOG is not set)"
+    )]
+    pub log_level: Option<String>,
+    #[clap(
+        long = "live-address",
+        about = "TCP address to serve the app and live connections"
+    )]
+    pub live_address: Option<SocketAddr>,
+    #[clap(
+        long = "session-idle",
+        about = "Seconds before an unused session expires"
+    )]
+    pub session_idle: Option<i64>,
+    #[clap(
+        long = "session-lifetime",
+        about = "Seconds before a session expires since a login"
+    )]
+    pub session_lifetime: Option<i64>,
+    #[clap(
+        long = "ctrl-address",
+        about = "TCP address to accept management connections in addition to the Unix socket"
+    )]
+    pub ctrl_address: Option<SocketAddr>,
+    #[clap(
+        long = "tls-cert",
+        about = "PEM file with a certificate chain to serve HTTPS"
+    )]
+    pub tls_cert: Option<PathBuf>,
+    #[clap(
+        long = "tls-key",
+        about = "PEM file with a private key of the certificate"
+    )]
+    pub tls_key: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    pub subcmd: ConfigSubCommand,
+}
+
+#[derive(Clap)]
+pub enum ConfigSubCommand {
+    #[clap(
+        name = "check",
+        about = "Check a config with overrides of environment variables"
+    )]
+    Check(ConfigCheckCommand),
+}
+
+#[derive(Clap)]
+pub struct ConfigCheckCommand {
+    #[clap(
+        long = "config",
+        about = "TOML file with settings (TODY_CONFIG if not set)"
+    )]
+    pub config: Option<PathBuf>,
 }
 
 #[derive(Clap)]
@@ -26,6 +125,31 @@ pub struct UserCommand {
 pub enum UserSubCommand {
     #[clap(name = "create", about = "Create a new user")]
     Create(UserCreateCommand),
+    #[clap(name = "role", about = "Set a role of a user")]
+    Role(UserRoleCommand),
+    #[clap(name = "list", about = "List of users")]
+    List,
+    #[clap(name = "show", about = "Show a user with its channels")]
+    Show(UserNameCommand),
+    #[clap(name = "passwd", about = "Set a new password of a user")]
+    Passwd(UserCreateCommand),
+    #[clap(name = "rename", about = "Change a username")]
+    Rename(UserRenameCommand),
+    #[clap(
+        name = "disable",
+        about = "Refuse logins of a user and close its sessions"
+    )]
+    Disable(UserNameCommand),
+    #[clap(name = "enable", about = "Allow logins of a disabled user")]
+    Enable(UserNameCommand),
+    #[clap(
+        name = "delete",
+        about = "Delete a user, but keep its messages",
+        long_about = "Delete a user, but keep its messages. Channels where the user \
+                      is the only member have to be deleted first, direct channels \
+                      of the user alone are deleted with the user"
+    )]
+    Delete(UserNameCommand),
 }
 
 #[derive(Clap)]
@@ -34,6 +158,50 @@ pub struct UserCreateCommand {
     pub password: String,
 }
 
+#[derive(Clap)]
+pub struct UserNameCommand {
+    pub username: String,
+}
+
+#[derive(Clap)]
+pub struct UserRenameCommand {
+    pub username: String,
+    pub new_username: String,
+}
+
+#[derive(Clap)]
+pub struct UserRoleCommand {
+    pub username: String,
+    #[clap(about = "owner, admin, member or guest")]
+    pub role: UserRole,
+}
+
+#[derive(Clap)]
+pub struct InviteCommand {
+    #[clap(subcommand)]
+    pub subcmd: InviteSubCommand,
+}
+
+#[derive(Clap)]
+pub enum InviteSubCommand {
+    #[clap(name = "create", about = "Create an invite token and a sign up link")]
+    Create(InviteCreateCommand),
+}
+
+#[derive(Clap)]
+pub struct InviteCreateCommand {
+    #[clap(long = "uses", default_value = "1", about = "Amount of registrations")]
+    pub uses: u32,
+    #[clap(long = "expires", about = "Days before the invite expires")]
+    pub expires: Option<u32>,
+    #[clap(
+        long = "url",
+        default_value = "http://127.0.0.1:3030",
+        about = "Public address of the server for the link"
+    )]
+    pub url: String,
+}
+
 #[derive(Clap)]
 pub struct ChannelCommand {
     #[clap(subcommand)]
@@ -48,6 +216,14 @@ pub enum ChannelSubCommand {
     List,
     #[clap(name = "delete", about = "Delete a channel")]
     Delete(ChannelDeleteCommand),
+    #[clap(name = "add-member", about = "Add a user to a channel")]
+    AddMember(ChannelMemberCommand),
+    #[clap(name = "remove-member", about = "Remove a user from a channel")]
+    RemoveMember(ChannelMemberCommand),
+    #[clap(name = "members", about = "List of channel's members")]
+    Members(ChannelMembersCommand),
+    #[clap(name = "role", about = "Set a role of a member")]
+    Role(ChannelRoleCommand),
 }
 
 #[derive(Clap)]
@@ -60,3 +236,131 @@ pub struct ChannelCreateCommand {
 pub struct ChannelDeleteCommand {
     pub channel: String,
 }
+
+#[derive(Clap)]
+pub struct ChannelMemberCommand {
+    pub channel: String,
+    pub username: String,
+}
+
+#[derive(Clap)]
+pub struct ChannelMembersCommand {
+    pub channel: String,
+}
+
+#[derive(Clap)]
+pub struct ChannelRoleCommand {
+    pub channel: String,
+    pub username: String,
+    #[clap(about = "owner or member")]
+    pub role: MemberRole,
+}
+
+#[derive(Clap)]
+pub struct SessionCommand {
+    #[clap(subcommand)]
+    pub subcmd: SessionSubCommand,
+}
+
+#[derive(Clap)]
+pub enum SessionSubCommand {
+    #[clap(name = "list", about = "List of user's sessions")]
+    List(SessionUserCommand),
+    #[clap(name = "revoke", about = "Revoke a session")]
+    Revoke(SessionRevokeCommand),
+    #[clap(name = "revoke-all", about = "Revoke all sessions of a user")]
+    RevokeAll(SessionUserCommand),
+}
+
+#[derive(Clap)]
+pub struct SessionUserCommand {
+    pub username: String,
+}
+
+#[derive(Clap)]
+pub struct SessionRevokeCommand {
+    pub session: u32,
+}
+
+#[derive(Clap)]
+pub struct TokenCommand {
+    #[clap(subcommand)]
+    pub subcmd: TokenSubCommand,
+}
+
+#[derive(Clap)]
+pub enum TokenSubCommand {
+    #[clap(name = "create", about = "Create an API token for bots and scripts")]
+    Create(TokenCreateCommand),
+    #[clap(name = "list", about = "List of user's API tokens")]
+    List(TokenUserCommand),
+    #[clap(name = "revoke", about = "Revoke an API token")]
+    Revoke(TokenRevokeCommand),
+}
+
+#[derive(Clap)]
+pub struct TokenCreateCommand {
+    pub username: String,
+    pub name: String,
+    #[clap(long = "scope", default_value = "read", about = "read or write")]
+    pub scope: TokenScope,
+    #[clap(long = "expires", about = "Days before the token expires")]
+    pub expires: Option<u32>,
+}
+
+#[derive(Clap)]
+pub struct TokenUserCommand {
+    pub username: String,
+}
+
+#[derive(Clap)]
+pub struct TokenRevokeCommand {
+    pub token: u32,
+}
+
+#[derive(Clap)]
+pub struct LockoutCommand {
+    #[clap(subcommand)]
+    pub subcmd: LockoutSubCommand,
+}
+
+#[derive(Clap)]
+pub enum LockoutSubCommand {
+    #[clap(name = "list", about = "List of active lockouts")]
+    List,
+    #[clap(name = "clear", about = "Clear lockouts")]
+    Clear(LockoutClearCommand),
+}
+
+#[derive(Clap)]
+pub struct LockoutClearCommand {
+    #[clap(long = "user", about = "Username (all lockouts if no target is set)")]
+    pub user: Option<String>,
+    #[clap(
+        long = "address",
+        about = "IP address (all lockouts if no target is set)"
+    )]
+    pub address: Option<IpAddr>,
+}
+
+#[derive(Clap)]
+pub struct AuditCommand {
+    #[clap(subcommand)]
+    pub subcmd: AuditSubCommand,
+}
+
+#[derive(Clap)]
+pub enum AuditSubCommand {
+    #[clap(name = "list", about = "List of audit records")]
+    List(AuditListCommand),
+}
+
+#[derive(Clap)]
+pub struct AuditListCommand {
+    #[clap(long = "since", about = "Unix timestamp of the oldest record")]
+    pub since: Option<Timestamp>,
+    #[clap(long = "actor", about = "Username of an initiator or [ctrl]")]
+    pub actor: Option<String>,
+    #[clap(long = "action", about = "Action like create-user or add-member")]
+    pub action: Option<AuditAction>,
+}
@@ -0,0 +1,102 @@
+//! Hashing of users' passwords.
+//!
+//! Hashes are kept in the PHC string format (`$argon2id$v=19$...`)
+//! that contains an algorithm and its parameters. Rows created before
+//! hashing was introduced contain plain passwords and they are
+//! rehashed on the next successful login.
+
+use anyhow::Error;
+use argon2::{Config, Variant};
+use constant_time_eq::constant_time_eq;
+use rand::{thread_rng, RngCore};
+
+const SALT_LEN: usize = 16;
+
+/// Prefix of every hash produced by any `argon2` variant.
+const ARGON2_PREFIX: &str = "$argon2";
+
+/// Prefix of hashes produced by the current scheme.
+const CURRENT_PREFIX: &str = "$argon2id$";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    Valid,
+    /// The password is valid, but has to be hashed again
+    /// with the current scheme.
+    Outdated,
+    Invalid,
+}
+
+fn config() -> Config<'static> {
+    Config {
+        variant: Variant::Argon2id,
+        ..Config::default()
+    }
+}
+
+/// Hashes a password with a random salt.
+pub fn hash(password: &str) -> Result<String, Error> {
+    let mut salt = [0; SALT_LEN];
+    thread_rng().fill_bytes(&mut salt);
+    let hash = argon2::hash_encoded(password.as_bytes(), &salt, &config())?;
+    Ok(hash)
+}
+
+/// Checks a password against a stored value in constant time.
+/// An empty or malformed stored value never matches.
+pub fn verify(stored: &str, password: &str) -> Result<Verification, Error> {
+    let (valid, current) = if stored.is_empty() {
+        (false, false)
+    } else if stored.starts_with(ARGON2_PREFIX) {
+        let valid = argon2::verify_encoded(stored, password.as_bytes()).unwrap_or_else(|err| {
+            log::warn!("Can't decode a password hash: {}", err);
+            false
+        });
+        (valid, stored.starts_with(CURRENT_PREFIX))
+    } else {
+        // Legacy plain password
+        let valid = constant_time_eq(stored.as_bytes(), password.as_bytes());
+        (valid, false)
+    };
+    let verification = match (valid, current) {
+        (false, _) => Verification::Invalid,
+        (true, false) => Verification::Outdated,
+        (true, true) => Verification::Valid,
+    };
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_and_verify() -> Result<(), Error> {
+        let hash = hash("secret")?;
+        assert!(hash.starts_with(CURRENT_PREFIX));
+        assert_eq!(verify(&hash, "secret")?, Verification::Valid);
+        assert_eq!(verify(&hash, "wrong")?, Verification::Invalid);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_passwords() -> Result<(), Error> {
+        assert_eq!(verify("secret", "secret")?, Verification::Outdated);
+        assert_eq!(verify("secret", "wrong")?, Verification::Invalid);
+        Ok(())
+    }
+
+    #[test]
+    fn empty_stored_value() -> Result<(), Error> {
+        assert_eq!(verify("", "")?, Verification::Invalid);
+        assert_eq!(verify("", "secret")?, Verification::Invalid);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_hash() -> Result<(), Error> {
+        assert_eq!(verify("$argon2id$broken", "")?, Verification::Invalid);
+        assert_eq!(verify("$argon2id$broken", "secret")?, Verification::Invalid);
+        Ok(())
+    }
+}
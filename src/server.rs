@@ -1,5 +1,7 @@
-use crate::actors::{CtrlServer, Engine, LiveServer};
-use crate::opts::Opts;
+use crate::actors::{CtrlServer, Engine, LiveServer, SessionLimits};
+use crate::config::Config;
+use crate::opts::{Opts, RunCommand, SubCommand};
+use crate::tls::TlsFiles;
 use anyhow::Error;
 
 pub struct Server {
@@ -12,19 +14,42 @@ impl Server {
     }
 
     pub async fn run(self) -> Result<(), Error> {
-        env_logger::try_init()?;
+        let cmd = match &self.opts.subcmd {
+            SubCommand::Run(cmd) => cmd,
+            _ => unreachable!(),
+        };
+        let path = cmd
+            .config
+            .as_deref()
+            .or_else(|| self.opts.config.as_deref());
+        let mut config = Config::load(path)?;
+        apply_options(&mut config, cmd);
+        config.check()?;
+
+        let env = env_logger::Env::default().default_filter_or(config.log_level.as_str());
+        env_logger::Builder::from_env(env).try_init()?;
         log::info!("Tody.Chat - version {}", clap::crate_version!());
 
+        let session_limits = SessionLimits {
+            idle: config.limits.session_idle,
+            lifetime: config.limits.session_lifetime,
+        };
+        let tls = match (config.live.tls_cert, config.live.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            // Both are set or not set after the check
+            _ => None,
+        };
+
         log::debug!("Starting engine actor...");
-        let mut engine = Engine::start();
+        let mut engine = Engine::start(config.data_dir.clone(), session_limits);
 
         log::debug!("Starting Ctrl server...");
-        let addr = ([127, 0, 0, 1], 3020).into();
-        let mut ctrl_server = CtrlServer::start(addr, engine.clone());
+        let mut ctrl_server =
+            CtrlServer::start(config.ctrl.address, config.data_dir.clone(), engine.clone());
 
         log::debug!("Starting Live server...");
-        let addr = ([127, 0, 0, 1], 3030).into();
-        let mut live_server = LiveServer::start(addr, engine.clone());
+        log::info!("Live listens on {}", config.live.address);
+        let mut live_server = LiveServer::start(config.live.address, tls, engine.clone());
 
         log::info!("Press Ctrl-C to terminate.");
         tokio::signal::ctrl_c().await?;
@@ -37,3 +62,29 @@ impl Server {
         Ok(())
     }
 }
+
+/// Options of the command line override the config.
+fn apply_options(config: &mut Config, cmd: &RunCommand) {
+    if let Some(data_dir) = cmd.data_dir.clone() {
+        config.data_dir = data_dir;
+    }
+    if let Some(log_level) = cmd.log_level.clone() {
+        config.log_level = log_level;
+    }
+    if let Some(address) = cmd.live_address {
+        config.live.address = address;
+    }
+    if let Some(address) = cmd.ctrl_address {
+        config.ctrl.address = Some(address);
+    }
+    if let Some(idle) = cmd.session_idle {
+        config.limits.session_idle = idle;
+    }
+    if let Some(lifetime) = cmd.session_lifetime {
+        config.limits.session_lifetime = lifetime;
+    }
+    if cmd.tls_cert.is_some() || cmd.tls_key.is_some() {
+        config.live.tls_cert = cmd.tls_cert.clone();
+        config.live.tls_key = cmd.tls_key.clone();
+    }
+}
@@ -0,0 +1,127 @@
+//! TLS termination of the live server.
+//!
+//! A certificate chain and a private key are read from PEM files.
+//! The files are checked periodically and reloaded when they change,
+//! so a renewed certificate is used for new connections without a restart.
+
+use anyhow::{anyhow, Error};
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio_rustls::TlsAcceptor;
+
+/// How often files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    fn modified(&self) -> Result<(SystemTime, SystemTime), Error> {
+        let cert = fs::metadata(&self.cert)?.modified()?;
+        let key = fs::metadata(&self.key)?.modified()?;
+        Ok((cert, key))
+    }
+
+    fn load(&self) -> Result<CertifiedKey, Error> {
+        let certs = pemfile::certs(&mut open(&self.cert)?)
+            .map_err(|_| anyhow!("can't parse certificates: {}", self.cert.display()))?;
+        if certs.is_empty() {
+            return Err(anyhow!("no certificates in {}", self.cert.display()));
+        }
+        let mut keys = pemfile::pkcs8_private_keys(&mut open(&self.key)?)
+            .map_err(|_| anyhow!("can't parse a key: {}", self.key.display()))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut open(&self.key)?)
+                .map_err(|_| anyhow!("can't parse a key: {}", self.key.display()))?;
+        }
+        let key = keys
+            .first()
+            .ok_or_else(|| anyhow!("no private key in {}", self.key.display()))?;
+        let key = sign::any_supported_type(key)
+            .map_err(|_| anyhow!("unsupported private key: {}", self.key.display()))?;
+        Ok(CertifiedKey::new(certs, Arc::new(key)))
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, Error> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
+struct Loaded {
+    modified: (SystemTime, SystemTime),
+    key: CertifiedKey,
+}
+
+/// Provides the last loaded certificate to every handshake.
+pub struct CertResolver {
+    files: TlsFiles,
+    loaded: RwLock<Loaded>,
+}
+
+impl CertResolver {
+    pub fn new(files: TlsFiles) -> Result<Self, Error> {
+        let modified = files.modified()?;
+        let key = files.load()?;
+        let loaded = Loaded { modified, key };
+        Ok(Self {
+            files,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Loads files again if they were modified since the last load.
+    /// Returns `true` if the certificate was replaced.
+    pub fn reload(&self) -> Result<bool, Error> {
+        let modified = self.files.modified()?;
+        let changed = self
+            .loaded
+            .read()
+            .map(|loaded| loaded.modified != modified)
+            .unwrap_or(true);
+        if changed {
+            let key = self.files.load()?;
+            let mut loaded = self
+                .loaded
+                .write()
+                .map_err(|_| anyhow!("certificate lock poisoned"))?;
+            *loaded = Loaded { modified, key };
+        }
+        Ok(changed)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello) -> Option<CertifiedKey> {
+        self.loaded.read().ok().map(|loaded| loaded.key.clone())
+    }
+}
+
+pub fn acceptor(resolver: Arc<CertResolver>) -> TlsAcceptor {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.cert_resolver = resolver;
+    config.set_protocols(&[b"http/1.1".to_vec()]);
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// Reloads the certificate when its files change.
+/// Keeps the previous one if new files can't be loaded.
+pub async fn watch(resolver: Arc<CertResolver>) {
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+        match resolver.reload() {
+            Ok(true) => log::info!("TLS certificate reloaded"),
+            Ok(false) => {}
+            Err(err) => log::error!("Can't reload TLS certificate: {}", err),
+        }
+    }
+}
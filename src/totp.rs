@@ -0,0 +1,187 @@
+//! Time-based one-time passwords (RFC 6238) used as the second factor of logins.
+//!
+//! Secrets have 160 bits as recommended by RFC 4226 and they are kept
+//! in the base32 encoding that authenticator apps expect. Recovery codes
+//! are shown to a user once and stored as hashes like session keys.
+
+use anyhow::Error;
+use base32::Alphabet;
+use constant_time_eq::constant_time_eq;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+const SECRET_LEN: usize = 20;
+
+const DIGITS: usize = 6;
+
+/// Seconds of a time step.
+const PERIOD: u64 = 30;
+
+/// Amount of steps around the current one accepted because of clock drift.
+const SKEW: u64 = 1;
+
+/// Amount of recovery codes generated on enrollment.
+const RECOVERY_CODES: usize = 10;
+
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Issuer shown by authenticator apps.
+const ISSUER: &str = "tody-chat";
+
+const ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+#[derive(Error, Debug)]
+#[error("invalid TOTP secret")]
+pub struct InvalidSecret;
+
+/// Generates a base32 encoded secret using the OS random generator.
+pub fn generate_secret() -> String {
+    let mut secret = [0; SECRET_LEN];
+    OsRng.fill_bytes(&mut secret);
+    base32::encode(ALPHABET, &secret)
+}
+
+/// Generates codes that can replace a one-time password once.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            OsRng
+                .sample_iter(&Alphanumeric)
+                .take(RECOVERY_CODE_LEN)
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns an `otpauth://` URI for QR codes of authenticator apps.
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}\
+         &algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        username = percent_encode(username),
+        secret = secret,
+        digits = DIGITS,
+        period = PERIOD,
+    )
+}
+
+/// Encodes every byte except unreserved characters of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char);
+            }
+            _ => {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Returns the time step of the current time.
+pub fn current_step() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    now / PERIOD
+}
+
+/// HOTP value (RFC 4226) of a counter.
+fn hotp(key: &[u8], counter: u64, digits: usize) -> String {
+    let mut mac = Hmac::<Sha1>::new_varkey(key).expect("HMAC takes keys of any size");
+    mac.input(&counter.to_be_bytes());
+    let hash = mac.result().code();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&hash[offset..offset + 4]);
+    let value = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+    let value = u64::from(value) % 10u64.pow(digits as u32);
+    format!("{:0width$}", value, width = digits)
+}
+
+/// Checks a code for steps around `step` that are newer than `last_step`.
+/// Returns the matched step that has to be kept to refuse a replay.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    step: u64,
+    last_step: Option<u64>,
+) -> Result<Option<u64>, Error> {
+    let key = base32::decode(ALPHABET, secret).ok_or(InvalidSecret)?;
+    let code = code.trim();
+    if code.len() != DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let first = step.saturating_sub(SKEW);
+    for candidate in first..=step + SKEW {
+        if last_step.map_or(false, |last| candidate <= last) {
+            continue;
+        }
+        let expected = hotp(&key, candidate, DIGITS);
+        if constant_time_eq(expected.as_bytes(), code.as_bytes()) {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA1 seed of test vectors of RFC 6238.
+    const SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc_vectors() {
+        let vectors = [
+            (59, "94287082"),
+            (1_111_111_109, "07081804"),
+            (1_234_567_890, "89005924"),
+            (20_000_000_000, "65353130"),
+        ];
+        for (time, expected) in vectors.iter() {
+            assert_eq!(hotp(SEED, time / PERIOD, 8), *expected);
+        }
+    }
+
+    #[test]
+    fn verify_codes() -> Result<(), Error> {
+        let secret = base32::encode(ALPHABET, SEED);
+        let step = 1_234_567_890 / PERIOD;
+        assert_eq!(verify(&secret, "005924", step, None)?, Some(step));
+        assert_eq!(verify(&secret, " 005924 ", step + 1, None)?, Some(step));
+        assert_eq!(verify(&secret, "005924", step + 2, None)?, None);
+        // Replays are refused
+        assert_eq!(verify(&secret, "005924", step, Some(step))?, None);
+        assert_eq!(verify(&secret, "abcdef", step, None)?, None);
+        assert!(verify("not base32!", "005924", step, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn generated_secrets() -> Result<(), Error> {
+        let secret = generate_secret();
+        let key = base32::decode(ALPHABET, &secret).ok_or(InvalidSecret)?;
+        assert_eq!(key.len(), SECRET_LEN);
+        let step = current_step();
+        let code = hotp(&key, step, DIGITS);
+        assert_eq!(verify(&secret, &code, step, None)?, Some(step));
+        let uri = provisioning_uri(&secret, "alice");
+        assert!(uri.starts_with("otpauth://totp/tody-chat:alice?secret="));
+        let uri = provisioning_uri(&secret, "a:b?c&d é");
+        assert!(uri.starts_with("otpauth://totp/tody-chat:a%3Ab%3Fc%26d%20%C3%A9?secret="));
+        assert_eq!(generate_recovery_codes().len(), RECOVERY_CODES);
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use anyhow::Error;
 use protocol::{
-    ChannelUpdate, ClientToServer, Credentials, Delta, Key, LoginUpdate, Reaction, ServerToClient,
+    ChannelId, ChannelInfo, ChannelUpdate, ClientToServer, Credentials, Delta, EmojiCount, Key,
+    LoginUpdate, MemberUpdate, MentionUpdate, MessageId, MessageInfo, MessageUpdate, ProfileInfo,
+    ProfileUpdate, Reaction, RevisionInfo, SecurityUpdate, ServerToClient, ThreadUpdate, TokenId,
+    TokenInfo, TokenScope, TokenUpdate,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -30,13 +33,112 @@ pub enum ConnectionStatus {
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum LoginStatus {
     Unauthorized,
-    NeedCredentials { fail: Option<String> },
+    NeedCredentials {
+        fail: Option<String>,
+    },
+    /// Credentials are accepted, but a code of the second factor is required.
+    NeedSecondFactor {
+        fail: Option<String>,
+    },
     LoggedIn,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum ChannelStatus {
+    ChannelsList(Vec<ChannelInfo>),
     ChannelCreated(String),
+    ChannelDeleted(ChannelId),
+    DirectOpened(ChannelInfo),
+    ReadUpdated {
+        channel: ChannelId,
+        last_read: Option<MessageId>,
+        unread: u32,
+        mentions: u32,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MessageStatus {
+    MessageAdded(MessageInfo),
+    History {
+        channel: ChannelId,
+        messages: Vec<MessageInfo>,
+        next: Option<MessageId>,
+    },
+    MessageEdited(MessageInfo),
+    MessageDeleted {
+        channel: ChannelId,
+        message: MessageId,
+    },
+    Revisions {
+        message: MessageId,
+        revisions: Vec<RevisionInfo>,
+    },
+    ReactionsChanged {
+        channel: ChannelId,
+        message: MessageId,
+        reactions: Vec<EmojiCount>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ThreadStatus {
+    Thread {
+        parent: MessageInfo,
+        replies: Vec<MessageInfo>,
+        next: Option<MessageId>,
+    },
+    ReplyAdded {
+        parent: MessageInfo,
+        reply: MessageInfo,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MentionStatus {
+    Mentioned { message: MessageInfo },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MemberStatus {
+    MembersList {
+        channel: ChannelId,
+        members: Vec<String>,
+    },
+    MemberAdded {
+        channel: ChannelId,
+        username: String,
+    },
+    MemberRemoved {
+        channel: ChannelId,
+        username: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ProfileStatus {
+    Profile {
+        username: String,
+        profile: ProfileInfo,
+    },
+    ProfileChanged {
+        username: String,
+        profile: ProfileInfo,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum SecurityStatus {
+    TotpEnrollment { secret: String, uri: String },
+    TotpEnabled { recovery_codes: Vec<String> },
+    TotpDisabled,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum TokenStatus {
+    TokensList { tokens: Vec<TokenInfo> },
+    TokenCreated { token: TokenInfo, key: Key },
+    TokenRevoked { id: TokenId },
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -44,6 +146,13 @@ pub enum Info {
     ConnectionInfo,
     LoginInfo,
     ChannelInfo,
+    MessageInfo,
+    MemberInfo,
+    ThreadInfo,
+    MentionInfo,
+    ProfileInfo,
+    SecurityInfo,
+    TokenInfo,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -52,9 +161,86 @@ pub enum Action {
 
     // TODO: Remove duplicatied requests
     SetCredentials(Credentials),
+    /// A code of the second factor after `NeedSecondFactor`.
+    SecondFactor(String),
+    /// Creates an account with an invite token.
+    Register {
+        token: Key,
+        credentials: Credentials,
+    },
+
+    Logout,
 
     // TODO: Remove duplicatied requests
     CreateChannel(String),
+
+    PostMessage {
+        channel: ChannelId,
+        text: String,
+    },
+
+    FetchHistory {
+        channel: ChannelId,
+        before: Option<MessageId>,
+    },
+
+    MarkRead {
+        channel: ChannelId,
+        message: MessageId,
+    },
+
+    FetchChannels,
+
+    OpenDirect(Vec<String>),
+
+    EditMessage {
+        message: MessageId,
+        text: String,
+    },
+
+    DeleteMessage(MessageId),
+
+    PostReply {
+        parent: MessageId,
+        text: String,
+    },
+
+    FetchThread(MessageId),
+
+    AddReaction {
+        message: MessageId,
+        emoji: String,
+    },
+
+    RemoveReaction {
+        message: MessageId,
+        emoji: String,
+    },
+
+    UpdateProfile(ProfileInfo),
+
+    FetchProfile(String),
+
+    EnrollTotp {
+        password: String,
+    },
+
+    ConfirmTotp {
+        code: String,
+        password: String,
+    },
+
+    DisableTotp(String),
+
+    CreateToken {
+        name: String,
+        scope: TokenScope,
+        lifetime: Option<i64>,
+    },
+
+    FetchTokens,
+
+    RevokeToken(TokenId),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -68,11 +254,22 @@ pub enum Notification {
     ConnectionStatus(ConnectionStatus),
     LoginStatus(LoginStatus),
     ChannelStatus(ChannelStatus),
+    MessageStatus(MessageStatus),
+    MemberStatus(MemberStatus),
+    ThreadStatus(ThreadStatus),
+    MentionStatus(MentionStatus),
+    ProfileStatus(ProfileStatus),
+    SecurityStatus(SecurityStatus),
+    TokenStatus(TokenStatus),
 }
 
 enum LoginBy {
     ByKey(Key),
     ByCredentials(Credentials),
+    ByInvite {
+        token: Key,
+        credentials: Credentials,
+    },
 }
 
 struct Task {
@@ -133,12 +330,33 @@ impl Agent for Connector {
         match msg {
             Msg::WsReady(res) => match res {
                 Ok(ServerToClient::Delta(delta)) => {
-                    match delta {
+                    match *delta {
                         Delta::LoginUpdate(update) => {
                             self.login_update(update);
                         }
                         Delta::ChannelUpdate(update) => {
                             self.channel_update(update);
+                        }
+                        Delta::MessageUpdate(update) => {
+                            self.message_update(update);
+                        }
+                        Delta::MemberUpdate(update) => {
+                            self.member_update(update);
+                        }
+                        Delta::ThreadUpdate(update) => {
+                            self.thread_update(update);
+                        }
+                        Delta::MentionUpdate(update) => {
+                            self.mention_update(update);
+                        }
+                        Delta::ProfileUpdate(update) => {
+                            self.profile_update(update);
+                        }
+                        Delta::SecurityUpdate(update) => {
+                            self.security_update(update);
+                        }
+                        Delta::TokenUpdate(update) => {
+                            self.token_update(update);
                         } /* TODO: Track results and notify about tasks
                           Delta::ChannelCreated(channel_name) => {
                               let msg =
@@ -164,6 +382,8 @@ impl Agent for Connector {
                     self.login();
                 }
                 WebSocketStatus::Closed | WebSocketStatus::Error => {
+                    // The server closes connections of revoked sessions
+                    self.ws.take();
                     self.set_connection_status(ConnectionStatus::Disconnected);
                 }
             },
@@ -177,10 +397,28 @@ impl Agent for Connector {
                 // Remove automatic login and wait for the new token
                 self.remove_key();
                 self.login_by = Some(LoginBy::ByCredentials(creds));
-                self.login();
+                if self.ws.is_some() {
+                    self.login();
+                } else if let Err(err) = self.connect() {
+                    // It will login when connected
+                    log::error!("Can't connect to a server by WebSocket: {}", err);
+                }
                 // TODO: Set it on authorized
                 //self.set_status(Status::LoggedIn);
             }
+            Action::SecondFactor(code) => {
+                self.second_factor(code);
+            }
+            Action::Register { token, credentials } => {
+                self.remove_key();
+                self.login_by = Some(LoginBy::ByInvite { token, credentials });
+                if self.ws.is_some() {
+                    self.login();
+                } else if let Err(err) = self.connect() {
+                    // It will register when connected
+                    log::error!("Can't connect to a server by WebSocket: {}", err);
+                }
+            }
             Action::Subscribe(set) => {
                 for info in set {
                     self.subscriptions.entry(info).or_default().insert(handler);
@@ -190,6 +428,70 @@ impl Agent for Connector {
             Action::CreateChannel(channel_name) => {
                 self.create_channel(channel_name);
             }
+            Action::PostMessage { channel, text } => {
+                self.post_message(channel, text);
+            }
+            Action::FetchHistory { channel, before } => {
+                self.fetch_history(channel, before);
+            }
+            Action::MarkRead { channel, message } => {
+                self.mark_read(channel, message);
+            }
+            Action::Logout => {
+                self.logout();
+            }
+            Action::FetchChannels => {
+                self.fetch_channels();
+            }
+            Action::OpenDirect(usernames) => {
+                self.open_direct(usernames);
+            }
+            Action::EditMessage { message, text } => {
+                self.edit_message(message, text);
+            }
+            Action::DeleteMessage(message) => {
+                self.delete_message(message);
+            }
+            Action::PostReply { parent, text } => {
+                self.post_reply(parent, text);
+            }
+            Action::FetchThread(message) => {
+                self.fetch_thread(message);
+            }
+            Action::AddReaction { message, emoji } => {
+                self.add_reaction(message, emoji);
+            }
+            Action::RemoveReaction { message, emoji } => {
+                self.remove_reaction(message, emoji);
+            }
+            Action::UpdateProfile(profile) => {
+                self.update_profile(profile);
+            }
+            Action::FetchProfile(username) => {
+                self.fetch_profile(username);
+            }
+            Action::EnrollTotp { password } => {
+                self.enroll_totp(password);
+            }
+            Action::ConfirmTotp { code, password } => {
+                self.confirm_totp(code, password);
+            }
+            Action::DisableTotp(code) => {
+                self.disable_totp(code);
+            }
+            Action::CreateToken {
+                name,
+                scope,
+                lifetime,
+            } => {
+                self.create_token(name, scope, lifetime);
+            }
+            Action::FetchTokens => {
+                self.fetch_tokens();
+            }
+            Action::RevokeToken(id) => {
+                self.revoke_token(id);
+            }
         }
     }
 
@@ -235,6 +537,10 @@ impl Connector {
                         ClientToServer::CreateSession(creds.to_owned())
                     }
                     LoginBy::ByKey(key) => ClientToServer::RestoreSession(key.to_owned()),
+                    LoginBy::ByInvite { token, credentials } => ClientToServer::Register {
+                        token: token.to_owned(),
+                        credentials: credentials.to_owned(),
+                    },
                 }
             };
             self.ws.as_mut().unwrap().send(Json(&msg));
@@ -245,11 +551,128 @@ impl Connector {
         // TODO: Schedule reconnection...
     }
 
+    fn second_factor(&mut self, code: String) {
+        let msg = ClientToServer::SecondFactor(code);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn logout(&mut self) {
+        let msg = ClientToServer::Logout;
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
     fn create_channel(&mut self, channel_name: String) {
         let msg = ClientToServer::CreateChannel(channel_name);
         self.ws.as_mut().unwrap().send(Json(&msg));
     }
 
+    fn mark_read(&mut self, channel: ChannelId, message: MessageId) {
+        let msg = ClientToServer::MarkRead { channel, message };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn fetch_channels(&mut self) {
+        let msg = ClientToServer::FetchChannels;
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn open_direct(&mut self, usernames: Vec<String>) {
+        let msg = ClientToServer::OpenDirect { usernames };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn post_message(&mut self, channel: ChannelId, text: String) {
+        let msg = ClientToServer::PostMessage { channel, text };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn edit_message(&mut self, message: MessageId, text: String) {
+        let msg = ClientToServer::EditMessage { message, text };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn delete_message(&mut self, message: MessageId) {
+        let msg = ClientToServer::DeleteMessage(message);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn add_reaction(&mut self, message: MessageId, emoji: String) {
+        let msg = ClientToServer::AddReaction { message, emoji };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn remove_reaction(&mut self, message: MessageId, emoji: String) {
+        let msg = ClientToServer::RemoveReaction { message, emoji };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn update_profile(&mut self, profile: ProfileInfo) {
+        let msg = ClientToServer::UpdateProfile(profile);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn fetch_profile(&mut self, username: String) {
+        let msg = ClientToServer::FetchProfile(username);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn enroll_totp(&mut self, password: String) {
+        let msg = ClientToServer::EnrollTotp { password };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn confirm_totp(&mut self, code: String, password: String) {
+        let msg = ClientToServer::ConfirmTotp { code, password };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn disable_totp(&mut self, code: String) {
+        let msg = ClientToServer::DisableTotp(code);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn create_token(&mut self, name: String, scope: TokenScope, lifetime: Option<i64>) {
+        let msg = ClientToServer::CreateToken {
+            name,
+            scope,
+            lifetime,
+        };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn fetch_tokens(&mut self) {
+        let msg = ClientToServer::FetchTokens;
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn revoke_token(&mut self, id: TokenId) {
+        let msg = ClientToServer::RevokeToken(id);
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn post_reply(&mut self, parent: MessageId, text: String) {
+        let msg = ClientToServer::PostReply { parent, text };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn fetch_thread(&mut self, message: MessageId) {
+        let msg = ClientToServer::FetchThread {
+            message,
+            before: None,
+            limit: Self::HISTORY_PAGE,
+        };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
+    fn fetch_history(&mut self, channel: ChannelId, before: Option<MessageId>) {
+        let msg = ClientToServer::FetchHistory {
+            channel,
+            before,
+            limit: Self::HISTORY_PAGE,
+        };
+        self.ws.as_mut().unwrap().send(Json(&msg));
+    }
+
     fn set_connection_status(&mut self, connection_status: ConnectionStatus) {
         self.connection_status = connection_status;
         let notification = Notification::ConnectionStatus(self.connection_status.clone());
@@ -318,6 +741,32 @@ impl Connector {
                 self.set_login_status(status);
                 self.store_key(key);
             }
+            LoginUpdate::LoginLocked { retry_after } => {
+                self.login_by.take();
+                let reason = format!("Too many attempts, try again in {} seconds", retry_after);
+                let status = LoginStatus::NeedCredentials { fail: Some(reason) };
+                self.set_login_status(status);
+            }
+            LoginUpdate::LoggedOut => {
+                self.remove_key();
+                self.login_by.take();
+                let status = LoginStatus::NeedCredentials { fail: None };
+                self.set_login_status(status);
+            }
+            LoginUpdate::SecondFactorRequired => {
+                let status = LoginStatus::NeedSecondFactor { fail: None };
+                self.set_login_status(status);
+            }
+            LoginUpdate::SecondFactorFail => {
+                let reason = "Invalid code".to_string();
+                let status = LoginStatus::NeedSecondFactor { fail: Some(reason) };
+                self.set_login_status(status);
+            }
+            LoginUpdate::RegisterFail { reason } => {
+                self.login_by.take();
+                let status = LoginStatus::NeedCredentials { fail: Some(reason) };
+                self.set_login_status(status);
+            }
             LoginUpdate::LoginFail => {
                 let reason;
                 // Reset login_by field
@@ -328,6 +777,9 @@ impl Connector {
                     Some(LoginBy::ByCredentials(_)) => {
                         reason = "Bad credentials".to_string();
                     }
+                    Some(LoginBy::ByInvite { .. }) => {
+                        reason = "Registration failed".to_string();
+                    }
                     None => {
                         unreachable!("Login failed without login info.");
                     }
@@ -340,12 +792,164 @@ impl Connector {
     }
 
     fn channel_update(&mut self, update: ChannelUpdate) {
-        todo!();
+        let status = {
+            match update {
+                ChannelUpdate::ChannelsList { channels } => ChannelStatus::ChannelsList(channels),
+                ChannelUpdate::ChannelCreated { channel } => {
+                    ChannelStatus::ChannelCreated(channel.title)
+                }
+                ChannelUpdate::ChannelDeleted { channel } => ChannelStatus::ChannelDeleted(channel),
+                ChannelUpdate::DirectOpened { channel } => ChannelStatus::DirectOpened(channel),
+                ChannelUpdate::ReadUpdated {
+                    channel,
+                    last_read,
+                    unread,
+                    mentions,
+                } => ChannelStatus::ReadUpdated {
+                    channel,
+                    last_read,
+                    unread,
+                    mentions,
+                },
+            }
+        };
+        let notification = Notification::ChannelStatus(status);
+        self.notify_subscribers(Info::ChannelInfo, notification);
+    }
+
+    fn message_update(&mut self, update: MessageUpdate) {
+        let status = {
+            match update {
+                MessageUpdate::MessageAdded { message } => MessageStatus::MessageAdded(message),
+                MessageUpdate::History {
+                    channel,
+                    messages,
+                    next,
+                } => MessageStatus::History {
+                    channel,
+                    messages,
+                    next,
+                },
+                MessageUpdate::MessageEdited { message } => MessageStatus::MessageEdited(message),
+                MessageUpdate::MessageDeleted { channel, message } => {
+                    MessageStatus::MessageDeleted { channel, message }
+                }
+                MessageUpdate::Revisions { message, revisions } => {
+                    MessageStatus::Revisions { message, revisions }
+                }
+                MessageUpdate::ReactionsChanged {
+                    channel,
+                    message,
+                    reactions,
+                } => MessageStatus::ReactionsChanged {
+                    channel,
+                    message,
+                    reactions,
+                },
+            }
+        };
+        let notification = Notification::MessageStatus(status);
+        self.notify_subscribers(Info::MessageInfo, notification);
+    }
+
+    fn thread_update(&mut self, update: ThreadUpdate) {
+        let status = {
+            match update {
+                ThreadUpdate::Thread {
+                    parent,
+                    replies,
+                    next,
+                } => ThreadStatus::Thread {
+                    parent,
+                    replies,
+                    next,
+                },
+                ThreadUpdate::ReplyAdded { parent, reply } => {
+                    ThreadStatus::ReplyAdded { parent, reply }
+                }
+            }
+        };
+        let notification = Notification::ThreadStatus(status);
+        self.notify_subscribers(Info::ThreadInfo, notification);
+    }
+
+    fn mention_update(&mut self, update: MentionUpdate) {
+        let status = {
+            match update {
+                MentionUpdate::Mentioned { message } => MentionStatus::Mentioned { message },
+            }
+        };
+        let notification = Notification::MentionStatus(status);
+        self.notify_subscribers(Info::MentionInfo, notification);
+    }
+
+    fn member_update(&mut self, update: MemberUpdate) {
+        let status = {
+            match update {
+                MemberUpdate::MembersList { channel, members } => {
+                    MemberStatus::MembersList { channel, members }
+                }
+                MemberUpdate::MemberAdded { channel, username } => {
+                    MemberStatus::MemberAdded { channel, username }
+                }
+                MemberUpdate::MemberRemoved { channel, username } => {
+                    MemberStatus::MemberRemoved { channel, username }
+                }
+            }
+        };
+        let notification = Notification::MemberStatus(status);
+        self.notify_subscribers(Info::MemberInfo, notification);
+    }
+
+    fn profile_update(&mut self, update: ProfileUpdate) {
+        let status = {
+            match update {
+                ProfileUpdate::Profile { username, profile } => {
+                    ProfileStatus::Profile { username, profile }
+                }
+                ProfileUpdate::ProfileChanged { username, profile } => {
+                    ProfileStatus::ProfileChanged { username, profile }
+                }
+            }
+        };
+        let notification = Notification::ProfileStatus(status);
+        self.notify_subscribers(Info::ProfileInfo, notification);
+    }
+
+    fn security_update(&mut self, update: SecurityUpdate) {
+        let status = {
+            match update {
+                SecurityUpdate::TotpEnrollment { secret, uri } => {
+                    SecurityStatus::TotpEnrollment { secret, uri }
+                }
+                SecurityUpdate::TotpEnabled { recovery_codes } => {
+                    SecurityStatus::TotpEnabled { recovery_codes }
+                }
+                SecurityUpdate::TotpDisabled => SecurityStatus::TotpDisabled,
+            }
+        };
+        let notification = Notification::SecurityStatus(status);
+        self.notify_subscribers(Info::SecurityInfo, notification);
+    }
+
+    fn token_update(&mut self, update: TokenUpdate) {
+        let status = {
+            match update {
+                TokenUpdate::TokensList { tokens } => TokenStatus::TokensList { tokens },
+                TokenUpdate::TokenCreated { token, key } => {
+                    TokenStatus::TokenCreated { token, key }
+                }
+                TokenUpdate::TokenRevoked { id } => TokenStatus::TokenRevoked { id },
+            }
+        };
+        let notification = Notification::TokenStatus(status);
+        self.notify_subscribers(Info::TokenInfo, notification);
     }
 }
 
 impl Connector {
     const KEY: &'static str = "tody.chat.login_key";
+    const HISTORY_PAGE: u32 = 50;
 
     fn store_key(&mut self, key: Key) {
         self.storage.store(Self::KEY, Json(&key));
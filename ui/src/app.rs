@@ -1,10 +1,18 @@
-use crate::agents::connector::{ConnectionStatus, Connector, LoginStatus, Notification};
+use crate::agents::connector::{
+    Action, ConnectionStatus, Connector, Info, LoginStatus, MemberStatus, Notification,
+    ProfileStatus,
+};
 use crate::components::{Chat, Login, Splash};
+use protocol::ProfileInfo;
+use std::collections::BTreeMap;
 use yew::prelude::*;
 
 enum Scene {
     Splash,
-    Login { fail: Option<String> },
+    Login {
+        fail: Option<String>,
+        second_factor: bool,
+    },
     Main,
 }
 
@@ -12,6 +20,8 @@ pub struct App {
     scene: Scene,
     link: ComponentLink<Self>,
     connector: Box<dyn Bridge<Connector>>,
+    /// Known profiles by usernames.
+    profiles: BTreeMap<String, ProfileInfo>,
 }
 
 pub enum Msg {
@@ -25,11 +35,14 @@ impl Component for App {
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
         let callback = link.callback(|n| Msg::FromConnector(n));
         let connector = Connector::bridge(callback);
-        Self {
+        let mut this = Self {
             scene: Scene::Splash,
             link,
             connector,
-        }
+            profiles: BTreeMap::new(),
+        };
+        this.subscribe();
+        this
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -44,13 +57,46 @@ impl Component for App {
                         self.scene = Scene::Splash;
                     }
                     LoginStatus::NeedCredentials { fail } => {
-                        self.scene = Scene::Login { fail };
+                        let second_factor = false;
+                        self.scene = Scene::Login {
+                            fail,
+                            second_factor,
+                        };
+                    }
+                    LoginStatus::NeedSecondFactor { fail } => {
+                        let second_factor = true;
+                        self.scene = Scene::Login {
+                            fail,
+                            second_factor,
+                        };
                     }
                     LoginStatus::LoggedIn => {
                         self.scene = Scene::Main;
                     }
                 },
-                Notification::ChannelStatus(_) => {}
+                Notification::ProfileStatus(status) => match status {
+                    ProfileStatus::Profile { username, profile }
+                    | ProfileStatus::ProfileChanged { username, profile } => {
+                        self.profiles.insert(username, profile);
+                    }
+                },
+                Notification::MemberStatus(status) => match status {
+                    MemberStatus::MembersList { members, .. } => {
+                        for username in members {
+                            self.fetch_profile(username);
+                        }
+                    }
+                    MemberStatus::MemberAdded { username, .. } => {
+                        self.fetch_profile(username);
+                    }
+                    MemberStatus::MemberRemoved { .. } => {}
+                },
+                Notification::ChannelStatus(_)
+                | Notification::MessageStatus(_)
+                | Notification::ThreadStatus(_)
+                | Notification::MentionStatus(_)
+                | Notification::SecurityStatus(_)
+                | Notification::TokenStatus(_) => {}
             },
         }
         true
@@ -63,16 +109,36 @@ impl Component for App {
                     <Splash />
                 }
             }
-            Scene::Login { ref fail } => {
+            Scene::Login {
+                ref fail,
+                second_factor,
+            } => {
                 html! {
-                    <Login fail=fail />
+                    <Login fail=fail second_factor=second_factor />
                 }
             }
             Scene::Main => {
                 html! {
-                    <Chat />
+                    <Chat profiles=self.profiles.clone() />
                 }
             }
         }
     }
 }
+
+impl App {
+    fn subscribe(&mut self) {
+        let info_set = vec![Info::MemberInfo, Info::ProfileInfo]
+            .into_iter()
+            .collect();
+        self.connector.send(Action::Subscribe(info_set));
+    }
+
+    /// Requests a profile that is not known yet. Changes are delivered
+    /// by the server for users of shared channels.
+    fn fetch_profile(&mut self, username: String) {
+        if !self.profiles.contains_key(&username) {
+            self.connector.send(Action::FetchProfile(username));
+        }
+    }
+}
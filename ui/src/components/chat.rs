@@ -1,4 +1,6 @@
 use crate::components::CreateChannel;
+use protocol::ProfileInfo;
+use std::collections::BTreeMap;
 use yew::prelude::*;
 
 #[derive(Debug, Clone)]
@@ -8,8 +10,14 @@ enum Scene {
     AddChannel,
 }
 
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub profiles: BTreeMap<String, ProfileInfo>,
+}
+
 pub struct Chat {
     link: ComponentLink<Self>,
+    profiles: BTreeMap<String, ProfileInfo>,
     scene: Scene,
     // TODO: Consider to use a size-limited stack here
     previous_scene: Option<Scene>,
@@ -23,11 +31,12 @@ pub enum Msg {
 
 impl Component for Chat {
     type Message = Msg;
-    type Properties = ();
+    type Properties = Props;
 
-    fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
         Self {
             link,
+            profiles: props.profiles,
             scene: Scene::Dashboard,
             previous_scene: Some(Scene::Dashboard),
         }
@@ -60,6 +69,7 @@ impl Component for Chat {
             <div class="app">
                 <div class="user">
                     <p>{ "User" }</p>
+                    { for self.profiles.iter().map(|(username, profile)| view_profile(username, profile)) }
                 </div>
                 <div class="channels">
                     <p>{ "Channels" }</p>
@@ -77,6 +87,28 @@ impl Component for Chat {
             </div>
         }
     }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.profiles = props.profiles;
+        true
+    }
+}
+
+/// Shows a display name with an avatar or the username if it's not set.
+fn view_profile(username: &str, profile: &ProfileInfo) -> Html {
+    let name = profile.display_name.as_deref().unwrap_or(username);
+    let avatar = match &profile.avatar {
+        Some(url) => html! {
+            <img class="avatar" src=url alt=username />
+        },
+        None => html! {},
+    };
+    html! {
+        <div class="profile" title=username>
+            { avatar }
+            <span>{ name }</span>
+        </div>
+    }
 }
 
 impl Chat {
@@ -1,25 +1,41 @@
 use crate::agents::connector::{Action, Connector, Notification};
 use protocol::Credentials;
+use url::Url;
 use yew::prelude::*;
 
+/// Query parameter of a sign up link.
+const INVITE_PARAM: &str = "invite";
+
 pub struct Login {
     link: ComponentLink<Self>,
     connector: Box<dyn Bridge<Connector>>,
     username: String,
     password: String,
+    /// A code of the second factor.
+    code: String,
+    /// Registration screen if set.
+    invite: Option<String>,
     fail: Option<String>,
+    second_factor: bool,
 }
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Props {
     #[prop_or_default]
     pub fail: Option<String>,
+    /// Asks for a code instead of credentials.
+    #[prop_or_default]
+    pub second_factor: bool,
 }
 
 pub enum Msg {
     UpdateUsername(String),
     UpdatePassword(String),
+    UpdateInvite(String),
+    UpdateCode(String),
+    ToggleRegister,
     SendCredentials,
+    SendCode,
     FromConnector(Notification),
 }
 
@@ -35,7 +51,10 @@ impl Component for Login {
             connector,
             username: String::new(),
             password: String::new(),
+            code: String::new(),
+            invite: invite_from_location(),
             fail: props.fail,
+            second_factor: props.second_factor,
         }
     }
 
@@ -47,14 +66,36 @@ impl Component for Login {
             Msg::UpdatePassword(password) => {
                 self.password = password;
             }
+            Msg::UpdateInvite(invite) => {
+                self.invite = Some(invite);
+            }
+            Msg::UpdateCode(code) => {
+                self.code = code;
+            }
+            Msg::ToggleRegister => {
+                self.invite = match self.invite {
+                    Some(_) => None,
+                    None => Some(String::new()),
+                };
+            }
             Msg::SendCredentials => {
                 let creds = Credentials {
                     username: self.username.clone(),
                     password: self.password.clone(),
                 };
-                let action = Action::SetCredentials(creds);
+                let action = match self.invite.clone() {
+                    Some(token) => Action::Register {
+                        token,
+                        credentials: creds,
+                    },
+                    None => Action::SetCredentials(creds),
+                };
                 self.connector.send(action);
             }
+            Msg::SendCode => {
+                let code = std::mem::take(&mut self.code);
+                self.connector.send(Action::SecondFactor(code));
+            }
             Msg::FromConnector(_) => {}
         }
         true
@@ -62,10 +103,19 @@ impl Component for Login {
 
     fn change(&mut self, props: Self::Properties) -> ShouldRender {
         self.fail = props.fail;
+        self.second_factor = props.second_factor;
         true
     }
 
     fn view(&self) -> Html {
+        if self.second_factor {
+            return self.view_second_factor();
+        }
+        let (submit, toggle) = if self.invite.is_some() {
+            ("Register", "I have an account")
+        } else {
+            ("Login", "I have an invite")
+        };
         html! {
             <div>
                 <div>
@@ -81,11 +131,57 @@ impl Component for Login {
                            placeholder="Password"
                            oninput=self.link.callback(|e: InputData| Msg::UpdatePassword(e.value)) />
                 </div>
+                { self.view_invite() }
                 <div>
                     <p>{ self.fail.clone().unwrap_or_else(String::default) }</p>
                 </div>
-                <p onclick=self.link.callback(|_| Msg::SendCredentials)>{ "Login" }</p>
+                <p onclick=self.link.callback(|_| Msg::SendCredentials)>{ submit }</p>
+                <p onclick=self.link.callback(|_| Msg::ToggleRegister)>{ toggle }</p>
             </div>
         }
     }
 }
+
+impl Login {
+    fn view_second_factor(&self) -> Html {
+        html! {
+            <div>
+                <div>
+                    <label>{ "Code" }</label>
+                    <input value=self.code
+                           placeholder="Authenticator or recovery code"
+                           autocomplete="one-time-code"
+                           oninput=self.link.callback(|e: InputData| Msg::UpdateCode(e.value)) />
+                </div>
+                <div>
+                    <p>{ self.fail.clone().unwrap_or_else(String::default) }</p>
+                </div>
+                <p onclick=self.link.callback(|_| Msg::SendCode)>{ "Verify" }</p>
+            </div>
+        }
+    }
+
+    fn view_invite(&self) -> Html {
+        if let Some(invite) = self.invite.as_ref() {
+            html! {
+                <div>
+                    <label>{ "Invite" }</label>
+                    <input value=invite
+                           placeholder="Invite token"
+                           oninput=self.link.callback(|e: InputData| Msg::UpdateInvite(e.value)) />
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+}
+
+/// Takes an invite token from a sign up link.
+fn invite_from_location() -> Option<String> {
+    let location = web_sys::window()?.location().to_string().as_string()?;
+    let url: Url = location.parse().ok()?;
+    url.query_pairs()
+        .find(|(key, _)| key == INVITE_PARAM)
+        .map(|(_, value)| value.into_owned())
+}